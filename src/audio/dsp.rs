@@ -0,0 +1,746 @@
+use crate::{
+    audio::Frame,
+    chart::FxEffect,
+};
+use std::f64::consts::PI;
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how long the gains take to settle, so they don't click when they jump
+const SMOOTHING_MS: f64 = 2.;
+
+// the flanger sweeps its delay between these every period
+const FLANGER_MIN_MS: f64 = 1.;
+const FLANGER_MAX_MS: f64 = 4.;
+const FLANGER_PERIOD_SECONDS: f64 = 2.;
+const FLANGER_FEEDBACK: f32 = 0.5;
+
+// the wobble sweeps its cutoff between these every division
+const WOBBLE_MIN_HZ: f64 = 400.;
+const WOBBLE_MAX_HZ: f64 = 16_000.;
+
+// the phaser sweeps its stages between these every period
+const PHASER_STAGES: usize = 4;
+const PHASER_MIN_HZ: f64 = 300.;
+const PHASER_MAX_HZ: f64 = 3_000.;
+const PHASER_PERIOD_SECONDS: f64 = 2.;
+const PHASER_FEEDBACK: f32 = 0.5;
+
+// the slowest tape stop takes this long, at a speed of 1
+const TAPE_STOP_MAX_SECONDS: f64 = 3.;
+
+// how low the side chain ducks the music at the start of every beat, and for
+// how much of the beat it comes back up
+const SIDE_CHAIN_DEPTH: f32 = 0.2;
+const SIDE_CHAIN_RELEASE: f64 = 0.6;
+
+// the length of the grains the pitch is shifted by
+const PITCH_SHIFT_WINDOW_MS: f64 = 50.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Processes the audio a frame at a time.
+///
+/// The position is the number of frames since the processing started, which
+/// the effects synced to the beat are timed by.
+pub trait Dsp: Send {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame;
+}
+
+/// A second-order filter, from the Audio EQ Cookbook
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    sample_rate: f64,
+
+    // the coefficients, normalized by a0
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+
+    // the last two inputs and outputs of each channel
+    x: [[f64; 2]; 2],
+    y: [[f64; 2]; 2],
+}
+
+/// Delays the frames written to it by up to its length
+#[derive(Debug, Clone)]
+pub struct DelayLine {
+    frames: Vec<Frame>,
+    next:   usize,
+}
+
+/// Eases a gain towards where it's wanted
+#[derive(Debug, Clone, Copy)]
+pub struct Smoother {
+    value: f32,
+    rate:  f32,
+}
+
+/// Repeats the first division for as long as it's on
+pub struct Retrigger {
+    slice:  Vec<Frame>,
+    played: usize,
+}
+
+/// Cuts the second half of every division
+pub struct Gate {
+    length: usize,
+    gain:   Smoother,
+}
+
+pub struct Flanger {
+    delay:       DelayLine,
+    sample_rate: f64,
+}
+
+/// Holds every frame for a number of frames
+pub struct BitCrusher {
+    reduction: usize,
+    held:      Frame,
+}
+
+/// A low-pass filter with a cutoff swept every division
+pub struct Wobble {
+    filter: Biquad,
+    length: usize,
+}
+
+pub struct Phaser {
+    sample_rate: f64,
+
+    // the last input and output of every stage, of each channel
+    stages:   [[(f32, f32); 2]; PHASER_STAGES],
+    feedback: Frame,
+}
+
+/// Slows the music down until it stops
+pub struct TapeStop {
+    recorded: Vec<Frame>,
+    read:     f64,
+    length:   f64,
+}
+
+pub struct Echo {
+    delay:    DelayLine,
+    length:   usize,
+    feedback: f32,
+}
+
+/// Ducks the music at the start of every beat
+pub struct SideChain {
+    beat: f64,
+    gain: Smoother,
+}
+
+/// Shifts the pitch with two grains read from a delay line at another speed,
+/// each fading in as the other one fades out
+pub struct PitchShift {
+    delay:  DelayLine,
+    window: f64,
+    phase:  f64,
+    step:   f64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Makes the processor of an FX effect. The beat is its length in frames.
+pub fn fx_effect(
+    effect: FxEffect,
+    sample_rate: u32,
+    beat: f64,
+) -> Box<dyn Dsp>
+{
+    use FxEffect::*;
+
+    let sample_rate = sample_rate as f64;
+    let division = |division: u32| {
+        ((beat * 4. / division as f64).round() as usize).max(2)
+    };
+
+    match effect {
+        Retrigger(d) => Box::new(self::Retrigger::new(division(d))),
+        Gate(d) => Box::new(self::Gate::new(division(d), sample_rate)),
+        Flanger => Box::new(self::Flanger::new(sample_rate)),
+        BitCrusher(reduction) => {
+            Box::new(self::BitCrusher::new(reduction as usize))
+        },
+        Wobble(d) => Box::new(self::Wobble::new(division(d), sample_rate)),
+        Phaser => Box::new(self::Phaser::new(sample_rate)),
+        TapeStop(speed) => Box::new(self::TapeStop::new(speed, sample_rate)),
+        Echo(d, feedback) => {
+            Box::new(self::Echo::new(division(d), feedback as f32 / 100.))
+        },
+        SideChain => Box::new(self::SideChain::new(beat, sample_rate)),
+        PitchShift(semitones) => {
+            Box::new(self::PitchShift::new(semitones, sample_rate))
+        },
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Biquad {
+    /// Makes a filter that lets everything through, until it's set otherwise
+    pub fn new(sample_rate: f64) -> Biquad {
+        Biquad {
+            sample_rate,
+            b0: 1.,
+            b1: 0.,
+            b2: 0.,
+            a1: 0.,
+            a2: 0.,
+            x: [[0.; 2]; 2],
+            y: [[0.; 2]; 2],
+        }
+    }
+
+    pub fn set_low_pass(
+        &mut self,
+        cutoff: f64,
+        q: f64,
+    )
+    {
+        let (cos, alpha) = self.omega(cutoff, q);
+
+        self.set(
+            [(1. - cos) / 2., 1. - cos, (1. - cos) / 2.],
+            [1. + alpha, -2. * cos, 1. - alpha],
+        );
+    }
+
+    pub fn set_high_pass(
+        &mut self,
+        cutoff: f64,
+        q: f64,
+    )
+    {
+        let (cos, alpha) = self.omega(cutoff, q);
+
+        self.set(
+            [(1. + cos) / 2., -(1. + cos), (1. + cos) / 2.],
+            [1. + alpha, -2. * cos, 1. - alpha],
+        );
+    }
+
+    /// Boosts, or cuts if the gain is negative, the frequencies around the
+    /// center
+    pub fn set_peak(
+        &mut self,
+        center: f64,
+        q: f64,
+        gain_db: f64,
+    )
+    {
+        let (cos, alpha) = self.omega(center, q);
+        let a = 10f64.powf(gain_db / 40.);
+
+        self.set(
+            [1. + alpha * a, -2. * cos, 1. - alpha * a],
+            [1. + alpha / a, -2. * cos, 1. - alpha / a],
+        );
+    }
+
+    pub fn process(
+        &mut self,
+        frame: Frame,
+    ) -> Frame
+    {
+        let mut out = [0.; 2];
+
+        for channel in 0 .. 2 {
+            let x = &mut self.x[channel];
+            let y = &mut self.y[channel];
+            let input = frame[channel] as f64;
+
+            let output = self.b0 * input + self.b1 * x[0] + self.b2 * x[1] -
+                self.a1 * y[0] -
+                self.a2 * y[1];
+
+            *x = [input, x[0]];
+            *y = [output, y[0]];
+            out[channel] = output as f32;
+        }
+
+        out
+    }
+
+    // the cutoff is kept under the nyquist frequency
+    fn omega(
+        &self,
+        freq: f64,
+        q: f64,
+    ) -> (f64, f64)
+    {
+        let freq = freq.max(10.).min(self.sample_rate * 0.45);
+        let omega = 2. * PI * freq / self.sample_rate;
+
+        (omega.cos(), omega.sin() / (2. * q))
+    }
+
+    fn set(
+        &mut self,
+        b: [f64; 3],
+        a: [f64; 3],
+    )
+    {
+        self.b0 = b[0] / a[0];
+        self.b1 = b[1] / a[0];
+        self.b2 = b[2] / a[0];
+        self.a1 = a[1] / a[0];
+        self.a2 = a[2] / a[0];
+    }
+}
+
+impl DelayLine {
+    pub fn new(length: usize) -> DelayLine {
+        DelayLine {
+            frames: vec![[0.; 2]; length.max(2) + 2],
+            next:   0,
+        }
+    }
+
+    pub fn write(
+        &mut self,
+        frame: Frame,
+    )
+    {
+        self.frames[self.next] = frame;
+        self.next = (self.next + 1) % self.frames.len();
+    }
+
+    /// Returns the frame written the given number of frames ago. The delay is
+    /// at least one frame.
+    pub fn read(
+        &self,
+        delay: f64,
+    ) -> Frame
+    {
+        let delay = delay.max(1.).min((self.frames.len() - 2) as f64);
+        let whole = delay.floor() as usize;
+        let fract = (delay - delay.floor()) as f32;
+
+        let a = self.at(whole);
+        let b = self.at(whole + 1);
+
+        [lerp(a[0], b[0], fract), lerp(a[1], b[1], fract)]
+    }
+
+    fn at(
+        &self,
+        delay: usize,
+    ) -> Frame
+    {
+        let len = self.frames.len();
+        self.frames[(self.next + len - delay) % len]
+    }
+}
+
+impl Smoother {
+    pub fn new(
+        value: f32,
+        sample_rate: f64,
+    ) -> Smoother
+    {
+        Smoother::with_time(value, SMOOTHING_MS, sample_rate)
+    }
+
+    /// Makes a smoother that takes about the given time to settle
+    pub fn with_time(
+        value: f32,
+        time_ms: f64,
+        sample_rate: f64,
+    ) -> Smoother
+    {
+        let rate = 1. - (-1000. / (time_ms * sample_rate)).exp();
+
+        Smoother {
+            value,
+            rate: rate as f32,
+        }
+    }
+
+    pub fn next(
+        &mut self,
+        target: f32,
+    ) -> f32
+    {
+        self.value += (target - self.value) * self.rate;
+        self.value
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+}
+
+impl Retrigger {
+    fn new(length: usize) -> Retrigger {
+        Retrigger {
+            slice:  Vec::with_capacity(length),
+            played: 0,
+        }
+    }
+}
+
+impl Dsp for Retrigger {
+    fn process(
+        &mut self,
+        frame: Frame,
+        _: i64,
+    ) -> Frame
+    {
+        let length = self.slice.capacity();
+        let idx = self.played % length;
+        self.played += 1;
+
+        // the slice is recorded the first time around
+        if self.slice.len() < length {
+            self.slice.push(frame);
+            frame
+        }
+        else {
+            self.slice[idx]
+        }
+    }
+}
+
+impl Gate {
+    fn new(
+        length: usize,
+        sample_rate: f64,
+    ) -> Gate
+    {
+        Gate {
+            length,
+            gain: Smoother::new(1., sample_rate),
+        }
+    }
+}
+
+impl Dsp for Gate {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame
+    {
+        let is_open = position.max(0) as usize % self.length < self.length / 2;
+        let gain = self.gain.next(if is_open { 1. } else { 0. });
+
+        [frame[0] * gain, frame[1] * gain]
+    }
+}
+
+impl Flanger {
+    fn new(sample_rate: f64) -> Flanger {
+        let length = (FLANGER_MAX_MS / 1000. * sample_rate).ceil() as usize;
+
+        Flanger {
+            delay: DelayLine::new(length + 1),
+            sample_rate,
+        }
+    }
+}
+
+impl Dsp for Flanger {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame
+    {
+        let seconds = position as f64 / self.sample_rate;
+        let sweep = sweep(seconds / FLANGER_PERIOD_SECONDS);
+        let delay_ms =
+            FLANGER_MIN_MS + (FLANGER_MAX_MS - FLANGER_MIN_MS) * sweep;
+
+        let delayed = self.delay.read(delay_ms / 1000. * self.sample_rate);
+        self.delay.write([
+            frame[0] + delayed[0] * FLANGER_FEEDBACK,
+            frame[1] + delayed[1] * FLANGER_FEEDBACK,
+        ]);
+
+        [(frame[0] + delayed[0]) * 0.5, (frame[1] + delayed[1]) * 0.5]
+    }
+}
+
+impl BitCrusher {
+    fn new(reduction: usize) -> BitCrusher {
+        BitCrusher {
+            reduction: reduction.max(1),
+            held:      [0.; 2],
+        }
+    }
+}
+
+impl Dsp for BitCrusher {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame
+    {
+        if position.max(0) as usize % self.reduction == 0 {
+            self.held = frame;
+        }
+
+        self.held
+    }
+}
+
+impl Wobble {
+    fn new(
+        length: usize,
+        sample_rate: f64,
+    ) -> Wobble
+    {
+        Wobble {
+            filter: Biquad::new(sample_rate),
+            length,
+        }
+    }
+}
+
+impl Dsp for Wobble {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame
+    {
+        // the cutoff starts high and dips down in the middle of the division
+        let phase = position.max(0) as f64 / self.length as f64;
+        let cutoff = exp_lerp(WOBBLE_MAX_HZ, WOBBLE_MIN_HZ, sweep(phase));
+
+        self.filter.set_low_pass(cutoff, 2.);
+        self.filter.process(frame)
+    }
+}
+
+impl Phaser {
+    fn new(sample_rate: f64) -> Phaser {
+        Phaser {
+            sample_rate,
+            stages: [[(0., 0.); 2]; PHASER_STAGES],
+            feedback: [0.; 2],
+        }
+    }
+}
+
+impl Dsp for Phaser {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame
+    {
+        let seconds = position as f64 / self.sample_rate;
+        let sweep = sweep(seconds / PHASER_PERIOD_SECONDS);
+        let freq = exp_lerp(PHASER_MIN_HZ, PHASER_MAX_HZ, sweep);
+
+        let tan = (PI * freq / self.sample_rate).tan();
+        let coefficient = ((tan - 1.) / (tan + 1.)) as f32;
+
+        let mut out = [0.; 2];
+        for channel in 0 .. 2 {
+            let mut signal =
+                frame[channel] + self.feedback[channel] * PHASER_FEEDBACK;
+
+            // first-order all-passes, one after the other
+            for stage in self.stages.iter_mut() {
+                let (x, y) = &mut stage[channel];
+                let output = coefficient * signal + *x - coefficient * *y;
+
+                *x = signal;
+                *y = output;
+                signal = output;
+            }
+
+            self.feedback[channel] = signal;
+            out[channel] = (frame[channel] + signal) * 0.5;
+        }
+
+        out
+    }
+}
+
+impl TapeStop {
+    fn new(
+        speed: u32,
+        sample_rate: f64,
+    ) -> TapeStop
+    {
+        let speed = speed.max(1).min(100) as f64;
+        let seconds = TAPE_STOP_MAX_SECONDS * (101. - speed) / 100.;
+        let length = seconds * sample_rate;
+
+        TapeStop {
+            recorded: Vec::with_capacity(length as usize + 1),
+            read: 0.,
+            length,
+        }
+    }
+}
+
+impl Dsp for TapeStop {
+    fn process(
+        &mut self,
+        frame: Frame,
+        _: i64,
+    ) -> Frame
+    {
+        // the music is only read at the speed the tape is still going at, so
+        // it falls behind what's being recorded
+        if self.recorded.len() < self.recorded.capacity() {
+            self.recorded.push(frame);
+        }
+
+        let speed = (1. - self.recorded.len() as f64 / self.length).max(0.);
+        let idx = self.read.floor() as usize;
+        let fract = (self.read - self.read.floor()) as f32;
+
+        let a = self.recorded.get(idx).cloned().unwrap_or([0.; 2]);
+        let b = self.recorded.get(idx + 1).cloned().unwrap_or(a);
+        self.read += speed;
+
+        let gain = speed as f32;
+        [lerp(a[0], b[0], fract) * gain, lerp(a[1], b[1], fract) * gain]
+    }
+}
+
+impl Echo {
+    fn new(
+        length: usize,
+        feedback: f32,
+    ) -> Echo
+    {
+        Echo {
+            delay: DelayLine::new(length),
+            length,
+            feedback,
+        }
+    }
+}
+
+impl Dsp for Echo {
+    fn process(
+        &mut self,
+        frame: Frame,
+        _: i64,
+    ) -> Frame
+    {
+        let delayed = self.delay.read(self.length as f64);
+        self.delay.write([
+            frame[0] + delayed[0] * self.feedback,
+            frame[1] + delayed[1] * self.feedback,
+        ]);
+
+        [frame[0] + delayed[0], frame[1] + delayed[1]]
+    }
+}
+
+impl SideChain {
+    fn new(
+        beat: f64,
+        sample_rate: f64,
+    ) -> SideChain
+    {
+        SideChain {
+            beat,
+            gain: Smoother::new(1., sample_rate),
+        }
+    }
+}
+
+impl Dsp for SideChain {
+    fn process(
+        &mut self,
+        frame: Frame,
+        position: i64,
+    ) -> Frame
+    {
+        let phase = (position.max(0) as f64 / self.beat).fract();
+        let release = (phase / SIDE_CHAIN_RELEASE).min(1.) as f32;
+        let target = SIDE_CHAIN_DEPTH + (1. - SIDE_CHAIN_DEPTH) * release;
+
+        let gain = self.gain.next(target);
+        [frame[0] * gain, frame[1] * gain]
+    }
+}
+
+impl PitchShift {
+    fn new(
+        semitones: i32,
+        sample_rate: f64,
+    ) -> PitchShift
+    {
+        let window = PITCH_SHIFT_WINDOW_MS / 1000. * sample_rate;
+        let ratio = 2f64.powf(semitones as f64 / 12.);
+
+        PitchShift {
+            delay: DelayLine::new(window.ceil() as usize + 2),
+            window,
+            phase: 0.,
+            step: (1. - ratio) / window,
+        }
+    }
+}
+
+impl Dsp for PitchShift {
+    fn process(
+        &mut self,
+        frame: Frame,
+        _: i64,
+    ) -> Frame
+    {
+        self.delay.write(frame);
+        self.phase += self.step;
+        self.phase -= self.phase.floor();
+
+        let phases = [self.phase, (self.phase + 0.5).fract()];
+        let mut out = [0.; 2];
+
+        for phase in phases.iter() {
+            let grain = self.delay.read(1. + phase * self.window);
+
+            // the grains fade in and out in triangles, which add up to one
+            let gain = (1. - (2. * phase - 1.).abs()) as f32;
+            out[0] += grain[0] * gain;
+            out[1] += grain[1] * gain;
+        }
+
+        out
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn lerp(
+    a: f32,
+    b: f32,
+    t: f32,
+) -> f32
+{
+    a + (b - a) * t
+}
+
+/// Moves between two frequencies evenly in pitch
+pub fn exp_lerp(
+    from: f64,
+    to: f64,
+    t: f64,
+) -> f64
+{
+    from * (to / from).powf(t)
+}
+
+/// Goes from 0 up to 1 and back down over every whole number
+fn sweep(phase: f64) -> f64 {
+    (1. - (2. * PI * phase).cos()) / 2.
+}
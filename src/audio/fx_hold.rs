@@ -0,0 +1,312 @@
+use crate::{
+    audio::{
+        dsp::{
+            self,
+            Dsp,
+        },
+        Effect,
+        Frame,
+    },
+    chart::{
+        Chart,
+        FxEffect,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use std::sync::{
+    atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+    Arc,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how long the effects take to fade in and out
+const FADE_MS: f64 = 5.;
+
+// stands for no hold being held on a lane
+const NOT_HELD: usize = usize::MAX;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Applies the effects of the FX holds to the music while they're held.
+///
+/// The game tells which holds are being held through the switch. The effects
+/// fade in and out instead of cutting in, so they don't click.
+pub struct FxHoldEffects {
+    sample_rate: u32,
+    lanes:       [FxLaneEffects; 2],
+    held:        Arc<[AtomicUsize; 2]>,
+
+    // how much the mix of an effect changes every frame as it fades
+    fade_step: f32,
+}
+
+/// Tells the effects which FX holds are being held
+#[derive(Clone)]
+pub struct FxHoldSwitch {
+    held: Arc<[AtomicUsize; 2]>,
+}
+
+struct FxLaneEffects {
+    holds: Vec<FxHold>,
+
+    // the hold whose effect is playing, along with the effect
+    current: Option<(usize, Box<dyn Dsp>)>,
+
+    // how much of the effect is heard, from 0 to 1
+    mix: f32,
+}
+
+struct FxHold {
+    start:  SongTime,
+    effect: Option<FxEffect>,
+
+    // the length of a beat at the start of the hold, in frames
+    beat: f64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl FxHoldEffects {
+    pub fn new(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+    ) -> (FxHoldEffects, FxHoldSwitch)
+    {
+        let sample_rate = tempo_map.freq();
+
+        // the holds are in the same order as they are in the judgment
+        let lane = |lane: usize| {
+            let holds = chart.fx[lane]
+                .iter()
+                .filter(|note| !note.is_chip())
+                .map(|note| {
+                    let effect = chart.fx_effects[lane]
+                        .iter()
+                        .find(|(tick, _)| *tick == note.tick)
+                        .map(|(_, effect)| *effect);
+                    let bpm = tempo_map.bpm_at(note.tick as f64);
+
+                    FxHold {
+                        start: tempo_map.tick_to_song_time(note.tick),
+                        effect,
+                        beat: 60. / bpm * sample_rate as f64,
+                    }
+                })
+                .collect();
+
+            FxLaneEffects {
+                holds,
+                current: None,
+                mix: 0.,
+            }
+        };
+
+        let held = Arc::new([
+            AtomicUsize::new(NOT_HELD),
+            AtomicUsize::new(NOT_HELD),
+        ]);
+
+        let effects = FxHoldEffects {
+            sample_rate,
+            lanes: [lane(0), lane(1)],
+            held: held.clone(),
+            fade_step: (1000. / (FADE_MS * sample_rate as f64)) as f32,
+        };
+
+        (effects, FxHoldSwitch {
+            held,
+        })
+    }
+}
+
+impl Effect for FxHoldEffects {
+    fn apply(
+        &mut self,
+        frame: Frame,
+        time: SongTime,
+    ) -> Frame
+    {
+        let mut frame = frame;
+
+        for (lane, effects) in self.lanes.iter_mut().enumerate() {
+            let held = match self.held[lane].load(Ordering::Relaxed) {
+                NOT_HELD => None,
+                idx => Some(idx),
+            };
+
+            frame = effects.apply(
+                frame,
+                time,
+                held,
+                self.fade_step,
+                self.sample_rate,
+            );
+        }
+
+        frame
+    }
+}
+
+impl FxHoldSwitch {
+    /// Sets the hold being held on the lane, by its index among the holds of
+    /// the lane
+    pub fn set_held(
+        &self,
+        lane: usize,
+        hold: Option<usize>,
+    )
+    {
+        self.held[lane].store(hold.unwrap_or(NOT_HELD), Ordering::Relaxed);
+    }
+}
+
+impl FxLaneEffects {
+    fn apply(
+        &mut self,
+        frame: Frame,
+        time: SongTime,
+        held: Option<usize>,
+        fade_step: f32,
+        sample_rate: u32,
+    ) -> Frame
+    {
+        let current = self.current.as_ref().map(|(idx, _)| *idx);
+
+        // another hold only takes over once the last one has faded out
+        if current != held && self.mix <= 0. {
+            let hold = held.and_then(|idx| Some((idx, self.holds.get(idx)?)));
+
+            self.current = hold.and_then(|(idx, hold)| {
+                let effect =
+                    dsp::fx_effect(hold.effect?, sample_rate, hold.beat);
+                Some((idx, effect))
+            });
+        }
+
+        let is_on = held.is_some() &&
+            self.current.as_ref().map(|(idx, _)| *idx) == held;
+        self.mix = if is_on {
+            (self.mix + fade_step).min(1.)
+        }
+        else {
+            (self.mix - fade_step).max(0.)
+        };
+
+        let (idx, effect) = match self.current.as_mut() {
+            Some((idx, effect)) => (*idx, effect),
+            None => return frame,
+        };
+
+        // the effects synced to the beat are timed from the start of the hold
+        let position = (time - self.holds[idx].start).0;
+        let wet = effect.process(frame, position);
+        let mix = self.mix;
+
+        [
+            frame[0] + (wet[0] - frame[0]) * mix,
+            frame[1] + (wet[1] - frame[1]) * mix,
+        ]
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::ksh;
+
+    // a hold with a retrigger of an eighth, which is 250 frames at 120 BPM
+    const CHART: &str = "t=120\n--\nfx-l=Retrigger;8\n0000|10|--\n0000|10|--\n\
+                         0000|10|--\n0000|10|--\n--\n0000|00|--\n--\n";
+
+    // an effect that silences everything
+    struct Mute;
+
+    impl Dsp for Mute {
+        fn process(
+            &mut self,
+            _: Frame,
+            _: i64,
+        ) -> Frame
+        {
+            [0.; 2]
+        }
+    }
+
+    #[test]
+    fn plays_the_effect_only_while_held() {
+        let chart = ksh::parse(CHART).unwrap();
+        let tempo_map = TempoMap::from_chart(&chart, 1000);
+        let (mut effects, switch) = FxHoldEffects::new(&chart, &tempo_map);
+
+        // every frame is its own time
+        let mut apply =
+            |time: i64| effects.apply([time as f32; 2], SongTime(time));
+
+        for time in 0 .. 100 {
+            assert_eq!(apply(time), [time as f32; 2]);
+        }
+
+        // there are no holds to be held on the other lane
+        switch.set_held(1, Some(0));
+        for time in 100 .. 200 {
+            assert_eq!(apply(time), [time as f32; 2]);
+        }
+
+        // the slice is heard as it is while it's recorded, and then repeated
+        switch.set_held(1, None);
+        switch.set_held(0, Some(0));
+        for time in 200 .. 450 {
+            assert_eq!(apply(time), [time as f32; 2]);
+        }
+
+        for time in 450 .. 950 {
+            let repeated = 200 + (time - 200) % 250;
+            assert_eq!(apply(time), [repeated as f32; 2], "at {}", time);
+        }
+
+        // and it fades out once released
+        switch.set_held(0, None);
+        assert_ne!(apply(950), [950.; 2]);
+
+        for time in 951 .. 960 {
+            apply(time);
+        }
+
+        for time in 960 .. 1000 {
+            assert_eq!(apply(time), [time as f32; 2]);
+        }
+    }
+
+    #[test]
+    fn fades_a_step_a_frame() {
+        let mut lane = FxLaneEffects {
+            holds:   vec![FxHold {
+                start:  SongTime(0),
+                effect: None,
+                beat:   500.,
+            }],
+            current: Some((0, Box::new(Mute) as Box<dyn Dsp>)),
+            mix:     0.,
+        };
+        let mut apply =
+            |held| lane.apply([1.; 2], SongTime(0), held, 0.25, 1000);
+
+        // the effect is muting, so the fade is all that's heard
+        for expected in [0.75, 0.5, 0.25, 0., 0.].iter() {
+            assert_eq!(apply(Some(0)), [*expected; 2]);
+        }
+
+        for expected in [0.25, 0.5, 0.75, 1., 1.].iter() {
+            assert_eq!(apply(None), [*expected; 2]);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use crate::{
+    audio::{
+        dsp::{
+            exp_lerp,
+            Biquad,
+            Smoother,
+        },
+        Effect,
+        Frame,
+    },
+    chart::{
+        Chart,
+        LaserFilter,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use std::{
+    cmp,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the cursors are set as often as the game updates, so they glide to where
+// they're set instead of stepping there
+const CURSOR_GLIDE_MS: f64 = 10.;
+
+// how long the filter takes to fade in and out as the lasers come and go
+const FADE_MS: f64 = 5.;
+
+// the gain of the filter when the chart doesn't set one, in percent
+const DEFAULT_GAIN: u8 = 50;
+
+// the peak moves between these as the laser moves away from where it rests.
+// it only boosts fully once the laser is a bit away from there.
+const PEAK_MIN_HZ: f64 = 80.;
+const PEAK_MAX_HZ: f64 = 8_000.;
+const PEAK_MAX_DB: f64 = 24.;
+const PEAK_RAMP: f64 = 0.1;
+const PEAK_Q: f64 = 1.4;
+
+const LOW_PASS_MIN_HZ: f64 = 300.;
+const LOW_PASS_MAX_HZ: f64 = 16_000.;
+const HIGH_PASS_MIN_HZ: f64 = 20.;
+const HIGH_PASS_MAX_HZ: f64 = 6_000.;
+
+// the resonance of the low and high passes goes up with the gain
+const PASS_MIN_Q: f64 = 0.7;
+const PASS_MAX_Q: f64 = 3.7;
+
+// how many frames each frame is held for at most, at full gain
+const BIT_CRUSHER_MAX_REDUCTION: f64 = 60.;
+
+// stands for no cursor being shown on a side
+const NO_CURSOR: u64 = u64::max_value();
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Filters the music while the lasers are followed.
+///
+/// The further a laser is from where it rests, the more the music is filtered.
+/// The game tells where the cursors of the lasers are through the cursors,
+/// which are read again for every frame.
+pub struct LaserFilterEffect {
+    sample_rate: f64,
+
+    // the filters and gains set by the chart, in the order they're set
+    filters: Vec<(SongTime, LaserFilter)>,
+    gains:   Vec<(SongTime, f32)>,
+
+    cursors: Arc<[AtomicU64; 2]>,
+    current: LaserFilter,
+
+    // how far the lasers are from where they rest, from 0 to 1
+    amount: Smoother,
+
+    // how much of the filter is heard, from 0 to 1
+    mix:       f32,
+    fade_step: f32,
+
+    biquad: Biquad,
+
+    // the frame held by the bit crusher, and for how much longer
+    crushed:    Frame,
+    crush_left: f64,
+}
+
+/// Tells the filter where the cursors of the lasers are
+#[derive(Clone)]
+pub struct LaserCursors {
+    cursors: Arc<[AtomicU64; 2]>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl LaserFilterEffect {
+    pub fn new(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+    ) -> (LaserFilterEffect, LaserCursors)
+    {
+        let sample_rate = tempo_map.freq() as f64;
+
+        let filters = chart
+            .laser_filters
+            .iter()
+            .map(|(tick, filter)| (tempo_map.tick_to_song_time(*tick), *filter))
+            .collect();
+        let gains = chart
+            .laser_filter_gain
+            .iter()
+            .map(|(tick, gain)| {
+                (tempo_map.tick_to_song_time(*tick), *gain as f32 / 100.)
+            })
+            .collect();
+
+        let cursors =
+            Arc::new([AtomicU64::new(NO_CURSOR), AtomicU64::new(NO_CURSOR)]);
+
+        let effect = LaserFilterEffect {
+            sample_rate,
+            filters,
+            gains,
+            cursors: cursors.clone(),
+            current: LaserFilter::default(),
+            amount: Smoother::with_time(0., CURSOR_GLIDE_MS, sample_rate),
+            mix: 0.,
+            fade_step: (1000. / (FADE_MS * sample_rate)) as f32,
+            biquad: Biquad::new(sample_rate),
+            crushed: [0.; 2],
+            crush_left: 0.,
+        };
+
+        (effect, LaserCursors {
+            cursors,
+        })
+    }
+
+    /// Returns how far the lasers being followed are from where they rest, if
+    /// any of them are
+    fn target_amount(&self) -> Option<f32> {
+        let cursor = |side: usize| {
+            match self.cursors[side].load(Ordering::Relaxed) {
+                NO_CURSOR => None,
+                bits => Some(f64::from_bits(bits)),
+            }
+        };
+
+        // the left laser rests on the left, and the right one on the right
+        let left = cursor(0);
+        let right = cursor(1).map(|cursor| 1. - cursor);
+
+        match (left, right) {
+            (Some(left), Some(right)) => Some(left.max(right) as f32),
+            (Some(amount), None) | (None, Some(amount)) => Some(amount as f32),
+            (None, None) => None,
+        }
+    }
+
+    fn filter(
+        &mut self,
+        frame: Frame,
+        amount: f64,
+        gain: f64,
+    ) -> Frame
+    {
+        match self.current {
+            LaserFilter::Peak => {
+                let center = exp_lerp(PEAK_MIN_HZ, PEAK_MAX_HZ, amount);
+                let boost =
+                    PEAK_MAX_DB * gain * (amount / PEAK_RAMP).min(1.);
+
+                self.biquad.set_peak(center, PEAK_Q, boost);
+                self.biquad.process(frame)
+            },
+
+            LaserFilter::LowPass => {
+                let cutoff = exp_lerp(LOW_PASS_MAX_HZ, LOW_PASS_MIN_HZ, amount);
+                let q = PASS_MIN_Q + (PASS_MAX_Q - PASS_MIN_Q) * gain;
+
+                self.biquad.set_low_pass(cutoff, q);
+                self.biquad.process(frame)
+            },
+
+            LaserFilter::HighPass => {
+                let cutoff =
+                    exp_lerp(HIGH_PASS_MIN_HZ, HIGH_PASS_MAX_HZ, amount);
+                let q = PASS_MIN_Q + (PASS_MAX_Q - PASS_MIN_Q) * gain;
+
+                self.biquad.set_high_pass(cutoff, q);
+                self.biquad.process(frame)
+            },
+
+            LaserFilter::BitCrusher => {
+                // the reduction is fractional, so it changes smoothly with
+                // the laser
+                if self.crush_left <= 0. {
+                    self.crushed = frame;
+                    self.crush_left += 1. +
+                        amount * gain * 2. * BIT_CRUSHER_MAX_REDUCTION;
+                }
+
+                self.crush_left -= 1.;
+                self.crushed
+            },
+        }
+    }
+}
+
+impl Effect for LaserFilterEffect {
+    fn apply(
+        &mut self,
+        frame: Frame,
+        time: SongTime,
+    ) -> Frame
+    {
+        let target = self.target_amount();
+
+        // the filter stays where it was as it fades out
+        let amount = match target {
+            Some(target) => self.amount.next(target),
+            None => self.amount.value(),
+        };
+
+        self.mix = if target.is_some() {
+            (self.mix + self.fade_step).min(1.)
+        }
+        else {
+            (self.mix - self.fade_step).max(0.)
+        };
+
+        let filter = at(&self.filters, time).unwrap_or_default();
+        if filter != self.current {
+            // the state of one filter makes no sense to another
+            self.current = filter;
+            self.biquad = Biquad::new(self.sample_rate);
+            self.crush_left = 0.;
+        }
+
+        if self.mix <= 0. {
+            return frame;
+        }
+
+        let gain = at(&self.gains, time)
+            .unwrap_or(DEFAULT_GAIN as f32 / 100.);
+        let wet = self.filter(frame, amount as f64, gain as f64);
+        let mix = self.mix;
+
+        [
+            frame[0] + (wet[0] - frame[0]) * mix,
+            frame[1] + (wet[1] - frame[1]) * mix,
+        ]
+    }
+}
+
+impl LaserCursors {
+    /// Sets where the cursor of the side is, from 0 on the left to 1 on the
+    /// right. There's no cursor when there's no laser to follow.
+    pub fn set(
+        &self,
+        side: usize,
+        cursor: Option<f64>,
+    )
+    {
+        let bits = cursor.map_or(NO_CURSOR, f64::to_bits);
+        self.cursors[side].store(bits, Ordering::Relaxed);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns whatever was last set at or before the given time
+fn at<T>(
+    events: &[(SongTime, T)],
+    time: SongTime,
+) -> Option<T>
+where
+    T: Copy,
+{
+    // never finds an equal, so it ends up past everything set by the time
+    let idx = events
+        .binary_search_by(|(at, _)| {
+            if *at <= time {
+                cmp::Ordering::Less
+            }
+            else {
+                cmp::Ordering::Greater
+            }
+        })
+        .unwrap_err();
+
+    idx.checked_sub(1).map(|idx| events[idx].1)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn laser_filter(
+        filters: Vec<(u32, LaserFilter)>,
+        gains: Vec<(u32, u8)>,
+        freq: u32,
+    ) -> (LaserFilterEffect, LaserCursors)
+    {
+        let mut chart = Chart::default();
+        chart.laser_filters = filters;
+        chart.laser_filter_gain = gains;
+        let tempo_map = TempoMap::new(&[(0, 120.)], &[], &[], 0, freq);
+
+        LaserFilterEffect::new(&chart, &tempo_map)
+    }
+
+    // the loudest frame of a 4 kHz sine once the filter has settled
+    fn loudness(
+        effect: &mut LaserFilterEffect,
+        start: i64,
+    ) -> f32
+    {
+        let sine = |time: i64| {
+            (2. * PI * 4_000. * time as f64 / 48_000.).sin() as f32
+        };
+
+        (start .. start + 4_800)
+            .map(|time| effect.apply([sine(time); 2], SongTime(time))[0])
+            .skip(4_320)
+            .fold(0., |loudest, sample| loudest.max(sample.abs()))
+    }
+
+    #[test]
+    fn passes_the_music_without_lasers() {
+        let (mut effect, lasers) =
+            laser_filter(vec![(0, LaserFilter::BitCrusher)], vec![], 1000);
+
+        for time in 0 .. 100 {
+            let frame = [time as f32, -time as f32];
+            assert_eq!(effect.apply(frame, SongTime(time)), frame);
+        }
+
+        // nor once the lasers are gone and the filter has faded out
+        lasers.set(0, Some(0.5));
+        for time in 100 .. 200 {
+            effect.apply([time as f32; 2], SongTime(time));
+        }
+
+        lasers.set(0, None);
+        for time in 200 .. 210 {
+            effect.apply([time as f32; 2], SongTime(time));
+        }
+
+        for time in 210 .. 300 {
+            let frame = [time as f32, -time as f32];
+            assert_eq!(effect.apply(frame, SongTime(time)), frame);
+        }
+    }
+
+    #[test]
+    fn follows_the_cursors() {
+        let (mut effect, lasers) =
+            laser_filter(vec![(0, LaserFilter::LowPass)], vec![], 48_000);
+
+        // the further the laser is from where it rests, the lower the cutoff
+        lasers.set(0, Some(0.1));
+        assert!(loudness(&mut effect, 0) > 0.7);
+
+        lasers.set(0, Some(0.9));
+        assert!(loudness(&mut effect, 4_800) < 0.1);
+
+        lasers.set(0, Some(0.1));
+        assert!(loudness(&mut effect, 9_600) > 0.7);
+
+        // the right laser rests on the right
+        lasers.set(0, None);
+        lasers.set(1, Some(0.1));
+        assert!(loudness(&mut effect, 14_400) < 0.1);
+    }
+
+    #[test]
+    fn switches_the_filter_as_the_chart_says() {
+        // a low pass for the first second, and then a high pass
+        let (mut effect, lasers) = laser_filter(
+            vec![(0, LaserFilter::LowPass), (480, LaserFilter::HighPass)],
+            vec![],
+            48_000,
+        );
+        lasers.set(0, Some(0.9));
+
+        let outputs: Vec<_> = (0 .. 96_000)
+            .map(|time| effect.apply([1.; 2], SongTime(time))[0])
+            .collect();
+
+        assert!((outputs[47_999] - 1.).abs() < 0.01);
+        assert!(outputs[95_999].abs() < 0.01);
+    }
+
+    // how many frames the bit crusher holds each frame for, on average
+    fn held_frames(gains: Vec<(u32, u8)>) -> f64 {
+        let (mut effect, lasers) =
+            laser_filter(vec![(0, LaserFilter::BitCrusher)], gains, 1000);
+        lasers.set(0, Some(0.5));
+
+        let outputs: Vec<_> = (0 .. 2000)
+            .map(|time| effect.apply([time as f32; 2], SongTime(time))[0])
+            .collect();
+        let changes = outputs[999 ..]
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .count();
+
+        1000. / changes as f64
+    }
+
+    #[test]
+    fn filters_as_much_as_the_gain_says() {
+        assert_eq!(held_frames(vec![(0, 0)]), 1.);
+        assert!((held_frames(vec![]) - 31.).abs() < 1.5);
+        assert!((held_frames(vec![(0, 100)]) - 61.).abs() < 3.);
+    }
+}
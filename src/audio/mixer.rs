@@ -0,0 +1,163 @@
+use crate::{
+    audio::{
+        sample::Sample,
+        Effect,
+        Frame,
+    },
+    song_player::song_timer::SongTime,
+};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the most samples played at once. the oldest one is cut off for a new one.
+const MAX_VOICES: usize = 16;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Plays samples over the music as soon as they're triggered.
+///
+/// The samples aren't timed by the song, since they're played in response to
+/// the player. They're picked up on the next frame taken by the output.
+pub struct SampleMixer {
+    samples: Vec<Sample>,
+    voices:  Vec<Voice>,
+
+    // the voices triggered since the last frame
+    triggered: Arc<Mutex<Vec<Voice>>>,
+}
+
+/// Triggers the samples of a mixer, by their index in it
+#[derive(Clone)]
+pub struct SampleTrigger {
+    triggered: Arc<Mutex<Vec<Voice>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    sample:   usize,
+    position: usize,
+    volume:   f32,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl SampleMixer {
+    pub fn new(samples: Vec<Sample>) -> (SampleMixer, SampleTrigger) {
+        let triggered = Arc::new(Mutex::new(vec![]));
+
+        let mixer = SampleMixer {
+            samples,
+            voices: vec![],
+            triggered: triggered.clone(),
+        };
+
+        (mixer, SampleTrigger {
+            triggered,
+        })
+    }
+}
+
+impl Effect for SampleMixer {
+    fn apply(
+        &mut self,
+        frame: Frame,
+        _time: SongTime,
+    ) -> Frame
+    {
+        // the output mustn't wait on the game, so the triggers are left for
+        // the next frame if they're being added to
+        if let Some(mut triggered) = self.triggered.try_lock() {
+            self.voices.extend(triggered.drain(..));
+        }
+
+        if MAX_VOICES < self.voices.len() {
+            let excess = self.voices.len() - MAX_VOICES;
+            self.voices.drain(.. excess);
+        }
+
+        let mut out = frame;
+        let samples = &self.samples;
+
+        self.voices.retain(|voice| {
+            samples
+                .get(voice.sample)
+                .map_or(false, |sample| voice.position < sample.len())
+        });
+
+        for voice in self.voices.iter_mut() {
+            let [left, right] = samples[voice.sample].frame(voice.position);
+
+            out[0] += left * voice.volume;
+            out[1] += right * voice.volume;
+            voice.position += 1;
+        }
+
+        out
+    }
+}
+
+impl SampleTrigger {
+    /// Plays the sample from its start. Samples that aren't in the mixer are
+    /// ignored.
+    pub fn play(
+        &self,
+        sample: usize,
+        volume: f32,
+    )
+    {
+        self.triggered.lock().push(Voice {
+            sample,
+            position: 0,
+            volume,
+        });
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    #[test]
+    fn plays_the_triggered_samples_over_the_frames() {
+        let slam = Sample::slam(SAMPLE_RATE);
+        let (mut mixer, trigger) = SampleMixer::new(vec![slam.clone()]);
+
+        assert_eq!(mixer.apply([1., 2.], SongTime(0)), [1., 2.]);
+
+        // the samples that aren't in the mixer are ignored
+        trigger.play(0, 0.5);
+        trigger.play(1, 1.);
+
+        for idx in 0 .. slam.len() {
+            let [left, right] = slam.frame(idx);
+            let frame = mixer.apply([1., 2.], SongTime(0));
+
+            assert_eq!(frame, [1. + left * 0.5, 2. + right * 0.5]);
+        }
+
+        assert_eq!(mixer.apply([1., 2.], SongTime(0)), [1., 2.]);
+    }
+
+    #[test]
+    fn cuts_off_the_oldest_voices() {
+        let slam = Sample::slam(SAMPLE_RATE);
+        let (mut mixer, trigger) = SampleMixer::new(vec![slam.clone()]);
+
+        // the first one doesn't fit
+        for volume in 1 ..= MAX_VOICES + 1 {
+            trigger.play(0, volume as f32);
+        }
+
+        let [left, _] = slam.frame(0);
+        let [mixed, _] = mixer.apply([0.; 2], SongTime(0));
+        let volumes = (2 ..= MAX_VOICES + 1).sum::<usize>() as f32;
+
+        assert!((mixed - left * volumes).abs() <= left.abs() * 1e-5);
+    }
+}
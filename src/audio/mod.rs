@@ -0,0 +1,83 @@
+pub mod dsp;
+pub mod fx_hold;
+pub mod laser_filter;
+pub mod mixer;
+pub mod music;
+pub mod output;
+pub mod sample;
+pub mod song;
+pub mod stretch;
+
+////////////////////////////////////////////////////////////////////////////////
+
+use crate::song_player::song_timer::SongTime;
+use rodio::decoder::DecoderError;
+use std::{
+    fmt,
+    io,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// everything is mixed in stereo
+pub const CHANNELS: u16 = 2;
+
+// the rate of the silence played in place of music that can't be loaded
+pub const SILENCE_SAMPLE_RATE: u32 = 44_100;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A frame of audio, as the left and the right samples
+pub type Frame = [f32; 2];
+
+/// Changes the music as it's played
+pub trait Effect: Send {
+    /// Applies the effect to the frame of the music at the given time
+    fn apply(
+        &mut self,
+        frame: Frame,
+        time: SongTime,
+    ) -> Frame;
+}
+
+#[derive(Debug)]
+pub enum AudioError {
+    Io(io::Error),
+    Decoder(DecoderError),
+    NoOutputDevice,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl fmt::Display for AudioError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        match self {
+            AudioError::Io(e) => write!(f, "could not read audio: {}", e),
+            AudioError::Decoder(e) => {
+                write!(f, "could not decode audio: {}", e)
+            },
+            AudioError::NoOutputDevice => {
+                write!(f, "there's no audio device to play on")
+            },
+        }
+    }
+}
+
+impl std::error::Error for AudioError {
+}
+
+impl From<io::Error> for AudioError {
+    fn from(e: io::Error) -> AudioError {
+        AudioError::Io(e)
+    }
+}
+
+impl From<DecoderError> for AudioError {
+    fn from(e: DecoderError) -> AudioError {
+        AudioError::Decoder(e)
+    }
+}
@@ -0,0 +1,579 @@
+use crate::{
+    audio::{
+        output::AudioOutput,
+        stretch::TimeStretch,
+        AudioError,
+        Effect,
+        Frame,
+        CHANNELS,
+        SILENCE_SAMPLE_RATE,
+    },
+    chart::Chart,
+    song_player::{
+        governor::CURRENT_SONG_TIMER,
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use parking_lot::Mutex;
+use rodio::{
+    Decoder,
+    Source,
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+    },
+    time::Duration,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how long the silence played in place of missing music goes on after the
+// last note
+const SILENCE_TAIL_SECONDS: f64 = 2.;
+
+// the slowest the music can be played at
+const MIN_RATE: f32 = 0.5;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A song decoded into memory, in stereo
+#[derive(Debug, Clone)]
+pub struct Music {
+    // the frames are kept as the left and right samples, one after the other
+    samples:     Arc<Vec<f32>>,
+    sample_rate: u32,
+}
+
+/// Plays the music of a song, which in turn drives the song timer.
+///
+/// The timer is moved by a frame for every frame of the music that is taken by
+/// the output. It may start before the music does, in which case silence is
+/// played until the timer reaches zero.
+pub struct MusicPlayer {
+    control:     Arc<MusicControl>,
+    sample_rate: u32,
+    length:      SongTime,
+    output:      Box<dyn AudioOutput>,
+}
+
+/// The music as a stream of samples, moving the song timer as it's taken
+pub struct MusicSource {
+    music:   Music,
+    control: Arc<MusicControl>,
+    effects: Vec<Box<dyn Effect>>,
+
+    // how far past the song timer the music is, while it's slowed down
+    fraction: f64,
+    stretch:  TimeStretch,
+
+    // the frame being handed out and the channel to hand out next
+    frame:   Frame,
+    channel: usize,
+}
+
+// shared between the player and the source it plays
+#[derive(Debug)]
+struct MusicControl {
+    is_playing: AtomicBool,
+    is_stopped: AtomicBool,
+    volume:     Mutex<f32>,
+
+    // how fast the music is played, and whether it keeps its pitch when it's
+    // slowed down
+    rate:        Mutex<f32>,
+    keeps_pitch: AtomicBool,
+
+    // where to move the timer to before the next frame
+    seek_to: Mutex<Option<SongTime>>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Music {
+    pub fn load<P>(path: P) -> Result<Music, AudioError>
+    where P: AsRef<Path> {
+        let decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+        let channels = decoder.channels().max(1) as usize;
+        let sample_rate = decoder.sample_rate();
+
+        let decoded = decoder.convert_samples::<f32>().collect::<Vec<_>>();
+
+        // mono is played on both sides, while anything past the first two
+        // channels is dropped
+        let samples = decoded
+            .chunks(channels)
+            .flat_map(|frame| {
+                let left = frame[0];
+                let right = frame.get(1).cloned().unwrap_or(left);
+                vec![left, right]
+            })
+            .collect();
+
+        Ok(Music {
+            samples: Arc::new(samples),
+            sample_rate,
+        })
+    }
+
+    /// Loads the music of the chart at the given path, or silence if it can't
+    /// be loaded
+    pub fn of_chart(
+        chart: &Chart,
+        chart_path: &Path,
+    ) -> Music
+    {
+        let path = chart_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&chart.meta.music_file);
+
+        Music::load(&path).unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {}", path.display(), e);
+
+            let tempo_map = TempoMap::from_chart(chart, SILENCE_SAMPLE_RATE);
+            let end = tempo_map
+                .tick_to_song_time(chart.last_tick())
+                .as_seconds(SILENCE_SAMPLE_RATE);
+
+            Music::silence(end + SILENCE_TAIL_SECONDS, SILENCE_SAMPLE_RATE)
+        })
+    }
+
+    /// Makes music out of stereo samples
+    pub fn from_samples(
+        samples: Vec<f32>,
+        sample_rate: u32,
+    ) -> Music
+    {
+        Music {
+            samples: Arc::new(samples),
+            sample_rate,
+        }
+    }
+
+    /// Makes music that's only silence, for when a song has none
+    pub fn silence(
+        seconds: f64,
+        sample_rate: u32,
+    ) -> Music
+    {
+        let frames = (seconds * sample_rate as f64) as usize;
+
+        Music {
+            samples: Arc::new(vec![0.; frames * CHANNELS as usize]),
+            sample_rate,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the length of the music in song time
+    pub fn length(&self) -> SongTime {
+        SongTime((self.samples.len() / CHANNELS as usize) as i64)
+    }
+
+    /// Returns the frame at the given time, which is silence outside of the
+    /// music
+    pub fn frame(
+        &self,
+        time: SongTime,
+    ) -> Frame
+    {
+        if time.0 < 0 || self.length() <= time {
+            return [0.; 2];
+        }
+
+        let idx = time.0 as usize * CHANNELS as usize;
+        [self.samples[idx], self.samples[idx + 1]]
+    }
+
+    /// Returns the frame at a position between two frames, interpolated
+    /// between them
+    pub fn frame_at(
+        &self,
+        position: f64,
+    ) -> Frame
+    {
+        let whole = position.floor();
+        let fract = (position - whole) as f32;
+
+        let a = self.frame(SongTime(whole as i64));
+        let b = self.frame(SongTime(whole as i64 + 1));
+
+        [a[0] + (b[0] - a[0]) * fract, a[1] + (b[1] - a[1]) * fract]
+    }
+}
+
+impl MusicPlayer {
+    /// Readies the music on the output, paused at the given time. The effects
+    /// are applied to the music in order.
+    pub fn new(
+        music: Music,
+        start: SongTime,
+        volume: f32,
+        effects: Vec<Box<dyn Effect>>,
+        mut output: Box<dyn AudioOutput>,
+    ) -> MusicPlayer
+    {
+        let sample_rate = music.sample_rate();
+        let length = music.length();
+
+        CURRENT_SONG_TIMER.start(sample_rate);
+        CURRENT_SONG_TIMER.set(start);
+
+        let control = Arc::new(MusicControl {
+            is_playing:  AtomicBool::new(false),
+            is_stopped:  AtomicBool::new(false),
+            volume:      Mutex::new(volume),
+            rate:        Mutex::new(1.),
+            keeps_pitch: AtomicBool::new(false),
+            seek_to:     Mutex::new(None),
+        });
+
+        let source = MusicSource::new(music, control.clone(), effects);
+        output.play(Box::new(source));
+
+        MusicPlayer {
+            control,
+            sample_rate,
+            length,
+            output,
+        }
+    }
+
+    /// Returns the frequency the song timer is counted in
+    pub fn freq(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn length(&self) -> SongTime {
+        self.length
+    }
+
+    /// Starts the music, or resumes it if it has been paused
+    pub fn play(&self) {
+        self.control.is_playing.store(true, Ordering::SeqCst);
+    }
+
+    /// Pauses the music. The song timer stops along with it.
+    pub fn pause(&self) {
+        self.control.is_playing.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.control.is_playing.load(Ordering::SeqCst)
+    }
+
+    /// Moves the music and the song timer to the given time
+    pub fn seek(
+        &self,
+        time: SongTime,
+    )
+    {
+        *self.control.seek_to.lock() = Some(time);
+    }
+
+    pub fn set_volume(
+        &self,
+        volume: f32,
+    )
+    {
+        *self.control.volume.lock() = volume;
+    }
+
+    /// Sets how fast the music is played, from half its speed to its full
+    /// speed. The song timer slows down along with it. A slowed down song
+    /// sounds lower unless it keeps its pitch.
+    pub fn set_rate(
+        &self,
+        rate: f32,
+        keeps_pitch: bool,
+    )
+    {
+        *self.control.rate.lock() = rate.max(MIN_RATE).min(1.);
+        self.control.keeps_pitch.store(keeps_pitch, Ordering::SeqCst);
+    }
+
+    /// Returns true once the music has been played to its end
+    pub fn is_finished(&self) -> bool {
+        CURRENT_SONG_TIMER
+            .get_current_song_time()
+            .map_or(true, |time| self.length <= time)
+    }
+}
+
+impl Drop for MusicPlayer {
+    fn drop(&mut self) {
+        self.control.is_stopped.store(true, Ordering::SeqCst);
+        self.output.stop();
+
+        // nothing is timed by the song anymore
+        CURRENT_SONG_TIMER.stop_and_reset();
+    }
+}
+
+impl MusicSource {
+    fn new(
+        music: Music,
+        control: Arc<MusicControl>,
+        effects: Vec<Box<dyn Effect>>,
+    ) -> MusicSource
+    {
+        let stretch = TimeStretch::new(music.sample_rate());
+
+        MusicSource {
+            music,
+            control,
+            effects,
+            fraction: 0.,
+            stretch,
+            frame: [0.; 2],
+            channel: CHANNELS as usize,
+        }
+    }
+
+    fn next_frame(&mut self) -> Option<Frame> {
+        let control = &self.control;
+
+        if control.is_stopped.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        if let Some(time) = control.seek_to.lock().take() {
+            CURRENT_SONG_TIMER.set(time);
+            self.fraction = 0.;
+            self.stretch.reset();
+        }
+
+        // the timer doesn't move while the music is paused
+        if !control.is_playing.load(Ordering::SeqCst) {
+            return Some([0.; 2]);
+        }
+
+        let time = CURRENT_SONG_TIMER.get_current_song_time()?;
+        let rate = *control.rate.lock() as f64;
+        let position = time.0 as f64 + self.fraction;
+
+        let [left, right] = if 1. <= rate {
+            self.music.frame(time)
+        }
+        else if control.keeps_pitch.load(Ordering::Relaxed) {
+            self.stretch.frame(&self.music, position)
+        }
+        else {
+            self.music.frame_at(position)
+        };
+
+        // the timer only moves by whole frames, with the rest kept for later
+        self.fraction += rate;
+        let frames = self.fraction.floor();
+        self.fraction -= frames;
+        CURRENT_SONG_TIMER.advance(frames as i64);
+
+        let volume = *control.volume.lock();
+        let frame = [left * volume, right * volume];
+
+        Some(
+            self.effects
+                .iter_mut()
+                .fold(frame, |frame, effect| effect.apply(frame, time)),
+        )
+    }
+}
+
+impl Iterator for MusicSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == CHANNELS as usize {
+            self.frame = self.next_frame()?;
+            self.channel = 0;
+        }
+
+        let sample = self.frame[self.channel];
+        self.channel += 1;
+
+        Some(sample)
+    }
+}
+
+impl Source for MusicSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.music.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::output::NullOutput,
+        song_player::governor::SONG_TIMER_LOCK,
+    };
+
+    const SAMPLE_RATE: u32 = 1000;
+
+    // four seconds of music, where every frame is its own index on both sides
+    fn ramp() -> Music {
+        let samples = (0 .. 4 * SAMPLE_RATE)
+            .flat_map(|idx| vec![idx as f32; 2])
+            .collect();
+
+        Music::from_samples(samples, SAMPLE_RATE)
+    }
+
+    fn player(start: SongTime) -> (MusicPlayer, NullOutput) {
+        let output = NullOutput::new();
+        let player = MusicPlayer::new(
+            ramp(),
+            start,
+            1.,
+            vec![],
+            Box::new(output.clone()),
+        );
+
+        (player, output)
+    }
+
+    fn now() -> SongTime {
+        CURRENT_SONG_TIMER.get_current_song_time().unwrap()
+    }
+
+    #[test]
+    fn pausing_stops_the_timer() {
+        let _lock = SONG_TIMER_LOCK.lock();
+        let (player, output) = player(SongTime(0));
+        assert!(!player.is_playing());
+
+        player.play();
+        assert!(player.is_playing());
+        assert_eq!(&output.pull(100)[198 ..], &[99., 99.]);
+        assert_eq!(now(), SongTime(100));
+
+        // only silence is played while it's paused
+        player.pause();
+        assert!(!player.is_playing());
+        assert!(output.pull(100).iter().all(|sample| *sample == 0.));
+        assert_eq!(now(), SongTime(100));
+
+        // and it picks up where it was
+        player.play();
+        assert_eq!(&output.pull(50)[.. 2], &[100., 100.]);
+        assert_eq!(now(), SongTime(150));
+    }
+
+    #[test]
+    fn seeking_moves_the_timer() {
+        let _lock = SONG_TIMER_LOCK.lock();
+        let (player, output) = player(SongTime(0));
+
+        player.play();
+        output.pull(100);
+
+        // the timer moves with the next frame taken
+        player.seek(SongTime(-300));
+        assert_eq!(now(), SongTime(100));
+
+        // and there's only silence before the music
+        assert!(output.pull(100).iter().all(|sample| *sample == 0.));
+        assert_eq!(now(), SongTime(-200));
+
+        player.seek(SongTime(2500));
+        assert_eq!(&output.pull(10)[.. 2], &[2500., 2500.]);
+        assert_eq!(now(), SongTime(2510));
+
+        // even while it's paused
+        player.pause();
+        player.seek(SongTime(1000));
+        output.pull(10);
+        assert_eq!(now(), SongTime(1000));
+
+        player.play();
+        assert_eq!(&output.pull(1)[..], &[1000., 1000.]);
+    }
+
+    #[test]
+    fn seeking_starts_the_slowed_down_music_anew() {
+        let _lock = SONG_TIMER_LOCK.lock();
+        let (player, output) = player(SongTime(0));
+
+        player.set_rate(0.5, true);
+        player.play();
+        output.pull(101);
+        assert_eq!(now(), SongTime(50));
+
+        // the stretch starts over right at the time seeked to, and the timer
+        // is a whole frame there
+        player.seek(SongTime(2000));
+        assert_eq!(&output.pull(1)[..], &[2000., 2000.]);
+        assert_eq!(now(), SongTime(2000));
+
+        output.pull(1);
+        assert_eq!(now(), SongTime(2001));
+    }
+
+    #[test]
+    fn slows_the_timer_down_with_the_rate() {
+        let _lock = SONG_TIMER_LOCK.lock();
+
+        // the timer moves a frame every two frames, whether the music keeps
+        // its pitch or not
+        for &keeps_pitch in [false, true].iter() {
+            let (player, output) = player(SongTime(0));
+            player.set_rate(0.5, keeps_pitch);
+            player.play();
+
+            for frame in 1 ..= 400 {
+                assert_eq!(output.pull(1).len(), 2);
+                assert_eq!(now(), SongTime(frame / 2));
+            }
+        }
+
+        // the music is read right where the timer is in between its frames
+        let (player, output) = player(SongTime(0));
+        player.set_rate(0.5, false);
+        player.play();
+
+        let pulled = output.pull(100);
+        for idx in 0 .. 100 {
+            assert_eq!(pulled[idx * 2], idx as f32 * 0.5);
+        }
+    }
+
+    #[test]
+    fn sets_the_volume() {
+        let _lock = SONG_TIMER_LOCK.lock();
+        let (player, output) = player(SongTime(1000));
+
+        player.play();
+        assert_eq!(&output.pull(1)[..], &[1000., 1000.]);
+
+        player.set_volume(0.5);
+        assert_eq!(&output.pull(1)[..], &[500.5, 500.5]);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::audio::AudioError;
+use parking_lot::Mutex;
+use rodio::{
+    Device,
+    Sink,
+    Source,
+};
+use std::{
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how often the clock of a null output wakes up to take the samples
+const NULL_CLOCK_PERIOD_MS: u64 = 5;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Anything that can be played on an output
+pub type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Where the audio is played.
+///
+/// Whatever is played is taken from as fast as the output plays it, which is
+/// what the song timer is counted by.
+pub trait AudioOutput: Send {
+    /// Plays the source, in place of whatever was being played
+    fn play(
+        &mut self,
+        source: BoxedSource,
+    );
+
+    fn stop(&mut self);
+}
+
+/// Plays on the default audio device
+pub struct RodioOutput {
+    device: Device,
+    sink:   Option<Sink>,
+}
+
+/// Takes the samples without playing them anywhere, as if they had been played
+/// at the rate of the source.
+///
+/// Time only passes for the output when the samples are pulled from it, either
+/// by hand or by a clock running alongside it. This allows a song to be played
+/// without an audio device, and faster than it would be heard.
+#[derive(Clone)]
+pub struct NullOutput {
+    inner: Arc<Mutex<NullInner>>,
+
+    // whether a clock is pulling the samples
+    is_clocked: Arc<AtomicBool>,
+}
+
+struct NullInner {
+    source: Option<BoxedSource>,
+
+    // the frames pulled so far, which is how much time has passed
+    frames_pulled: u64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl RodioOutput {
+    pub fn new() -> Result<RodioOutput, AudioError> {
+        let device =
+            rodio::default_output_device().ok_or(AudioError::NoOutputDevice)?;
+
+        Ok(RodioOutput {
+            device,
+            sink: None,
+        })
+    }
+}
+
+impl AudioOutput for RodioOutput {
+    fn play(
+        &mut self,
+        source: BoxedSource,
+    )
+    {
+        self.stop();
+
+        // a sink that has been stopped can't be played on again
+        let sink = Sink::new(&self.device);
+        sink.append(source);
+        self.sink = Some(sink);
+    }
+
+    fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+    }
+}
+
+impl NullOutput {
+    pub fn new() -> NullOutput {
+        let inner = NullInner {
+            source:        None,
+            frames_pulled: 0,
+        };
+
+        NullOutput {
+            inner:      Arc::new(Mutex::new(inner)),
+            is_clocked: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Pulls the given number of frames from the source. Returns the samples
+    /// taken, which are fewer than asked for if the source has ended.
+    pub fn pull(
+        &self,
+        frames: usize,
+    ) -> Vec<f32>
+    {
+        let mut inner = self.inner.lock();
+
+        let source = match inner.source.as_mut() {
+            Some(source) => source,
+            None => return vec![],
+        };
+
+        let channels = source.channels().max(1) as usize;
+        let samples =
+            source.by_ref().take(frames * channels).collect::<Vec<_>>();
+
+        if samples.len() < frames * channels {
+            inner.source = None;
+        }
+
+        inner.frames_pulled += (samples.len() / channels) as u64;
+        samples
+    }
+
+    /// Pulls as many frames as would have been played in the given duration
+    pub fn advance(
+        &self,
+        duration: Duration,
+    )
+    {
+        let frames = (duration_seconds(duration) * self.sample_rate() as f64)
+            .round() as usize;
+
+        self.pull(frames);
+    }
+
+    /// Returns the rate the source is played at, if there's one
+    pub fn sample_rate(&self) -> u32 {
+        self.inner
+            .lock()
+            .source
+            .as_ref()
+            .map_or(0, |source| source.sample_rate())
+    }
+
+    /// Returns the number of frames that have been pulled
+    pub fn frames_pulled(&self) -> u64 {
+        self.inner.lock().frames_pulled
+    }
+
+    /// Starts a clock that pulls the samples as fast as they would be played,
+    /// on a thread of its own. It stops along with the output.
+    pub fn start_clock(&self) {
+        if self.is_clocked.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let output = self.clone();
+        thread::spawn(move || {
+            let start = Instant::now();
+            let start_frames = output.frames_pulled();
+
+            // the frames are counted from the start, so the rounding doesn't
+            // pile up over time
+            while output.is_clocked.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(NULL_CLOCK_PERIOD_MS));
+
+                let elapsed = duration_seconds(start.elapsed());
+                let due = start_frames +
+                    (elapsed * output.sample_rate() as f64) as u64;
+                let pulled = output.frames_pulled();
+
+                if pulled < due {
+                    output.pull((due - pulled) as usize);
+                }
+            }
+        });
+    }
+}
+
+impl AudioOutput for NullOutput {
+    fn play(
+        &mut self,
+        source: BoxedSource,
+    )
+    {
+        self.inner.lock().source = Some(source);
+    }
+
+    fn stop(&mut self) {
+        self.inner.lock().source = None;
+        self.is_clocked.store(false, Ordering::SeqCst);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn duration_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::music::{
+            Music,
+            MusicPlayer,
+        },
+        song_player::{
+            governor::{
+                CURRENT_SONG_TIMER,
+                SONG_TIMER_LOCK,
+            },
+            song_timer::SongTime,
+        },
+    };
+
+    const SAMPLE_RATE: u32 = 1000;
+
+    // a stereo source of the given number of frames, counting up
+    struct Counter {
+        samples: std::ops::Range<usize>,
+    }
+
+    impl Iterator for Counter {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            self.samples.next().map(|sample| (sample / 2) as f32)
+        }
+    }
+
+    impl Source for Counter {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    fn counter(frames: usize) -> BoxedSource {
+        Box::new(Counter {
+            samples: 0 .. frames * 2,
+        })
+    }
+
+    #[test]
+    fn pulls_the_frames_asked_for() {
+        let mut output = NullOutput::new();
+        assert!(output.pull(10).is_empty());
+        assert_eq!(output.frames_pulled(), 0);
+
+        output.play(counter(300));
+        assert_eq!(output.sample_rate(), SAMPLE_RATE);
+
+        let samples = output.pull(100);
+        assert_eq!(samples.len(), 200);
+        assert_eq!(&samples[198 ..], &[99., 99.]);
+        assert_eq!(output.frames_pulled(), 100);
+
+        output.advance(Duration::from_millis(150));
+        assert_eq!(output.frames_pulled(), 250);
+
+        // the source ends before the frames asked for
+        assert_eq!(output.pull(100).len(), 100);
+        assert_eq!(output.frames_pulled(), 300);
+        assert!(output.pull(100).is_empty());
+    }
+
+    #[test]
+    fn moves_the_song_timer_by_the_frames_pulled() {
+        let _lock = SONG_TIMER_LOCK.lock();
+
+        let output = NullOutput::new();
+        let player = MusicPlayer::new(
+            Music::silence(2., SAMPLE_RATE),
+            SongTime(-100),
+            1.,
+            vec![],
+            Box::new(output.clone()),
+        );
+        let now = || CURRENT_SONG_TIMER.get_current_song_time();
+
+        // nothing moves while paused
+        output.pull(50);
+        assert_eq!(now(), Some(SongTime(-100)));
+
+        player.play();
+        output.pull(50);
+        assert_eq!(now(), Some(SongTime(-50)));
+
+        output.pull(150);
+        assert_eq!(now(), Some(SongTime(100)));
+
+        // and the time passing doesn't move it either
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(now(), Some(SongTime(100)));
+
+        output.advance(Duration::from_millis(400));
+        assert_eq!(now(), Some(SongTime(500)));
+
+        drop(player);
+        assert_eq!(now(), None);
+    }
+}
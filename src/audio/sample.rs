@@ -0,0 +1,225 @@
+use crate::{
+    audio::{
+        dsp::Biquad,
+        music::Music,
+        AudioError,
+        Frame,
+    },
+    chart::FxSound,
+};
+use std::{
+    f64::consts::PI,
+    path::Path,
+    sync::Arc,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the claps are a few bursts of noise in quick succession, followed by a tail
+const CLAP_BURSTS: usize = 3;
+const CLAP_BURST_MS: f64 = 10.;
+
+// the snares start a bit higher than their tone and drop down to it
+const SNARE_PITCH_DROP: f64 = 1.5;
+const SNARE_NOISE_HZ: f64 = 1_500.;
+
+const SLAM_NOISE_HZ: f64 = 2_000.;
+const SLAM_MS: f64 = 40.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A short sound played over the music, at the rate of the music
+#[derive(Debug, Clone)]
+pub struct Sample {
+    frames: Arc<Vec<Frame>>,
+}
+
+// a noise that's the same every time, so the built-in samples are too
+struct Noise {
+    state: u32,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Sample {
+    /// Loads a sample from an audio file, resampled to the given rate
+    pub fn load<P>(
+        path: P,
+        sample_rate: u32,
+    ) -> Result<Sample, AudioError>
+    where
+        P: AsRef<Path>,
+    {
+        let music = Music::load(path)?;
+        let ratio = music.sample_rate() as f64 / sample_rate as f64;
+        let length = (music.length().0 as f64 / ratio).ceil() as usize;
+
+        let frames = (0 .. length)
+            .map(|idx| music.frame_at(idx as f64 * ratio))
+            .collect();
+
+        Ok(Sample {
+            frames: Arc::new(frames),
+        })
+    }
+
+    /// Makes the sound, loading it from the given directory if it's a file
+    pub fn from_sound<P>(
+        sound: &FxSound,
+        directory: P,
+        sample_rate: u32,
+    ) -> Result<Sample, AudioError>
+    where
+        P: AsRef<Path>,
+    {
+        let rate = sample_rate as f64;
+
+        let sample = match sound {
+            FxSound::Clap => clap(rate, 1_200., 150.),
+            FxSound::ClapImpact => clap(rate, 900., 250.),
+            FxSound::ClapPunchy => clap(rate, 1_600., 80.),
+            FxSound::Snare => snare(rate, 190., 180.),
+            FxSound::SnareLo => snare(rate, 150., 250.),
+            FxSound::File(file) => {
+                return Sample::load(directory.as_ref().join(file), sample_rate)
+            },
+        };
+
+        Ok(sample)
+    }
+
+    /// Makes the sound of a slam being hit
+    pub fn slam(sample_rate: u32) -> Sample {
+        let rate = sample_rate as f64;
+        let length = (SLAM_MS / 1000. * rate) as usize;
+
+        let mut noise = Noise::new();
+        let mut filter = Biquad::new(rate);
+        filter.set_high_pass(SLAM_NOISE_HZ, 0.7);
+
+        Sample::synthesize(length, |idx| {
+            let t = idx as f64 / length as f64;
+            let envelope = (1. - t).powi(3);
+
+            filter.process([noise.next(); 2])[0] * envelope as f32
+        })
+    }
+
+    /// Returns the number of frames in the sample
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the frame at the given index, which is silence past the end
+    pub fn frame(
+        &self,
+        idx: usize,
+    ) -> Frame
+    {
+        self.frames.get(idx).cloned().unwrap_or([0.; 2])
+    }
+
+    // makes a sample in mono out of every one of its samples
+    fn synthesize<F>(
+        length: usize,
+        mut sample: F,
+    ) -> Sample
+    where
+        F: FnMut(usize) -> f32,
+    {
+        let frames = (0 .. length)
+            .map(|idx| {
+                let sample = sample(idx);
+                [sample, sample]
+            })
+            .collect();
+
+        Sample {
+            frames: Arc::new(frames),
+        }
+    }
+}
+
+impl Noise {
+    fn new() -> Noise {
+        Noise {
+            state: 0x2545_f491,
+        }
+    }
+
+    /// Returns the next value of the noise, from -1 to 1
+    fn next(&mut self) -> f32 {
+        // xorshift
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        self.state as f32 / u32::max_value() as f32 * 2. - 1.
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Makes a clap out of noise around the given band
+fn clap(
+    sample_rate: f64,
+    band: f64,
+    tail_ms: f64,
+) -> Sample
+{
+    let burst = (CLAP_BURST_MS / 1000. * sample_rate) as usize;
+    let tail = (tail_ms / 1000. * sample_rate) as usize;
+
+    let mut noise = Noise::new();
+    let mut high_pass = Biquad::new(sample_rate);
+    let mut low_pass = Biquad::new(sample_rate);
+    high_pass.set_high_pass(band * 0.7, 0.7);
+    low_pass.set_low_pass(band * 2.5, 0.7);
+
+    Sample::synthesize(burst * CLAP_BURSTS + tail, |idx| {
+        // every burst starts loud and dies down before the next one, with the
+        // last one ringing out the longest
+        let envelope = if idx < burst * (CLAP_BURSTS - 1) {
+            let t = (idx % burst) as f64 / burst as f64;
+            (1. - t).powi(2)
+        }
+        else {
+            let t = (idx - burst * (CLAP_BURSTS - 1)) as f64 /
+                (burst + tail) as f64;
+            (1. - t).powi(4)
+        };
+
+        let filtered = low_pass.process(high_pass.process([noise.next(); 2]));
+        filtered[0] * envelope as f32
+    })
+}
+
+/// Makes a snare out of a falling tone and a tail of noise
+fn snare(
+    sample_rate: f64,
+    tone: f64,
+    tail_ms: f64,
+) -> Sample
+{
+    let length = (tail_ms / 1000. * sample_rate) as usize;
+
+    let mut noise = Noise::new();
+    let mut high_pass = Biquad::new(sample_rate);
+    high_pass.set_high_pass(SNARE_NOISE_HZ, 0.7);
+
+    let mut phase = 0.;
+
+    Sample::synthesize(length, |idx| {
+        let t = idx as f64 / length as f64;
+
+        // the tone is only in the attack, while the noise rings out
+        let pitch = tone * (1. + (SNARE_PITCH_DROP - 1.) * (1. - t).powi(8));
+        phase += pitch / sample_rate;
+        let body = (2. * PI * phase).sin() * (1. - t).powi(6);
+
+        let rattle = high_pass.process([noise.next(); 2])[0] as f64 *
+            (1. - t).powi(2);
+
+        (body * 0.5 + rattle * 0.35) as f32
+    })
+}
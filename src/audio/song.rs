@@ -0,0 +1,340 @@
+use crate::{
+    audio::{
+        fx_hold::{
+            FxHoldEffects,
+            FxHoldSwitch,
+        },
+        laser_filter::{
+            LaserCursors,
+            LaserFilterEffect,
+        },
+        mixer::{
+            SampleMixer,
+            SampleTrigger,
+        },
+        music::{
+            Music,
+            MusicPlayer,
+        },
+        output::AudioOutput,
+        sample::Sample,
+    },
+    chart::{
+        Chart,
+        FxSound,
+    },
+    judgment::{
+        hold::LongHitType,
+        JudgedObject,
+        Judgment,
+    },
+    song_player::{
+        session::SongSession,
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use std::path::Path;
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the slam is always the first sample of the mixer
+const SLAM_SAMPLE: usize = 0;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The audio of a song being played, which follows what the player does
+pub struct SongAudio {
+    player:   MusicPlayer,
+    fx_holds: FxHoldSwitch,
+    lasers:   LaserCursors,
+    samples:  SampleTrigger,
+
+    // the sample of every sounded FX chip, and its volume, by the time of the
+    // chip
+    fx_samples:  [Vec<(SongTime, usize, f32)>; 2],
+    slam_volume: f32,
+
+    // the number of judgments that have been played the samples of
+    heard: usize,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl SongAudio {
+    /// Readies the music of the chart on the output, paused at the given time.
+    /// The samples of the chart are loaded from its directory.
+    pub fn new(
+        chart: &Chart,
+        directory: &Path,
+        tempo_map: &TempoMap,
+        music: Music,
+        start: SongTime,
+        output: Box<dyn AudioOutput>,
+    ) -> SongAudio
+    {
+        let sample_rate = music.sample_rate();
+        let volume = chart.meta.music_volume as f32 / 100.;
+
+        // every sound is only loaded once, however many chips use it
+        let mut sounds: Vec<(FxSound, Option<usize>)> = vec![];
+        let mut bank = vec![Sample::slam(sample_rate)];
+
+        let mut fx_samples = [vec![], vec![]];
+        for (lane, samples) in chart.fx_samples.iter().enumerate() {
+            for (tick, sample) in samples.iter() {
+                let loaded = sounds.iter().find(|(s, _)| *s == sample.sound);
+
+                let idx = match loaded {
+                    Some((_, idx)) => *idx,
+                    None => {
+                        let idx = Sample::from_sound(
+                            &sample.sound,
+                            directory,
+                            sample_rate,
+                        )
+                        .map_err(|e| {
+                            eprintln!(
+                                "Failed to load {}: {}",
+                                sample.sound.name(),
+                                e
+                            )
+                        })
+                        .ok()
+                        .map(|loaded| {
+                            bank.push(loaded);
+                            bank.len() - 1
+                        });
+
+                        sounds.push((sample.sound.clone(), idx));
+                        idx
+                    },
+                };
+
+                // the chips of a sample that couldn't be loaded are silent
+                if let Some(idx) = idx {
+                    fx_samples[lane].push((
+                        tempo_map.tick_to_song_time(*tick),
+                        idx,
+                        sample.volume as f32 / 100.,
+                    ));
+                }
+            }
+        }
+
+        let (fx_effects, fx_holds) = FxHoldEffects::new(chart, tempo_map);
+        let (laser_filter, lasers) = LaserFilterEffect::new(chart, tempo_map);
+        let (mixer, samples) = SampleMixer::new(bank);
+
+        // the samples are played over the effects, so they're heard as they
+        // are
+        let player = MusicPlayer::new(
+            music,
+            start,
+            volume,
+            vec![
+                Box::new(fx_effects),
+                Box::new(laser_filter),
+                Box::new(mixer),
+            ],
+            output,
+        );
+
+        SongAudio {
+            player,
+            fx_holds,
+            lasers,
+            samples,
+            fx_samples,
+            slam_volume: chart.meta.slam_volume as f32 / 100.,
+            heard: 0,
+        }
+    }
+
+    pub fn player(&self) -> &MusicPlayer {
+        &self.player
+    }
+
+    /// Switches the effects of the FX holds on for as long as they're held,
+    /// has the laser filter follow the cursors and plays the samples of
+    /// whatever has just been hit
+    pub fn sync(
+        &mut self,
+        session: &SongSession,
+    )
+    {
+        let holds = session.judgment().fx_holds();
+
+        for lane in 0 .. 2 {
+            let held = holds
+                .lane(lane)
+                .iter()
+                .position(|hold| hold.hit_type() == LongHitType::Active);
+
+            self.fx_holds.set_held(lane, held);
+        }
+
+        let lasers = session.judgment().lasers();
+
+        for side in 0 .. 2 {
+            self.lasers.set(side, lasers.cursor(side));
+        }
+
+        // the samples are played as the notes are judged, instead of when
+        // they're due
+        let events = session.events();
+
+        for event in events[self.heard ..].iter() {
+            if event.judgment == Judgment::Error {
+                continue;
+            }
+
+            match event.object {
+                JudgedObject::FxChip(lane) => {
+                    let samples = &self.fx_samples[lane];
+                    let found = samples
+                        .binary_search_by_key(&event.time, |(time, ..)| *time);
+
+                    if let Ok(idx) = found {
+                        let (_, sample, volume) = samples[idx];
+                        self.samples.play(sample, volume);
+                    }
+                },
+
+                JudgedObject::Slam(_) => {
+                    self.samples.play(SLAM_SAMPLE, self.slam_volume)
+                },
+
+                _ => {},
+            }
+        }
+
+        self.heard = events.len();
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::output::NullOutput,
+        chart::{
+            FxSample,
+            GraphPoint,
+            GraphValue,
+            Interval,
+            LaserSection,
+        },
+        environment::key_bindings::BindRoles,
+        song_player::{
+            governor::SONG_TIMER_LOCK,
+            session::{
+                PlayInput,
+                PlaySettings,
+            },
+        },
+    };
+
+    const FREQ: u32 = 48_000;
+
+    // FX chips at one and two seconds, with a clap at half and at full volume,
+    // and a slam to the right at three seconds
+    fn chart() -> Chart {
+        let chip = |tick| {
+            Interval {
+                tick,
+                length: 0,
+            }
+        };
+        let clap = |volume| {
+            FxSample {
+                sound: FxSound::Clap,
+                volume,
+            }
+        };
+
+        let mut chart = Chart::default();
+        chart.bpm_changes = vec![(0, 120.)];
+        chart.fx[0] = vec![chip(480), chip(960)];
+        chart.fx_samples[0] = vec![(480, clap(50)), (960, clap(100))];
+        chart.lasers[0] = vec![LaserSection {
+            tick:   1440,
+            points: vec![GraphPoint {
+                tick:  0,
+                value: GraphValue {
+                    v:  0.,
+                    vf: 1.,
+                },
+            }],
+            wide:   false,
+        }];
+        chart.meta.slam_volume = 40;
+
+        chart
+    }
+
+    fn at(seconds: f64) -> SongTime {
+        SongTime::from_seconds(seconds, FREQ)
+    }
+
+    // the samples of the sample as the output takes them
+    fn played(
+        sample: &Sample,
+        volume: f32,
+    ) -> Vec<f32>
+    {
+        (0 .. sample.len())
+            .flat_map(|idx| {
+                let [left, right] = sample.frame(idx);
+                vec![left * volume, right * volume]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_the_samples_of_whatever_is_hit() {
+        let _lock = SONG_TIMER_LOCK.lock();
+
+        let chart = chart();
+        let tempo_map = TempoMap::from_chart(&chart, FREQ);
+        let output = NullOutput::new();
+        let mut audio = SongAudio::new(
+            &chart,
+            Path::new("."),
+            &tempo_map,
+            Music::silence(4., FREQ),
+            SongTime(0),
+            Box::new(output.clone()),
+        );
+        audio.player().play();
+
+        let mut session =
+            SongSession::new(chart.clone(), tempo_map, PlaySettings::default());
+        let clap = Sample::from_sound(&FxSound::Clap, ".", FREQ).unwrap();
+        let slam = Sample::slam(FREQ);
+
+        // the first chip plays its clap at its own volume, and only once
+        // however many times the audio is synced
+        session.input(PlayInput::Press(BindRoles::FX_L), at(1.));
+        session.input(PlayInput::Release(BindRoles::FX_L), at(1.1));
+        audio.sync(&session);
+        audio.sync(&session);
+
+        assert_eq!(output.pull(clap.len()), played(&clap, 0.5));
+        audio.sync(&session);
+        assert!(output.pull(100).iter().all(|sample| *sample == 0.));
+
+        // the second one is missed, so it plays nothing
+        session.update(at(2.5));
+        assert_eq!(session.events().len(), 2);
+        audio.sync(&session);
+        assert!(output.pull(clap.len()).iter().all(|sample| *sample == 0.));
+
+        // and the slam is heard at the volume of the chart
+        session.input(PlayInput::Knob(0, 0.5), at(3.));
+        audio.sync(&session);
+        assert_eq!(output.pull(slam.len()), played(&slam, 0.4));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::audio::{
+    music::Music,
+    Frame,
+};
+use std::f64::consts::PI;
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the length of the grains the music is stretched with
+const GRAIN_MS: f64 = 40.;
+
+// how far a grain may start from the song to line up with the other grain,
+// and how much of them is compared to find where they do
+const SEEK_MS: f64 = 8.;
+const OVERLAP_MS: f64 = 5.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Slows the music down without lowering its pitch.
+///
+/// The music is read in grains at its own speed, each one starting from where
+/// the song is when the grain starts. There are always two grains playing, each
+/// fading in as the other one fades out, and every grain starts wherever near
+/// the song it best lines up with the other one so they don't cancel out.
+pub struct TimeStretch {
+    length:  usize,
+    seek:    usize,
+    overlap: usize,
+
+    // where each grain started in the music, and how far it has played. they
+    // start along with the first frame.
+    grains: Option<[Grain; 2]>,
+
+    // what the other grain is about to play, kept around so the output doesn't
+    // allocate
+    ahead: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Grain {
+    start: f64,
+    age:   usize,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl TimeStretch {
+    pub fn new(sample_rate: u32) -> TimeStretch {
+        let frames = |ms: f64| (ms / 1000. * sample_rate as f64) as usize;
+
+        TimeStretch {
+            length:  frames(GRAIN_MS).max(2),
+            seek:    frames(SEEK_MS),
+            overlap: frames(OVERLAP_MS).max(1),
+            grains:  None,
+            ahead:   vec![],
+        }
+    }
+
+    /// Forgets the grains, for when the song has jumped elsewhere
+    pub fn reset(&mut self) {
+        self.grains = None;
+    }
+
+    /// Returns the next frame, with the song at the given position
+    pub fn frame(
+        &mut self,
+        music: &Music,
+        position: f64,
+    ) -> Frame
+    {
+        let length = self.length;
+
+        // the grains are half of a grain apart, both reading from the position
+        let grains = self.grains.get_or_insert_with(|| {
+            [
+                Grain {
+                    start: position,
+                    age:   0,
+                },
+                Grain {
+                    start: position - (length / 2) as f64,
+                    age:   length / 2,
+                },
+            ]
+        });
+
+        for idx in 0 .. 2 {
+            if length <= grains[idx].age {
+                let other = grains[1 - idx];
+                let other_position = other.start + other.age as f64;
+
+                grains[idx] = Grain {
+                    start: best_start(
+                        music,
+                        position,
+                        other_position,
+                        self.seek,
+                        self.overlap,
+                        &mut self.ahead,
+                    ),
+                    age:   0,
+                };
+            }
+        }
+
+        let mut out = [0.; 2];
+
+        for grain in grains.iter_mut() {
+            // the windows of the two grains add up to one
+            let phase = grain.age as f64 / length as f64;
+            let gain = (PI * phase).sin().powi(2) as f32;
+
+            let [left, right] = music.frame_at(grain.start + grain.age as f64);
+            out[0] += left * gain;
+            out[1] += right * gain;
+
+            grain.age += 1;
+        }
+
+        out
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns where near the position the music is most like it is from where the
+/// other grain is, so a grain starting there fades in without cancelling it out
+fn best_start(
+    music: &Music,
+    position: f64,
+    other: f64,
+    seek: usize,
+    overlap: usize,
+    ahead: &mut Vec<f32>,
+) -> f64
+{
+    let mono = |at: f64| {
+        let [left, right] = music.frame_at(at);
+        left + right
+    };
+
+    ahead.clear();
+    ahead.extend((0 .. overlap).map(|idx| mono(other + idx as f64)));
+
+    let mut best = position;
+    let mut best_score = std::f32::MIN;
+
+    for offset in 0 ..= seek * 2 {
+        let start = position + offset as f64 - seek as f64;
+
+        let score: f32 = ahead
+            .iter()
+            .enumerate()
+            .map(|(idx, sample)| sample * mono(start + idx as f64))
+            .sum();
+
+        if best_score < score {
+            best = start;
+            best_score = score;
+        }
+    }
+
+    best
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // at a thousand frames a second, the milliseconds are frames
+    const SAMPLE_RATE: u32 = 1000;
+
+    fn stretched(music: &Music) -> Vec<(f64, Frame)> {
+        let mut stretch = TimeStretch::new(SAMPLE_RATE);
+
+        // the song at half its speed
+        (0 .. 2000)
+            .map(|idx| {
+                let position = 1000. + idx as f64 * 0.5;
+                (position, stretch.frame(music, position))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_loudness_of_the_music() {
+        let music = Music::from_samples(vec![0.5; 8000], SAMPLE_RATE);
+
+        for (position, [left, right]) in stretched(&music) {
+            assert!((left - 0.5).abs() < 1e-5, "at {}", position);
+            assert!((right - 0.5).abs() < 1e-5, "at {}", position);
+        }
+    }
+
+    #[test]
+    fn keeps_up_with_the_song() {
+        // every frame is its own index
+        let music = Music::from_samples(
+            (0 .. 4000).flat_map(|idx| vec![idx as f32; 2]).collect(),
+            SAMPLE_RATE,
+        );
+        let frames = stretched(&music);
+
+        // it starts right where the song is, and never strays further than a
+        // grain and its seek from there
+        assert_eq!(frames[0].1, [1000.; 2]);
+
+        for (position, [left, _]) in frames {
+            let reach = GRAIN_MS + SEEK_MS;
+            assert!((left as f64 - position).abs() <= reach, "at {}", position);
+        }
+    }
+}
@@ -0,0 +1,1195 @@
+use crate::chart::{
+    read_chart_text,
+    Chart,
+    ChartLoadError,
+    FxEffect,
+    FxSample,
+    FxSound,
+    GraphPoint,
+    GraphValue,
+    Interval,
+    LaserFilter,
+    LaserSection,
+    SpinDirection,
+    SpinEvent,
+    SpinKind,
+    Tick,
+    TiltMode,
+    TimeSignature,
+    TICKS_PER_WHOLE,
+};
+use std::{
+    fmt,
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// two laser points at most this far apart from each other form a slam
+const SLAM_THRESHOLD: Tick = TICKS_PER_WHOLE / 32;
+
+// spin and stop lengths are written in 1/192ths of a whole note
+const LENGTH_UNIT: Tick = TICKS_PER_WHOLE / 192;
+
+// the number of distinct laser positions, from `0` to `o`
+const LASER_POSITIONS: u32 = 51;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// An error while parsing a KSH chart, with the location of the offending line
+#[derive(Debug, Clone, PartialEq)]
+pub struct KshError {
+    // both are 1-based. the header is considered to be measure 0.
+    pub line:    usize,
+    pub measure: usize,
+    pub kind:    KshErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KshErrorKind {
+    MalformedLine(String),
+    InvalidValue {
+        key:   String,
+        value: String,
+    },
+    InvalidNoteChar {
+        column: &'static str,
+        found:  char,
+    },
+    MisplacedTimeSignature,
+    MissingBpm,
+}
+
+impl fmt::Display for KshError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        write!(
+            f,
+            "line {} (measure {}): {}",
+            self.line, self.measure, self.kind
+        )
+    }
+}
+
+impl fmt::Display for KshErrorKind {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        use KshErrorKind::*;
+
+        match self {
+            MalformedLine(line) => write!(f, "malformed line `{}`", line),
+            InvalidValue {
+                key,
+                value,
+            } => write!(f, "invalid value `{}` for `{}`", value, key),
+            InvalidNoteChar {
+                column,
+                found,
+            } => {
+                write!(
+                    f,
+                    "invalid character `{}` in the {} column",
+                    found, column
+                )
+            },
+            MisplacedTimeSignature => {
+                write!(f, "`beat` can only be set at the start of a measure")
+            },
+            MissingBpm => write!(f, "the chart does not declare a BPM"),
+        }
+    }
+}
+
+impl std::error::Error for KshError {
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reads and parses the KSH chart at the given path
+pub fn load<P>(path: P) -> Result<Chart, ChartLoadError>
+where P: AsRef<Path> {
+    let text = read_chart_text(path)?;
+    Ok(parse(&text)?)
+}
+
+/// Parses the contents of a KSH chart
+pub fn parse(source: &str) -> Result<Chart, KshError> {
+    let mut parser = Parser::default();
+    let mut lines = source
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim_end()));
+
+    // the header lasts until the first measure separator
+    for (line_no, line) in lines.by_ref() {
+        if line.starts_with("--") {
+            break;
+        }
+
+        parser.parse_header_line(line).map_err(|kind| {
+            KshError {
+                line: line_no,
+                measure: 0,
+                kind,
+            }
+        })?;
+    }
+
+    // then collect the lines of every measure before parsing them, since the
+    // position of a line depends on how many lines there are in the measure
+    let mut measure_lines = vec![];
+    for (line_no, line) in lines {
+        if line.starts_with("--") {
+            parser.parse_measure(&measure_lines)?;
+            measure_lines.clear();
+        }
+        else {
+            measure_lines.push((line_no, line));
+        }
+    }
+
+    // whatever is left after the last separator is only a measure if it has
+    // notes in it. usually, it's just the custom effect definitions.
+    if measure_lines.iter().any(|(_, line)| is_note_line(line)) {
+        parser.parse_measure(&measure_lines)?;
+    }
+
+    parser.finish()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Default)]
+struct Parser {
+    chart: Chart,
+
+    // the current position of the parser
+    measure:       u32,
+    measure_start: Tick,
+    signature:     TimeSignature,
+
+    // the ticks where the currently held notes started
+    bt_holds: [Option<Tick>; 4],
+    fx_holds: [Option<Tick>; 2],
+
+    // the effects set for the FX holds starting at the tick
+    fx_effect_options: [Option<(Tick, FxEffect)>; 2],
+
+    // the samples set for the FX chips at the tick
+    fx_sample_options: [Option<(Tick, FxSample)>; 2],
+
+    lasers:      [LaserBuilder; 2],
+    wide_lasers: [bool; 2],
+}
+
+#[derive(Default)]
+struct LaserBuilder {
+    section: Option<LaserSection>,
+
+    // whether a `:` has been met since the last point
+    connected: bool,
+}
+
+impl Parser {
+    fn parse_header_line(
+        &mut self,
+        line: &str,
+    ) -> Result<(), KshErrorKind>
+    {
+        let (key, value) = match split_option(line) {
+            Some(kv) => kv,
+            None => return Ok(()),
+        };
+
+        let meta = &mut self.chart.meta;
+
+        match key {
+            "title" => meta.title = value.to_owned(),
+            "artist" => meta.artist = value.to_owned(),
+            "effect" => meta.effector = value.to_owned(),
+            "jacket" => meta.jacket = value.to_owned(),
+            "illustrator" => meta.illustrator = value.to_owned(),
+
+            "difficulty" => {
+                meta.difficulty = match value {
+                    "light" => 0,
+                    "challenge" => 1,
+                    "extended" => 2,
+                    "infinite" => 3,
+                    _ => return Err(invalid_value(key, value)),
+                }
+            },
+
+            "level" => meta.level = parse_value(key, value)?,
+
+            // the header BPM may be a range (e.g. `120-240`), which is only
+            // meant to be displayed. the real BPM is then in the first measure.
+            "t" => {
+                meta.display_bpm = value.to_owned();
+
+                if let Ok(bpm) = value.parse::<f64>() {
+                    self.set_bpm(0, bpm, key, value)?;
+                }
+            },
+
+            // the music may be followed by an alternative file, separated by a
+            // semicolon. we only want the first.
+            "m" => {
+                meta.music_file =
+                    value.split(';').next().unwrap_or("").to_owned()
+            },
+
+            "mvol" => meta.music_volume = parse_value(key, value)?,
+            "o" => meta.offset_ms = parse_value(key, value)?,
+            "chokkakuvol" => meta.slam_volume = parse_value(key, value)?,
+            "beat" => self.signature = parse_signature(key, value)?,
+
+            // the laser filters and the tilt may also be set from the start in
+            // the header
+            "filtertype" | "pfiltergain" | "tilt" => {
+                self.parse_option(0, key, value)?
+            },
+
+            // everything else is of no concern to us yet
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    fn parse_measure(
+        &mut self,
+        lines: &[(usize, &str)],
+    ) -> Result<(), KshError>
+    {
+        let measure = self.measure;
+        let at_line = |line: usize| {
+            move |kind| {
+                KshError {
+                    line,
+                    measure: measure as usize + 1,
+                    kind,
+                }
+            }
+        };
+
+        // the time signature must be set before any of the notes of the
+        // measure
+        let mut has_notes = false;
+        for &(line_no, line) in lines.iter() {
+            if is_note_line(line) {
+                has_notes = true;
+            }
+            else if let Some(("beat", value)) = split_option(line) {
+                if has_notes {
+                    return Err(at_line(line_no)(
+                        KshErrorKind::MisplacedTimeSignature,
+                    ));
+                }
+
+                self.signature =
+                    parse_signature("beat", value).map_err(at_line(line_no))?;
+            }
+        }
+
+        let last_signature = self
+            .chart
+            .time_signatures
+            .last()
+            .map(|(_, sig)| *sig)
+            .unwrap_or_default();
+        if self.chart.time_signatures.is_empty() ||
+            last_signature != self.signature
+        {
+            self.chart.time_signatures.push((measure, self.signature));
+        }
+
+        // divide the measure evenly between all of its note lines
+        let measure_length = self.signature.measure_length();
+        let note_count = lines.iter().filter(|(_, l)| is_note_line(l)).count();
+        let note_count = note_count.max(1) as Tick;
+
+        let mut note_idx = 0;
+        for &(line_no, line) in lines.iter() {
+            let tick =
+                self.measure_start + note_idx * measure_length / note_count;
+
+            if line.is_empty() ||
+                line.starts_with("//") ||
+                line.starts_with('#')
+            {
+                continue;
+            }
+            else if is_note_line(line) {
+                self.parse_note_line(tick, line).map_err(at_line(line_no))?;
+                note_idx += 1;
+            }
+            else if let Some((key, value)) = split_option(line) {
+                self.parse_option(tick, key, value)
+                    .map_err(at_line(line_no))?;
+            }
+            else {
+                return Err(at_line(line_no)(KshErrorKind::MalformedLine(
+                    line.to_owned(),
+                )));
+            }
+        }
+
+        self.measure += 1;
+        self.measure_start += measure_length;
+
+        Ok(())
+    }
+
+    fn parse_option(
+        &mut self,
+        tick: Tick,
+        key: &str,
+        value: &str,
+    ) -> Result<(), KshErrorKind>
+    {
+        match key {
+            "t" => {
+                let bpm = parse_value(key, value)?;
+                self.set_bpm(tick, bpm, key, value)?;
+            },
+
+            "stop" => {
+                let length: Tick = parse_value(key, value)?;
+                self.chart.stops.push((tick, length * LENGTH_UNIT));
+            },
+
+            "zoom_top" => {
+                push_graph_value(
+                    &mut self.chart.zoom_top,
+                    tick,
+                    parse_value(key, value)?,
+                )
+            },
+
+            "zoom_bottom" => {
+                push_graph_value(
+                    &mut self.chart.zoom_bottom,
+                    tick,
+                    parse_value(key, value)?,
+                )
+            },
+
+            "zoom_side" => {
+                push_graph_value(
+                    &mut self.chart.zoom_side,
+                    tick,
+                    parse_value(key, value)?,
+                )
+            },
+
+            "center_split" => {
+                push_graph_value(
+                    &mut self.chart.center_split,
+                    tick,
+                    parse_value(key, value)?,
+                )
+            },
+
+            "lane_toggle" => {
+                let length: Tick = parse_value(key, value)?;
+                self.chart.lane_toggles.push((tick, length * LENGTH_UNIT));
+            },
+
+            // a value switches to the manual tilt, which is a graph
+            "tilt" => {
+                if let Ok(tilt) = value.parse() {
+                    let modes = &mut self.chart.tilt_modes;
+                    if modes.last().map(|(_, m)| *m) != Some(TiltMode::Manual) {
+                        modes.push((tick, TiltMode::Manual));
+                    }
+
+                    push_graph_value(&mut self.chart.tilt, tick, tilt);
+                }
+                else if let Some(mode) = TiltMode::from_name(value) {
+                    self.chart.tilt_modes.push((tick, mode));
+                }
+            },
+
+            "fx-l" | "fx-r" => {
+                let lane = if key == "fx-l" { 0 } else { 1 };
+
+                // the custom effects defined at the end of the chart aren't
+                // supported, so they're left to the legacy effects
+                let effect = parse_fx_effect(value);
+                self.fx_effect_options[lane] = effect.map(|e| (tick, e));
+            },
+
+            // the custom filters defined at the end of the chart aren't
+            // supported, so the filter stays as it was
+            "filtertype" => {
+                if let Some(filter) = LaserFilter::from_name(value) {
+                    // only the last filter set on a tick is ever heard
+                    let filters = &mut self.chart.laser_filters;
+                    match filters.last_mut() {
+                        Some(last) if last.0 == tick => last.1 = filter,
+                        _ => filters.push((tick, filter)),
+                    }
+                }
+            },
+
+            "pfiltergain" => {
+                let gain: u8 = parse_value(key, value)?;
+                self.chart.laser_filter_gain.push((tick, gain.min(100)));
+            },
+
+            "fx-l_se" | "fx-r_se" => {
+                let lane = if key == "fx-l_se" { 0 } else { 1 };
+                let sample = parse_fx_sample(key, value)?;
+                self.fx_sample_options[lane] = Some((tick, sample));
+            },
+
+            "laserrange_l" => {
+                self.wide_lasers[0] = parse_laser_range(key, value)?
+            },
+
+            "laserrange_r" => {
+                self.wide_lasers[1] = parse_laser_range(key, value)?
+            },
+
+            // the time signature has already been handled
+            "beat" => {},
+
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    fn parse_note_line(
+        &mut self,
+        tick: Tick,
+        line: &str,
+    ) -> Result<(), KshErrorKind>
+    {
+        let malformed = || KshErrorKind::MalformedLine(line.to_owned());
+
+        let mut columns = line.splitn(3, '|');
+        let bt = columns.next().ok_or_else(malformed)?;
+        let fx = columns.next().ok_or_else(malformed)?;
+        let rest = columns.next().ok_or_else(malformed)?;
+
+        // the lasers are followed by the optional spin annotations
+        let laser_len = rest.char_indices().nth(2).map(|(i, _)| i);
+        let (laser, spin) = match laser_len {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, ""),
+        };
+
+        if bt.chars().count() != 4 ||
+            fx.chars().count() != 2 ||
+            laser.chars().count() != 2
+        {
+            return Err(malformed());
+        }
+
+        for (lane, c) in bt.chars().enumerate() {
+            self.parse_bt(tick, lane, c)?;
+        }
+
+        for (lane, c) in fx.chars().enumerate() {
+            self.parse_fx(tick, lane, c)?;
+        }
+
+        for (side, c) in laser.chars().enumerate() {
+            self.parse_laser(tick, side, c)?;
+        }
+
+        if !spin.is_empty() {
+            self.parse_spin(tick, spin)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_bt(
+        &mut self,
+        tick: Tick,
+        lane: usize,
+        c: char,
+    ) -> Result<(), KshErrorKind>
+    {
+        match c {
+            '0' => self.end_bt_hold(tick, lane),
+
+            '1' => {
+                self.end_bt_hold(tick, lane);
+                self.chart.bt[lane].push(Interval {
+                    tick,
+                    length: 0,
+                });
+            },
+
+            '2' => {
+                self.bt_holds[lane].get_or_insert(tick);
+            },
+
+            found => {
+                return Err(KshErrorKind::InvalidNoteChar {
+                    column: "BT",
+                    found,
+                })
+            },
+        }
+
+        Ok(())
+    }
+
+    fn parse_fx(
+        &mut self,
+        tick: Tick,
+        lane: usize,
+        c: char,
+    ) -> Result<(), KshErrorKind>
+    {
+        match c {
+            '0' => self.end_fx_hold(tick, lane),
+
+            '2' => {
+                self.end_fx_hold(tick, lane);
+                self.chart.fx[lane].push(Interval {
+                    tick,
+                    length: 0,
+                });
+
+                // a chip only has a sample if it's set right along with it
+                match self.fx_sample_options[lane].take() {
+                    Some((at, sample)) if at == tick => {
+                        self.chart.fx_samples[lane].push((tick, sample))
+                    },
+                    _ => {},
+                }
+            },
+
+            // other than `1`, holds may be written with the letters of the
+            // legacy effects
+            c if c == '1' || c.is_ascii_alphabetic() => {
+                if self.fx_holds[lane].is_none() {
+                    self.fx_holds[lane] = Some(tick);
+                    self.start_fx_effect(tick, lane, c);
+                }
+            },
+
+            found => {
+                return Err(KshErrorKind::InvalidNoteChar {
+                    column: "FX",
+                    found,
+                })
+            },
+        }
+
+        Ok(())
+    }
+
+    fn parse_laser(
+        &mut self,
+        tick: Tick,
+        side: usize,
+        c: char,
+    ) -> Result<(), KshErrorKind>
+    {
+        match c {
+            '-' => self.end_laser(side),
+
+            ':' => {
+                if self.lasers[side].section.is_none() {
+                    return Err(KshErrorKind::InvalidNoteChar {
+                        column: "laser",
+                        found:  c,
+                    });
+                }
+
+                self.lasers[side].connected = true;
+            },
+
+            c => {
+                let value = match laser_position(c) {
+                    Some(v) => v,
+                    None => {
+                        return Err(KshErrorKind::InvalidNoteChar {
+                            column: "laser",
+                            found:  c,
+                        })
+                    },
+                };
+
+                let wide = self.wide_lasers[side];
+                let builder = &mut self.lasers[side];
+                let connected = builder.connected;
+                builder.connected = false;
+
+                let section = builder.section.get_or_insert_with(|| {
+                    LaserSection {
+                        tick,
+                        points: vec![],
+                        wide,
+                    }
+                });
+
+                let relative_tick = tick - section.tick;
+
+                match section.points.last_mut() {
+                    // two points right next to each other turn into a slam
+                    Some(last)
+                        if !connected &&
+                            relative_tick - last.tick <= SLAM_THRESHOLD =>
+                    {
+                        last.value.vf = value;
+                    },
+
+                    _ => {
+                        section.points.push(GraphPoint {
+                            tick:  relative_tick,
+                            value: GraphValue::new(value),
+                        })
+                    },
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn parse_spin(
+        &mut self,
+        tick: Tick,
+        spin: &str,
+    ) -> Result<(), KshErrorKind>
+    {
+        use SpinDirection::*;
+        use SpinKind::*;
+
+        let invalid = || invalid_value("spin", spin);
+
+        let (kind, direction) = match spin.get(.. 2) {
+            Some("@(") => (Full, Left),
+            Some("@)") => (Full, Right),
+            Some("@<") => (Half, Left),
+            Some("@>") => (Half, Right),
+            Some("S<") => (Sway, Left),
+            Some("S>") => (Sway, Right),
+            _ => return Err(invalid()),
+        };
+
+        // swings carry more parameters after the length, which we ignore
+        let length = spin[2 ..]
+            .split(';')
+            .next()
+            .and_then(|l| l.parse::<Tick>().ok())
+            .ok_or_else(invalid)?;
+
+        self.chart.spins.push(SpinEvent {
+            tick,
+            kind,
+            direction,
+            length: length * LENGTH_UNIT,
+        });
+
+        Ok(())
+    }
+
+    fn set_bpm(
+        &mut self,
+        tick: Tick,
+        bpm: f64,
+        key: &str,
+        value: &str,
+    ) -> Result<(), KshErrorKind>
+    {
+        if !(bpm > 0.) {
+            return Err(invalid_value(key, value));
+        }
+
+        let bpm_changes = &mut self.chart.bpm_changes;
+
+        match bpm_changes.last_mut() {
+            Some(last) if last.0 == tick => last.1 = bpm,
+            _ => bpm_changes.push((tick, bpm)),
+        }
+
+        Ok(())
+    }
+
+    fn end_bt_hold(
+        &mut self,
+        tick: Tick,
+        lane: usize,
+    )
+    {
+        if let Some(start) = self.bt_holds[lane].take() {
+            self.chart.bt[lane].push(Interval {
+                tick:   start,
+                length: tick - start,
+            });
+        }
+    }
+
+    fn end_fx_hold(
+        &mut self,
+        tick: Tick,
+        lane: usize,
+    )
+    {
+        if let Some(start) = self.fx_holds[lane].take() {
+            self.chart.fx[lane].push(Interval {
+                tick:   start,
+                length: tick - start,
+            });
+        }
+    }
+
+    /// Sets the effect of the FX hold starting at the tick. The effect set by
+    /// the options takes over the one of the letter the hold is written with.
+    fn start_fx_effect(
+        &mut self,
+        tick: Tick,
+        lane: usize,
+        c: char,
+    )
+    {
+        let option = match self.fx_effect_options[lane] {
+            Some((at, effect)) if at == tick => Some(effect),
+            _ => None,
+        };
+
+        if let Some(effect) = option.or_else(|| legacy_fx_effect(c)) {
+            self.chart.fx_effects[lane].push((tick, effect));
+        }
+    }
+
+    fn end_laser(
+        &mut self,
+        side: usize,
+    )
+    {
+        let builder = &mut self.lasers[side];
+        builder.connected = false;
+
+        if let Some(section) = builder.section.take() {
+            self.chart.lasers[side].push(section);
+        }
+    }
+
+    fn finish(mut self) -> Result<Chart, KshError> {
+        // close whatever is still open at the end of the chart
+        let end = self.measure_start;
+
+        for lane in 0 .. 4 {
+            self.end_bt_hold(end, lane);
+        }
+
+        for lane in 0 .. 2 {
+            self.end_fx_hold(end, lane);
+            self.end_laser(lane);
+        }
+
+        if self.chart.bpm_changes.first().map(|b| b.0) != Some(0) {
+            return Err(KshError {
+                line:    0,
+                measure: 0,
+                kind:    KshErrorKind::MissingBpm,
+            });
+        }
+
+        Ok(self.chart)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn is_note_line(line: &str) -> bool {
+    !line.starts_with("//") &&
+        !line.starts_with('#') &&
+        line.contains('|') &&
+        !line.contains('=')
+}
+
+/// Parses an effect in the form of `Echo;4;60`
+fn parse_fx_effect(value: &str) -> Option<FxEffect> {
+    let mut parts = value.split(';');
+    let name = parts.next()?.trim();
+    let params = parts
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<Vec<i64>>>()?;
+
+    FxEffect::from_parts(name, &params)
+}
+
+/// Returns the effect of the letter an FX hold is written with, from before the
+/// effects could be set by the options
+fn legacy_fx_effect(c: char) -> Option<FxEffect> {
+    use FxEffect::*;
+
+    let effect = match c {
+        'S' => Retrigger(8),
+        'V' => Retrigger(12),
+        'T' => Retrigger(16),
+        'W' => Retrigger(24),
+        'U' => Retrigger(32),
+        'G' => Gate(4),
+        'H' => Gate(8),
+        'K' => Gate(12),
+        'I' => Gate(16),
+        'L' => Gate(24),
+        'J' => Gate(32),
+        'F' => Flanger,
+        'P' => PitchShift(12),
+        'B' => BitCrusher(5),
+        'Q' => Phaser,
+        'X' => Wobble(12),
+        'A' => TapeStop(50),
+        'D' => SideChain,
+        _ => return None,
+    };
+
+    Some(effect)
+}
+
+/// Parses a sample in the form of `clap;60`, where the volume is optional
+fn parse_fx_sample(
+    key: &str,
+    value: &str,
+) -> Result<FxSample, KshErrorKind>
+{
+    let mut parts = value.splitn(2, ';');
+    let name = parts.next().unwrap_or("").trim();
+
+    if name.is_empty() {
+        return Err(invalid_value(key, value));
+    }
+
+    let volume = match parts.next() {
+        Some(volume) => parse_value::<u8>(key, volume.trim())?.min(100),
+        None => 100,
+    };
+
+    Ok(FxSample {
+        sound: FxSound::from_name(name),
+        volume,
+    })
+}
+
+fn split_option(line: &str) -> Option<(&str, &str)> {
+    let mut split = line.splitn(2, '=');
+    let key = split.next()?;
+    let value = split.next()?;
+
+    Some((key.trim(), value.trim()))
+}
+
+fn invalid_value(
+    key: &str,
+    value: &str,
+) -> KshErrorKind
+{
+    KshErrorKind::InvalidValue {
+        key:   key.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+fn parse_value<T>(
+    key: &str,
+    value: &str,
+) -> Result<T, KshErrorKind>
+where
+    T: std::str::FromStr,
+{
+    value.parse().map_err(|_| invalid_value(key, value))
+}
+
+fn parse_signature(
+    key: &str,
+    value: &str,
+) -> Result<TimeSignature, KshErrorKind>
+{
+    let mut split = value.splitn(2, '/');
+    let numerator = split.next().and_then(|n| n.parse().ok());
+    let denominator = split.next().and_then(|d| d.parse().ok());
+
+    match (numerator, denominator) {
+        (Some(numerator), Some(denominator))
+            if numerator > 0 && denominator > 0 =>
+        {
+            Ok(TimeSignature {
+                numerator,
+                denominator,
+            })
+        },
+
+        _ => Err(invalid_value(key, value)),
+    }
+}
+
+fn parse_laser_range(
+    key: &str,
+    value: &str,
+) -> Result<bool, KshErrorKind>
+{
+    match value {
+        "1x" => Ok(false),
+        "2x" => Ok(true),
+        _ => Err(invalid_value(key, value)),
+    }
+}
+
+/// Maps the laser characters `0-9`, `A-Z` and `a-o` into [0, 1]
+fn laser_position(c: char) -> Option<f64> {
+    let idx = match c {
+        '0' ..= '9' => c as u32 - '0' as u32,
+        'A' ..= 'Z' => c as u32 - 'A' as u32 + 10,
+        'a' ..= 'o' => c as u32 - 'a' as u32 + 36,
+        _ => return None,
+    };
+
+    Some(idx as f64 / (LASER_POSITIONS - 1) as f64)
+}
+
+fn push_graph_value(
+    graph: &mut Vec<GraphPoint>,
+    tick: Tick,
+    value: f64,
+)
+{
+    match graph.last_mut() {
+        // a second value on the same tick is an instant jump
+        Some(last) if last.tick == tick => last.value.vf = value,
+
+        _ => {
+            graph.push(GraphPoint {
+                tick,
+                value: GraphValue::new(value),
+            })
+        },
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a measure of the given note lines, with the lines at the given indices
+    // replaced
+    fn measure(
+        lines: usize,
+        replaced: &[(usize, &str)],
+    ) -> String
+    {
+        let mut measure = vec!["0000|00|--"; lines];
+        for &(idx, line) in replaced.iter() {
+            measure[idx] = line;
+        }
+
+        measure.join("\n") + "\n--\n"
+    }
+
+    #[test]
+    fn parses_chips_and_holds() {
+        let chart = parse(
+            "t=120\n--\n1000|00|--\n0200|10|--\n0200|12|--\n0000|00|--\n--\n",
+        )
+        .unwrap();
+
+        let chip = |tick| {
+            Interval {
+                tick,
+                length: 0,
+            }
+        };
+
+        assert_eq!(chart.bt[0], vec![chip(0)]);
+        assert_eq!(chart.bt[1], vec![Interval {
+            tick:   240,
+            length: 480,
+        }]);
+        assert!(chart.bt[2].is_empty() && chart.bt[3].is_empty());
+
+        assert_eq!(chart.fx[0], vec![Interval {
+            tick:   240,
+            length: 480,
+        }]);
+        assert_eq!(chart.fx[1], vec![chip(480)]);
+    }
+
+    #[test]
+    fn parses_fx_effects() {
+        use FxEffect::*;
+
+        assert_eq!(parse_fx_effect("Retrigger;8"), Some(Retrigger(8)));
+        assert_eq!(parse_fx_effect("Retrigger; 16"), Some(Retrigger(16)));
+        assert_eq!(parse_fx_effect("Echo;4;60"), Some(Echo(4, 60)));
+        assert_eq!(parse_fx_effect("Retrigger;0"), None);
+        assert_eq!(parse_fx_effect("Retrigger;eighth"), None);
+        assert_eq!(parse_fx_effect("Reverb;8"), None);
+
+        // the missing parameters are given their defaults
+        assert_eq!(parse_fx_effect("Retrigger"), Some(Retrigger(8)));
+        assert_eq!(parse_fx_effect("Gate"), Some(Gate(4)));
+        assert_eq!(parse_fx_effect("BitCrusher"), Some(BitCrusher(5)));
+        assert_eq!(parse_fx_effect("Wobble"), Some(Wobble(12)));
+        assert_eq!(parse_fx_effect("TapeStop"), Some(TapeStop(50)));
+        assert_eq!(parse_fx_effect("Echo"), Some(Echo(4, 60)));
+        assert_eq!(parse_fx_effect("PitchShift"), Some(PitchShift(12)));
+
+        // the names of KSON are understood too, and the parameters are kept
+        // within their ranges
+        assert_eq!(
+            FxEffect::from_parts("tape_stop", &[150]),
+            Some(TapeStop(100))
+        );
+        assert_eq!(FxEffect::from_parts("echo", &[8, -5]), Some(Echo(8, 0)));
+        assert_eq!(
+            FxEffect::from_parts("pitch_shift", &[-60]),
+            Some(PitchShift(-48))
+        );
+
+        // and the effect is given to the hold it starts with
+        let chart = parse(
+            "t=120\n--\nfx-l=Retrigger;16\n0000|10|--\n0000|00|--\n--\n",
+        )
+        .unwrap();
+        assert_eq!(chart.fx_effects[0], vec![(0, Retrigger(16))]);
+    }
+
+    #[test]
+    fn closes_holds_at_the_end_of_the_chart() {
+        let chart = parse("t=120\n--\n2000|01|--\n2000|01|--\n--\n").unwrap();
+
+        assert_eq!(chart.bt[0], vec![Interval {
+            tick:   0,
+            length: 960,
+        }]);
+        assert_eq!(chart.fx[1], vec![Interval {
+            tick:   0,
+            length: 960,
+        }]);
+    }
+
+    #[test]
+    fn parses_lasers_and_slams() {
+        let left = [(0, "0000|00|0-"), (1, "0000|00|:-"), (2, "0000|00|o-")];
+        let right = [(0, "0000|00|-0"), (1, "0000|00|-o")];
+        let source = String::from("t=120\n--\n") +
+            &measure(4, &left) +
+            &measure(32, &right);
+        let chart = parse(&source).unwrap();
+
+        assert_eq!(chart.lasers[0], vec![LaserSection {
+            tick:   0,
+            points: vec![
+                GraphPoint {
+                    tick:  0,
+                    value: GraphValue::new(0.),
+                },
+                GraphPoint {
+                    tick:  480,
+                    value: GraphValue::new(1.),
+                },
+            ],
+            wide:   false,
+        }]);
+
+        // the two points of the right laser are close enough to be a slam
+        assert_eq!(chart.lasers[1], vec![LaserSection {
+            tick:   960,
+            points: vec![GraphPoint {
+                tick:  0,
+                value: GraphValue {
+                    v:  0.,
+                    vf: 1.,
+                },
+            }],
+            wide:   false,
+        }]);
+    }
+
+    #[test]
+    fn maps_laser_characters_evenly() {
+        assert_eq!(laser_position('0'), Some(0.));
+        assert_eq!(laser_position('P'), Some(0.5));
+        assert_eq!(laser_position('o'), Some(1.));
+        assert_eq!(laser_position('p'), None);
+    }
+
+    #[test]
+    fn parses_bpm_and_beat_changes() {
+        let chart = parse(
+            "t=120\nbeat=4/4\n--\n1000|00|--\n--\nt=180\nbeat=3/4\n\
+             1000|00|--\n--\n1000|00|--\n--\n",
+        )
+        .unwrap();
+
+        assert_eq!(chart.bpm_changes, vec![(0, 120.), (960, 180.)]);
+        assert_eq!(chart.time_signatures, vec![
+            (0, TimeSignature::default()),
+            (1, TimeSignature {
+                numerator:   3,
+                denominator: 4,
+            }),
+        ]);
+
+        // the last measure is only 3/4 long
+        let ticks = chart.bt[0].iter().map(|n| n.tick).collect::<Vec<_>>();
+        assert_eq!(ticks, vec![0, 960, 1680]);
+    }
+
+    #[test]
+    fn takes_the_bpm_of_a_range_from_the_first_measure() {
+        let chart = parse("t=120-240\n--\nt=120\n1000|00|--\n--\n").unwrap();
+
+        assert_eq!(chart.meta.display_bpm, "120-240");
+        assert_eq!(chart.bpm_changes, vec![(0, 120.)]);
+    }
+
+    #[test]
+    fn reports_the_line_and_measure_of_errors() {
+        let error = |source: &str| parse(source).unwrap_err();
+
+        let bad_note = error("t=120\n--\n1000|00|--\n10x0|00|--\n--\n");
+        assert_eq!(bad_note, KshError {
+            line:    4,
+            measure: 1,
+            kind:    KshErrorKind::InvalidNoteChar {
+                column: "BT",
+                found:  'x',
+            },
+        });
+        assert_eq!(
+            bad_note.to_string(),
+            "line 4 (measure 1): invalid character `x` in the BT column"
+        );
+
+        let bad_bpm =
+            error("t=120\n--\n1000|00|--\n--\n0000|00|--\nt=fast\n--\n");
+        assert_eq!((bad_bpm.line, bad_bpm.measure), (6, 2));
+        assert_eq!(bad_bpm.kind, invalid_value("t", "fast"));
+
+        let late_beat = error("t=120\n--\n1000|00|--\nbeat=3/4\n--\n");
+        assert_eq!((late_beat.line, late_beat.measure), (4, 1));
+        assert_eq!(late_beat.kind, KshErrorKind::MisplacedTimeSignature);
+
+        let malformed = error("t=120\n--\n1000|00\n--\n");
+        assert_eq!((malformed.line, malformed.measure), (3, 1));
+
+        // the header is measure 0
+        let bad_header = error("title=x\nt=120\ndifficulty=hard\n--\n");
+        assert_eq!((bad_header.line, bad_header.measure), (3, 0));
+
+        let no_bpm = error("title=x\n--\n1000|00|--\n--\n");
+        assert_eq!(no_bpm.kind, KshErrorKind::MissingBpm);
+    }
+}
@@ -0,0 +1,1147 @@
+use crate::chart::{
+    read_chart_text,
+    Chart,
+    ChartLoadError,
+    FxEffect,
+    FxSample,
+    FxSound,
+    GraphPoint,
+    GraphValue,
+    Interval,
+    LaserFilter,
+    LaserSection,
+    SpinDirection,
+    SpinEvent,
+    SpinKind,
+    Tick,
+    TiltMode,
+    TimeSignature,
+    TICKS_PER_BEAT,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use std::{
+    fmt,
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const KSON_VERSION: &str = "0.7.0";
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum KsonError {
+    Json(serde_json::Error),
+
+    // the path is in the form of `note.bt[0][3]`
+    InvalidValue(String),
+    UnsupportedResolution(u64),
+    MissingBpm,
+}
+
+impl fmt::Display for KsonError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        use KsonError::*;
+
+        match self {
+            Json(e) => write!(f, "malformed JSON: {}", e),
+            InvalidValue(path) => write!(f, "invalid value at `{}`", path),
+            UnsupportedResolution(r) => {
+                write!(f, "unsupported resolution of {} per beat", r)
+            },
+            MissingBpm => write!(f, "the chart does not declare a BPM"),
+        }
+    }
+}
+
+impl std::error::Error for KsonError {
+}
+
+impl From<serde_json::Error> for KsonError {
+    fn from(e: serde_json::Error) -> KsonError {
+        KsonError::Json(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Reads and parses the KSON chart at the given path
+pub fn load<P>(path: P) -> Result<Chart, ChartLoadError>
+where P: AsRef<Path> {
+    let text = read_chart_text(path)?;
+    Ok(parse(&text)?)
+}
+
+/// Writes the chart as KSON into the given path
+pub fn save<P>(
+    chart: &Chart,
+    path: P,
+) -> Result<(), ChartLoadError>
+where
+    P: AsRef<Path>,
+{
+    std::fs::write(path, to_string(chart))?;
+    Ok(())
+}
+
+/// Parses the contents of a KSON chart
+pub fn parse(source: &str) -> Result<Chart, KsonError> {
+    let root: Value = serde_json::from_str(source)?;
+    let mut chart = Chart::default();
+
+    read_meta(&root, &mut chart)?;
+    read_beat(&root, &mut chart)?;
+    read_notes(&root, &mut chart)?;
+    read_audio(&root, &mut chart)?;
+    read_camera(&root, &mut chart)?;
+
+    Ok(chart)
+}
+
+/// Serializes the chart into KSON
+pub fn to_string(chart: &Chart) -> String {
+    to_value(chart).to_string()
+}
+
+/// Serializes the chart into a JSON tree
+pub fn to_value(chart: &Chart) -> Value {
+    let meta = &chart.meta;
+
+    let spins_of = |kind| {
+        chart
+            .spins
+            .iter()
+            .filter(|s| s.kind == kind)
+            .map(|s| json!([s.tick, write_direction(s.direction), s.length]))
+            .collect::<Vec<_>>()
+    };
+
+    json!({
+        "version": KSON_VERSION,
+        "meta": {
+            "title": meta.title,
+            "artist": meta.artist,
+            "chart_author": meta.effector,
+            "jacket_filename": meta.jacket,
+            "jacket_author": meta.illustrator,
+            "difficulty": meta.difficulty,
+            "level": meta.level,
+            "disp_bpm": meta.display_bpm,
+        },
+        "beat": {
+            "bpm": chart
+                .bpm_changes
+                .iter()
+                .map(|(tick, bpm)| json!([tick, bpm]))
+                .collect::<Vec<_>>(),
+            "time_sig": chart
+                .time_signatures
+                .iter()
+                .map(|(idx, sig)| {
+                    json!([idx, [sig.numerator, sig.denominator]])
+                })
+                .collect::<Vec<_>>(),
+            "stop": chart
+                .stops
+                .iter()
+                .map(|(tick, length)| json!([tick, length]))
+                .collect::<Vec<_>>(),
+            "scroll_speed": write_graph(&chart.scroll_speed),
+            "resolution": TICKS_PER_BEAT,
+        },
+        "note": {
+            "bt": chart.bt.iter().map(|l| write_lane(l)).collect::<Vec<_>>(),
+            "fx": chart.fx.iter().map(|l| write_lane(l)).collect::<Vec<_>>(),
+            "laser": chart
+                .lasers
+                .iter()
+                .map(|side| {
+                    side.iter().map(write_laser_section).collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>(),
+        },
+        "audio": {
+            "bgm": {
+                "filename": meta.music_file,
+                "vol": meta.music_volume as f64 / 100.,
+                "offset": meta.offset_ms,
+            },
+            "audio_effect": {
+                "fx": {
+                    "long_event": write_fx_effects(&chart.fx_effects),
+                },
+                "laser": {
+                    "pulse_event": write_laser_filters(&chart.laser_filters),
+                    "filter_gain": chart
+                        .laser_filter_gain
+                        .iter()
+                        .map(|(tick, gain)| json!([tick, *gain as f64 / 100.]))
+                        .collect::<Vec<_>>(),
+                },
+            },
+            "key_sound": {
+                "fx": {
+                    "chip_event": write_fx_samples(&chart.fx_samples),
+                },
+                "laser": {
+                    "vol": [[0, meta.slam_volume as f64 / 100.]],
+                },
+            },
+        },
+        "camera": {
+            "tilt": write_tilt(&chart.tilt, &chart.tilt_modes),
+            "cam": {
+                "body": {
+                    "zoom": write_graph(&chart.zoom_bottom),
+                    "shift_x": write_graph(&chart.zoom_side),
+                    "rotation_x": write_graph(&chart.zoom_top),
+                    "center_split": write_graph(&chart.center_split),
+                },
+                "pattern": {
+                    "laser": {
+                        "slam_event": {
+                            "spin": spins_of(SpinKind::Full),
+                            "half_spin": spins_of(SpinKind::Half),
+                            "swing": spins_of(SpinKind::Sway),
+                        },
+                    },
+                },
+            },
+            "lane_toggle": chart
+                .lane_toggles
+                .iter()
+                .map(|(tick, length)| json!([tick, length]))
+                .collect::<Vec<_>>(),
+        },
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn read_meta(
+    root: &Value,
+    chart: &mut Chart,
+) -> Result<(), KsonError>
+{
+    let meta = &mut chart.meta;
+
+    meta.title = read_string(root, "meta.title")?;
+    meta.artist = read_string(root, "meta.artist")?;
+    meta.effector = read_string(root, "meta.chart_author")?;
+    meta.jacket = read_string(root, "meta.jacket_filename")?;
+    meta.illustrator = read_string(root, "meta.jacket_author")?;
+    meta.display_bpm = read_string(root, "meta.disp_bpm")?;
+
+    if let Some(difficulty) = lookup(root, "meta.difficulty") {
+        meta.difficulty = as_int(difficulty, "meta.difficulty")?;
+    }
+
+    if let Some(level) = lookup(root, "meta.level") {
+        meta.level = as_int(level, "meta.level")?;
+    }
+
+    Ok(())
+}
+
+fn read_beat(
+    root: &Value,
+    chart: &mut Chart,
+) -> Result<(), KsonError>
+{
+    // everything we have is in our own resolution
+    if let Some(resolution) = lookup(root, "beat.resolution") {
+        let resolution: u64 = as_int(resolution, "beat.resolution")?;
+
+        if resolution != TICKS_PER_BEAT as u64 {
+            return Err(KsonError::UnsupportedResolution(resolution));
+        }
+    }
+
+    for (idx, change) in read_array(root, "beat.bpm")?.iter().enumerate() {
+        let path = format!("beat.bpm[{}]", idx);
+        let (tick, bpm) = as_pair(change, &path)?;
+
+        let bpm = bpm.as_f64().filter(|bpm| *bpm > 0.);
+        let bpm = bpm.ok_or_else(|| invalid(&path))?;
+
+        // the changes are in order, each on a tick of its own
+        let tick = as_int(tick, &path)?;
+        let last = chart.bpm_changes.last().map(|(last, _)| *last);
+        if last.map_or(false, |last| tick <= last) {
+            return Err(invalid(&path));
+        }
+
+        chart.bpm_changes.push((tick, bpm));
+    }
+
+    if chart.bpm_changes.first().map(|b| b.0) != Some(0) {
+        return Err(KsonError::MissingBpm);
+    }
+
+    for (idx, change) in read_array(root, "beat.time_sig")?.iter().enumerate() {
+        let path = format!("beat.time_sig[{}]", idx);
+        let (measure, sig) = as_pair(change, &path)?;
+        let (numerator, denominator) = as_pair(sig, &path)?;
+
+        let signature = TimeSignature {
+            numerator:   as_int(numerator, &path)?,
+            denominator: as_int(denominator, &path)?,
+        };
+
+        if signature.numerator == 0 || signature.denominator == 0 {
+            return Err(invalid(&path));
+        }
+
+        // the signatures are in order, each on a measure of its own
+        let measure = as_int(measure, &path)?;
+        let last = chart.time_signatures.last().map(|(last, _)| *last);
+        if last.map_or(false, |last| measure <= last) {
+            return Err(invalid(&path));
+        }
+
+        chart.time_signatures.push((measure, signature));
+    }
+
+    chart.scroll_speed = read_graph(root, "beat.scroll_speed")?;
+
+    for (idx, stop) in read_array(root, "beat.stop")?.iter().enumerate() {
+        let path = format!("beat.stop[{}]", idx);
+        let (tick, length) = as_pair(stop, &path)?;
+
+        chart
+            .stops
+            .push((as_int(tick, &path)?, as_int(length, &path)?));
+    }
+
+    Ok(())
+}
+
+fn read_notes(
+    root: &Value,
+    chart: &mut Chart,
+) -> Result<(), KsonError>
+{
+    for (idx, lane) in read_array(root, "note.bt")?.iter().enumerate().take(4)
+    {
+        chart.bt[idx] = read_lane(lane, &format!("note.bt[{}]", idx))?;
+    }
+
+    for (idx, lane) in read_array(root, "note.fx")?.iter().enumerate().take(2)
+    {
+        chart.fx[idx] = read_lane(lane, &format!("note.fx[{}]", idx))?;
+    }
+
+    let lasers = read_array(root, "note.laser")?;
+    for (side, sections) in lasers.iter().enumerate().take(2) {
+        let path = format!("note.laser[{}]", side);
+        let sections = sections.as_array().ok_or_else(|| invalid(&path))?;
+
+        for (idx, section) in sections.iter().enumerate() {
+            let path = format!("{}[{}]", path, idx);
+            chart.lasers[side].push(read_laser_section(section, &path)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn read_audio(
+    root: &Value,
+    chart: &mut Chart,
+) -> Result<(), KsonError>
+{
+    let meta = &mut chart.meta;
+
+    meta.music_file = read_string(root, "audio.bgm.filename")?;
+
+    if let Some(vol) = lookup(root, "audio.bgm.vol") {
+        meta.music_volume = as_volume(vol, "audio.bgm.vol")?;
+    }
+
+    if let Some(offset) = lookup(root, "audio.bgm.offset") {
+        meta.offset_ms = as_int(offset, "audio.bgm.offset")?;
+    }
+
+    let path = "audio.audio_effect.fx.long_event";
+    if let Some(events) = lookup(root, path) {
+        let events = events.as_object().ok_or_else(|| invalid(path))?;
+
+        for (name, lanes) in events.iter() {
+            let path = format!("{}.{}", path, name);
+            let lanes = lanes.as_array().ok_or_else(|| invalid(&path))?;
+
+            for (lane, events) in lanes.iter().enumerate().take(2) {
+                let path = format!("{}[{}]", path, lane);
+                let events = events.as_array().ok_or_else(|| invalid(&path))?;
+
+                for (idx, event) in events.iter().enumerate() {
+                    let path = format!("{}[{}]", path, idx);
+
+                    // the custom effects aren't supported
+                    if let Some(event) = read_fx_effect(name, event, &path)? {
+                        chart.fx_effects[lane].push(event);
+                    }
+                }
+            }
+        }
+
+        for effects in chart.fx_effects.iter_mut() {
+            effects.sort_by_key(|(tick, _)| *tick);
+        }
+    }
+
+    // the events are the ticks the lasers switch to the filter at
+    let path = "audio.audio_effect.laser.pulse_event";
+    if let Some(events) = lookup(root, path) {
+        let events = events.as_object().ok_or_else(|| invalid(path))?;
+
+        for (name, ticks) in events.iter() {
+            let path = format!("{}.{}", path, name);
+            let ticks = ticks.as_array().ok_or_else(|| invalid(&path))?;
+
+            // the custom filters aren't supported
+            let filter = match LaserFilter::from_name(name) {
+                Some(filter) => filter,
+                None => continue,
+            };
+
+            for (idx, tick) in ticks.iter().enumerate() {
+                let path = format!("{}[{}]", path, idx);
+                chart.laser_filters.push((as_int(tick, &path)?, filter));
+            }
+        }
+
+        // the file can't order the filters of the same tick, so the last one
+        // by name is the one kept
+        let filters = &mut chart.laser_filters;
+        filters.sort_by_key(|(tick, _)| *tick);
+        filters.dedup_by(|next, last| {
+            let is_same_tick = next.0 == last.0;
+            if is_same_tick {
+                *last = *next;
+            }
+
+            is_same_tick
+        });
+    }
+
+    let path = "audio.key_sound.fx.chip_event";
+    if let Some(events) = lookup(root, path) {
+        let events = events.as_object().ok_or_else(|| invalid(path))?;
+
+        for (name, lanes) in events.iter() {
+            let path = format!("{}.{}", path, name);
+            let lanes = lanes.as_array().ok_or_else(|| invalid(&path))?;
+
+            for (lane, events) in lanes.iter().enumerate().take(2) {
+                let path = format!("{}[{}]", path, lane);
+                let events = events.as_array().ok_or_else(|| invalid(&path))?;
+
+                for (idx, event) in events.iter().enumerate() {
+                    let path = format!("{}[{}]", path, idx);
+                    let event = read_fx_sample(name, event, &path)?;
+                    chart.fx_samples[lane].push(event);
+                }
+            }
+        }
+
+        for samples in chart.fx_samples.iter_mut() {
+            samples.sort_by_key(|(tick, _)| *tick);
+        }
+    }
+
+    // the volume of the slams can't change through the chart, so only the
+    // first one is taken
+    let path = "audio.key_sound.laser.vol";
+    if let Some(change) = read_array(root, path)?.first() {
+        let (_, vol) = as_pair(change, path)?;
+        chart.meta.slam_volume = as_volume(vol, path)?;
+    }
+
+    let path = "audio.audio_effect.laser.filter_gain";
+    for (idx, change) in read_array(root, path)?.iter().enumerate() {
+        let path = format!("{}[{}]", path, idx);
+        let (tick, gain) = as_pair(change, &path)?;
+
+        let gain = gain
+            .as_f64()
+            .filter(|g| 0. <= *g && *g <= 1.)
+            .ok_or_else(|| invalid(&path))?;
+
+        chart
+            .laser_filter_gain
+            .push((as_int(tick, &path)?, (gain * 100.).round() as u8));
+    }
+
+    Ok(())
+}
+
+fn read_camera(
+    root: &Value,
+    chart: &mut Chart,
+) -> Result<(), KsonError>
+{
+    read_tilt(root, chart)?;
+    chart.zoom_bottom = read_graph(root, "camera.cam.body.zoom")?;
+    chart.zoom_top = read_graph(root, "camera.cam.body.rotation_x")?;
+    chart.zoom_side = read_graph(root, "camera.cam.body.shift_x")?;
+    chart.center_split = read_graph(root, "camera.cam.body.center_split")?;
+
+    let path = "camera.lane_toggle";
+    for (idx, toggle) in read_array(root, path)?.iter().enumerate() {
+        let path = format!("{}[{}]", path, idx);
+        let (tick, length) = as_pair(toggle, &path)?;
+
+        chart
+            .lane_toggles
+            .push((as_int(tick, &path)?, as_int(length, &path)?));
+    }
+
+    let spin_kinds = [
+        ("spin", SpinKind::Full),
+        ("half_spin", SpinKind::Half),
+        ("swing", SpinKind::Sway),
+    ];
+
+    for (name, kind) in spin_kinds.iter() {
+        let path = format!("camera.cam.pattern.laser.slam_event.{}", name);
+
+        for (idx, spin) in read_array(root, &path)?.iter().enumerate() {
+            let path = format!("{}[{}]", path, idx);
+            let spin = spin.as_array().ok_or_else(|| invalid(&path))?;
+
+            // swings may have extra parameters after the length
+            if spin.len() < 3 {
+                return Err(invalid(&path));
+            }
+
+            let direction = match spin[1].as_i64() {
+                Some(-1) => SpinDirection::Left,
+                Some(1) => SpinDirection::Right,
+                _ => return Err(invalid(&path)),
+            };
+
+            chart.spins.push(SpinEvent {
+                tick: as_int(&spin[0], &path)?,
+                kind: *kind,
+                direction,
+                length: as_int(&spin[2], &path)?,
+            });
+        }
+    }
+
+    // the spins are stored by kind in the file but by time in the chart. the
+    // spins at the same tick are sorted on the rest of their fields, so the
+    // order doesn't depend on the order of the kinds in the file
+    chart.spins.sort();
+
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn read_lane(
+    lane: &Value,
+    path: &str,
+) -> Result<Vec<Interval>, KsonError>
+{
+    let lane = lane.as_array().ok_or_else(|| invalid(path))?;
+    let mut notes = Vec::with_capacity(lane.len());
+
+    for (idx, note) in lane.iter().enumerate() {
+        let path = format!("{}[{}]", path, idx);
+
+        // a chip may be written as just its position
+        let interval = match note {
+            Value::Array(_) => {
+                let (tick, length) = as_pair(note, &path)?;
+
+                Interval {
+                    tick:   as_int(tick, &path)?,
+                    length: as_int(length, &path)?,
+                }
+            },
+
+            _ => {
+                Interval {
+                    tick:   as_int(note, &path)?,
+                    length: 0,
+                }
+            },
+        };
+
+        notes.push(interval);
+    }
+
+    Ok(notes)
+}
+
+fn write_lane(lane: &[Interval]) -> Value {
+    lane.iter()
+        .map(|note| {
+            if note.is_chip() {
+                json!(note.tick)
+            }
+            else {
+                json!([note.tick, note.length])
+            }
+        })
+        .collect()
+}
+
+fn read_laser_section(
+    section: &Value,
+    path: &str,
+) -> Result<LaserSection, KsonError>
+{
+    let section = section.as_array().ok_or_else(|| invalid(path))?;
+
+    let tick = section.get(0).ok_or_else(|| invalid(path))?;
+    let points = section.get(1).ok_or_else(|| invalid(path))?;
+    let points = read_graph_points(points, path)?;
+
+    // the width is either 1 or 2 and may be omitted if it's 1
+    let wide = match section.get(2).map(|w| w.as_u64()) {
+        None | Some(Some(1)) => false,
+        Some(Some(2)) => true,
+        _ => return Err(invalid(path)),
+    };
+
+    Ok(LaserSection {
+        tick: as_int(tick, path)?,
+        points,
+        wide,
+    })
+}
+
+fn write_laser_section(section: &LaserSection) -> Value {
+    let points = write_graph(&section.points);
+
+    if section.wide {
+        json!([section.tick, points, 2])
+    }
+    else {
+        json!([section.tick, points])
+    }
+}
+
+fn read_graph(
+    root: &Value,
+    path: &str,
+) -> Result<Vec<GraphPoint>, KsonError>
+{
+    match lookup(root, path) {
+        Some(points) => read_graph_points(points, path),
+        None => Ok(vec![]),
+    }
+}
+
+fn read_graph_points(
+    points: &Value,
+    path: &str,
+) -> Result<Vec<GraphPoint>, KsonError>
+{
+    let points = points.as_array().ok_or_else(|| invalid(path))?;
+    let mut graph = Vec::with_capacity(points.len());
+
+    for (idx, point) in points.iter().enumerate() {
+        let path = format!("{}[{}]", path, idx);
+        graph.push(read_graph_point(point, &path)?);
+    }
+
+    Ok(graph)
+}
+
+fn read_graph_point(
+    point: &Value,
+    path: &str,
+) -> Result<GraphPoint, KsonError>
+{
+    let point = point.as_array().ok_or_else(|| invalid(path))?;
+
+    // there may be curve parameters after the value, which we don't support
+    // yet
+    let tick = point.get(0).ok_or_else(|| invalid(path))?;
+    let value = point.get(1).ok_or_else(|| invalid(path))?;
+
+    let value = match value {
+        Value::Array(_) => {
+            let (v, vf) = as_pair(value, path)?;
+
+            GraphValue {
+                v:  v.as_f64().ok_or_else(|| invalid(path))?,
+                vf: vf.as_f64().ok_or_else(|| invalid(path))?,
+            }
+        },
+
+        v => GraphValue::new(v.as_f64().ok_or_else(|| invalid(path))?),
+    };
+
+    Ok(GraphPoint {
+        tick: as_int(tick, path)?,
+        value,
+    })
+}
+
+/// Reads the tilt, where the values of the manual tilt are mixed in with the
+/// names of the other modes. A value switches to the manual tilt.
+fn read_tilt(
+    root: &Value,
+    chart: &mut Chart,
+) -> Result<(), KsonError>
+{
+    let path = "camera.tilt";
+
+    for (idx, change) in read_array(root, path)?.iter().enumerate() {
+        let path = format!("{}[{}]", path, idx);
+        let name = change.as_array().and_then(|c| c.get(1)?.as_str());
+
+        if let Some(name) = name {
+            let (tick, _) = as_pair(change, &path)?;
+            let mode = TiltMode::from_name(name)
+                .filter(|mode| *mode != TiltMode::Manual)
+                .ok_or_else(|| invalid(&path))?;
+
+            chart.tilt_modes.push((as_int(tick, &path)?, mode));
+        }
+        else {
+            let point = read_graph_point(change, &path)?;
+
+            let modes = &mut chart.tilt_modes;
+            if modes.last().map(|(_, m)| *m) != Some(TiltMode::Manual) {
+                modes.push((point.tick, TiltMode::Manual));
+            }
+
+            chart.tilt.push(point);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_graph(graph: &[GraphPoint]) -> Value {
+    graph
+        .iter()
+        .map(|point| {
+            let value = point.value;
+
+            if value.is_slam() {
+                json!([point.tick, [value.v, value.vf]])
+            }
+            else {
+                json!([point.tick, value.v])
+            }
+        })
+        .collect()
+}
+
+/// Writes the manual tilt along with the other modes, in the order of the
+/// modes. The switches to the manual tilt are left to its values.
+fn write_tilt(
+    graph: &[GraphPoint],
+    modes: &[(Tick, TiltMode)],
+) -> Value
+{
+    let points = write_graph(graph);
+    let points = points.as_array().map_or(&[][..], |p| p.as_slice());
+    let mut points = graph
+        .iter()
+        .map(|point| point.tick)
+        .zip(points.iter().cloned())
+        .peekable();
+
+    let mut tilt = vec![];
+
+    for (idx, (tick, mode)) in modes.iter().enumerate() {
+        if *mode == TiltMode::Manual {
+            continue;
+        }
+
+        // the values on the same tick as a mode come before it, unless the
+        // tilt is switched back to manual on that tick, which they came with
+        let is_switched_back = modes[idx + 1 ..]
+            .iter()
+            .take_while(|(next, _)| next == tick)
+            .any(|(_, next)| *next == TiltMode::Manual);
+
+        let is_before = |point: &(Tick, Value)| {
+            point.0 < *tick || (point.0 == *tick && !is_switched_back)
+        };
+
+        while points.peek().map_or(false, is_before) {
+            tilt.extend(points.next().map(|(_, point)| point));
+        }
+
+        tilt.push(json!([tick, mode.name()]));
+    }
+
+    tilt.extend(points.map(|(_, point)| point));
+    Value::Array(tilt)
+}
+
+/// Reads an FX effect, which is either written as its tick, or as its tick and
+/// parameters
+fn read_fx_effect(
+    name: &str,
+    event: &Value,
+    path: &str,
+) -> Result<Option<(Tick, FxEffect)>, KsonError>
+{
+    let (tick, params) = match event {
+        Value::Array(_) => {
+            let (tick, params) = as_pair(event, path)?;
+            let params = params
+                .as_array()
+                .ok_or_else(|| invalid(path))?
+                .iter()
+                .map(|p| p.as_i64().ok_or_else(|| invalid(path)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            (tick, params)
+        },
+
+        tick => (tick, vec![]),
+    };
+
+    let tick = as_int(tick, path)?;
+    Ok(FxEffect::from_parts(name, &params).map(|effect| (tick, effect)))
+}
+
+fn write_fx_effects(effects: &[Vec<(Tick, FxEffect)>; 2]) -> Value {
+    let mut events = Map::new();
+
+    for (lane, effects) in effects.iter().enumerate() {
+        for (tick, effect) in effects.iter() {
+            let lanes = events
+                .entry(effect.name())
+                .or_insert_with(|| json!([[], []]));
+
+            lanes[lane]
+                .as_array_mut()
+                .unwrap()
+                .push(json!([tick, effect.params()]));
+        }
+    }
+
+    Value::Object(events)
+}
+
+/// Reads an FX sample, which is either written as its tick, or as its tick and
+/// volume
+fn read_fx_sample(
+    name: &str,
+    event: &Value,
+    path: &str,
+) -> Result<(Tick, FxSample), KsonError>
+{
+    let (tick, volume) = match event {
+        Value::Array(_) => {
+            let (tick, params) = as_pair(event, path)?;
+            let volume = match params.get("vol") {
+                Some(vol) => as_volume(vol, path)?,
+                None => 100,
+            };
+
+            (tick, volume)
+        },
+
+        tick => (tick, 100),
+    };
+
+    let sample = FxSample {
+        sound: FxSound::from_name(name),
+        volume,
+    };
+
+    Ok((as_int(tick, path)?, sample))
+}
+
+fn write_fx_samples(samples: &[Vec<(Tick, FxSample)>; 2]) -> Value {
+    let mut events = Map::new();
+
+    for (lane, samples) in samples.iter().enumerate() {
+        for (tick, sample) in samples.iter() {
+            let lanes = events
+                .entry(sample.sound.name())
+                .or_insert_with(|| json!([[], []]));
+
+            lanes[lane].as_array_mut().unwrap().push(json!([
+                tick,
+                { "vol": sample.volume as f64 / 100. }
+            ]));
+        }
+    }
+
+    Value::Object(events)
+}
+
+/// Writes the filters by name. Of the filters of the same tick, only the last
+/// one is heard, so it's the only one written.
+fn write_laser_filters(filters: &[(Tick, LaserFilter)]) -> Value {
+    let mut events = Map::new();
+
+    let is_replaced = |idx: usize| {
+        filters.get(idx + 1).map(|next| next.0) == Some(filters[idx].0)
+    };
+
+    for (idx, (tick, filter)) in filters.iter().enumerate() {
+        if is_replaced(idx) {
+            continue;
+        }
+
+        events
+            .entry(filter.name())
+            .or_insert_with(|| json!([]))
+            .as_array_mut()
+            .unwrap()
+            .push(json!(tick));
+    }
+
+    Value::Object(events)
+}
+
+fn write_direction(direction: SpinDirection) -> i64 {
+    match direction {
+        SpinDirection::Left => -1,
+        SpinDirection::Right => 1,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Follows a dotted path from the root. Returns `None` if any of the objects
+/// along the way is missing.
+fn lookup<'a>(
+    root: &'a Value,
+    path: &str,
+) -> Option<&'a Value>
+{
+    path.split('.').try_fold(root, |value, key| value.get(key))
+}
+
+fn read_string(
+    root: &Value,
+    path: &str,
+) -> Result<String, KsonError>
+{
+    match lookup(root, path) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(_) => Err(invalid(path)),
+        None => Ok(String::new()),
+    }
+}
+
+fn read_array<'a>(
+    root: &'a Value,
+    path: &str,
+) -> Result<&'a [Value], KsonError>
+{
+    match lookup(root, path) {
+        Some(Value::Array(a)) => Ok(a),
+        Some(_) => Err(invalid(path)),
+        None => Ok(&[]),
+    }
+}
+
+fn as_pair<'a>(
+    value: &'a Value,
+    path: &str,
+) -> Result<(&'a Value, &'a Value), KsonError>
+{
+    match value.as_array().map(|a| a.as_slice()) {
+        Some([a, b]) => Ok((a, b)),
+        _ => Err(invalid(path)),
+    }
+}
+
+fn as_int<T>(
+    value: &Value,
+    path: &str,
+) -> Result<T, KsonError>
+where
+    T: std::convert::TryFrom<i64>,
+{
+    value
+        .as_i64()
+        .and_then(|i| T::try_from(i).ok())
+        .ok_or_else(|| invalid(path))
+}
+
+/// Reads a volume, which is a fraction of the full volume in the file but a
+/// percentage in the chart. KSH lets the volumes go over 100%, so anything up
+/// to what fits in the percentage is taken
+fn as_volume(
+    value: &Value,
+    path: &str,
+) -> Result<u8, KsonError>
+{
+    value
+        .as_f64()
+        .map(|v| (v * 100.).round())
+        .filter(|v| 0. <= *v && *v <= u8::MAX as f64)
+        .map(|v| v as u8)
+        .ok_or_else(|| invalid(path))
+}
+
+fn invalid(path: &str) -> KsonError {
+    KsonError::InvalidValue(path.to_owned())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::ksh;
+
+    const FIXTURE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/chart.ksh",
+    ));
+
+    #[test]
+    fn keeps_a_ksh_chart_through_a_round_trip() {
+        let chart = ksh::parse(FIXTURE).unwrap();
+
+        // the fixture has tilt values on the same ticks as other modes, both
+        // before and after them, and two filters set on the same tick
+        let modes = &chart.tilt_modes;
+        let is_manual = |idx: usize| modes[idx].1 == TiltMode::Manual;
+        let same_ticks = (1 .. modes.len())
+            .filter(|idx| modes[idx - 1].0 == modes[*idx].0)
+            .collect::<Vec<_>>();
+
+        assert!(same_ticks.iter().any(|idx| is_manual(*idx - 1)));
+        assert!(same_ticks.iter().any(|idx| is_manual(*idx)));
+        assert_eq!(chart.laser_filters, vec![
+            (0, LaserFilter::Peak),
+            (960, LaserFilter::HighPass),
+        ]);
+
+        let written = to_string(&chart);
+        let read = parse(&written).unwrap();
+        assert_eq!(read, chart);
+
+        // and writing it again gives the same file
+        assert_eq!(to_string(&read), written);
+    }
+
+    #[test]
+    fn writes_the_last_filter_of_a_tick() {
+        let mut chart = ksh::parse(FIXTURE).unwrap();
+        chart.laser_filters = vec![
+            (0, LaserFilter::Peak),
+            (960, LaserFilter::HighPass),
+            (960, LaserFilter::LowPass),
+            (1920, LaserFilter::BitCrusher),
+        ];
+
+        let read = parse(&to_string(&chart)).unwrap();
+        assert_eq!(read.laser_filters, vec![
+            (0, LaserFilter::Peak),
+            (960, LaserFilter::LowPass),
+            (1920, LaserFilter::BitCrusher),
+        ]);
+    }
+
+    #[test]
+    fn rejects_time_signatures_out_of_order() {
+        let chart = ksh::parse(FIXTURE).unwrap();
+        let mut value = to_value(&chart);
+
+        let time_sig = |measures: [u32; 3]| {
+            json!([
+                [measures[0], [4, 4]],
+                [measures[1], [3, 4]],
+                [measures[2], [6, 8]],
+            ])
+        };
+
+        value["beat"]["time_sig"] = time_sig([0, 2, 4]);
+        assert!(parse(&value.to_string()).is_ok());
+
+        value["beat"]["time_sig"] = time_sig([0, 4, 2]);
+        assert!(parse(&value.to_string()).is_err());
+
+        value["beat"]["time_sig"] = time_sig([0, 2, 2]);
+        assert!(parse(&value.to_string()).is_err());
+    }
+
+    #[test]
+    fn rejects_bpm_changes_out_of_order() {
+        let chart = ksh::parse(FIXTURE).unwrap();
+        let mut value = to_value(&chart);
+
+        let bpm = |ticks: [u32; 3]| {
+            json!([[ticks[0], 120.], [ticks[1], 180.], [ticks[2], 90.]])
+        };
+
+        value["beat"]["bpm"] = bpm([0, 480, 960]);
+        assert!(parse(&value.to_string()).is_ok());
+
+        value["beat"]["bpm"] = bpm([0, 960, 480]);
+        assert!(parse(&value.to_string()).is_err());
+
+        value["beat"]["bpm"] = bpm([0, 480, 480]);
+        assert!(parse(&value.to_string()).is_err());
+    }
+
+    #[test]
+    fn reads_volumes_over_the_full_volume() {
+        let chart = ksh::parse(FIXTURE).unwrap();
+        assert_eq!(chart.meta.music_volume, 120);
+        assert_eq!(chart.meta.slam_volume, 150);
+
+        let read = parse(&to_string(&chart)).unwrap();
+        assert_eq!(read.meta.music_volume, 120);
+        assert_eq!(read.meta.slam_volume, 150);
+
+        let mut value = to_value(&chart);
+        value["audio"]["bgm"]["vol"] = json!(2.6);
+        assert!(parse(&value.to_string()).is_err());
+
+        value["audio"]["bgm"]["vol"] = json!(-0.1);
+        assert!(parse(&value.to_string()).is_err());
+    }
+
+    #[test]
+    fn keeps_the_order_of_spins_at_the_same_tick() {
+        let mut chart = ksh::parse(FIXTURE).unwrap();
+
+        let spin = |tick, kind, direction| {
+            SpinEvent {
+                tick,
+                kind,
+                direction,
+                length: 240,
+            }
+        };
+
+        // written by kind, so they're read out of order
+        chart.spins = vec![
+            spin(480, SpinKind::Full, SpinDirection::Right),
+            spin(0, SpinKind::Sway, SpinDirection::Left),
+            spin(0, SpinKind::Half, SpinDirection::Left),
+            spin(0, SpinKind::Full, SpinDirection::Right),
+            spin(0, SpinKind::Full, SpinDirection::Left),
+        ];
+
+        let read = parse(&to_string(&chart)).unwrap();
+        assert_eq!(read.spins, vec![
+            spin(0, SpinKind::Full, SpinDirection::Left),
+            spin(0, SpinKind::Full, SpinDirection::Right),
+            spin(0, SpinKind::Half, SpinDirection::Left),
+            spin(0, SpinKind::Sway, SpinDirection::Left),
+            spin(480, SpinKind::Full, SpinDirection::Right),
+        ]);
+    }
+}
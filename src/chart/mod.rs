@@ -0,0 +1,593 @@
+pub mod ksh;
+pub mod kson;
+
+////////////////////////////////////////////////////////////////////////////////
+
+use fnv::FnvHasher;
+use std::{
+    fmt,
+    hash::Hasher as _,
+    io,
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// A musical position in the chart, counted in pulses from the very first
+/// measure.
+pub type Tick = u32;
+
+/// The number of ticks in a quarter note
+pub const TICKS_PER_BEAT: Tick = 240;
+
+/// The number of ticks in a measure of 4/4
+pub const TICKS_PER_WHOLE: Tick = TICKS_PER_BEAT * 4;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The in-memory representation of a chart, independent of the format it was
+/// loaded from.
+///
+/// Everything in here is expressed in ticks. Conversion to `SongTime` only
+/// happens once the chart is loaded into the song player.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Chart {
+    pub meta: ChartMeta,
+
+    // timing
+    pub bpm_changes:     Vec<(Tick, f64)>,
+    pub time_signatures: Vec<(u32, TimeSignature)>,
+    pub stops:           Vec<(Tick, Tick)>,
+    pub scroll_speed:    Vec<GraphPoint>,
+
+    // notes
+    pub bt:     [Vec<Interval>; 4],
+    pub fx:     [Vec<Interval>; 2],
+    pub lasers: [Vec<LaserSection>; 2],
+
+    // the audio effects of the FX holds, by the tick each hold starts at
+    pub fx_effects: [Vec<(Tick, FxEffect)>; 2],
+
+    // the samples played as the FX chips are hit, by the tick of each chip
+    pub fx_samples: [Vec<(Tick, FxSample)>; 2],
+
+    // the filter the lasers put the music through from the tick on, one for
+    // each tick at most, and how strongly they do, in percent
+    pub laser_filters:     Vec<(Tick, LaserFilter)>,
+    pub laser_filter_gain: Vec<(Tick, u8)>,
+
+    // camera. the tilt is only the manual one, which is followed while the
+    // tilt mode is manual.
+    pub zoom_top:     Vec<GraphPoint>,
+    pub zoom_bottom:  Vec<GraphPoint>,
+    pub zoom_side:    Vec<GraphPoint>,
+    pub center_split: Vec<GraphPoint>,
+    pub tilt:         Vec<GraphPoint>,
+    pub tilt_modes:   Vec<(Tick, TiltMode)>,
+    pub spins:        Vec<SpinEvent>,
+
+    // the lanes are hidden by the first toggle, shown again by the next and so
+    // on. each one fades them over its length.
+    pub lane_toggles: Vec<(Tick, Tick)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartMeta {
+    pub title:       String,
+    pub artist:      String,
+    pub effector:    String,
+    pub jacket:      String,
+    pub illustrator: String,
+    pub difficulty:  u8,
+    pub level:       u8,
+    pub display_bpm: String,
+
+    // the audio file is relative to the directory of the chart. the volumes
+    // are in percent.
+    pub music_file:   String,
+    pub music_volume: u8,
+    pub offset_ms:    i64,
+    pub slam_volume:  u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeSignature {
+    pub numerator:   u32,
+    pub denominator: u32,
+}
+
+/// A BT or FX note. A chip has a length of zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval {
+    pub tick:   Tick,
+    pub length: Tick,
+}
+
+/// A continuous stretch of laser, starting at `tick`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaserSection {
+    pub tick:   Tick,
+    pub points: Vec<GraphPoint>,
+
+    // whether the laser spans twice the width of the lanes
+    pub wide: bool,
+}
+
+/// A point on a graph. Two different values on the same point makes a slam.
+///
+/// For laser sections, the tick is relative to the start of the section.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphPoint {
+    pub tick:  Tick,
+    pub value: GraphValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphValue {
+    // the value upon arriving at the point
+    pub v:  f64,
+    // the value upon leaving the point
+    pub vf: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpinEvent {
+    pub tick:      Tick,
+    pub kind:      SpinKind,
+    pub direction: SpinDirection,
+    pub length:    Tick,
+}
+
+/// An audio effect applied to the music while an FX hold is held.
+///
+/// The divisions are of a whole note, so a retrigger of 8 repeats every eighth
+/// note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FxEffect {
+    Retrigger(u32),
+    Gate(u32),
+    Flanger,
+
+    // how many frames each frame is held for
+    BitCrusher(u32),
+
+    Wobble(u32),
+    Phaser,
+
+    // how fast the music stops, from 1 to 100
+    TapeStop(u32),
+
+    // the division and how much is fed back, in percent
+    Echo(u32, u32),
+
+    SideChain,
+
+    // in semitones
+    PitchShift(i32),
+}
+
+/// A sample played when an FX chip is hit
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FxSample {
+    pub sound: FxSound,
+
+    // in percent
+    pub volume: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FxSound {
+    Clap,
+    ClapImpact,
+    ClapPunchy,
+    Snare,
+    SnareLo,
+
+    // an audio file, relative to the directory of the chart
+    File(String),
+}
+
+/// A filter the music goes through while a laser is followed. How far the
+/// laser is from where it rests sets how much the music is filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LaserFilter {
+    Peak,
+    LowPass,
+    HighPass,
+    BitCrusher,
+}
+
+/// How the lanes tilt from the tick on. The lanes tilt towards the lasers as
+/// strongly as the mode says, and the ones that keep the tilt hold it once the
+/// lasers are gone instead of going back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TiltMode {
+    Zero,
+    Normal,
+    Bigger,
+    Biggest,
+    KeepNormal,
+    KeepBigger,
+    KeepBiggest,
+
+    // the lanes follow the tilt graph of the chart instead of the lasers
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SpinKind {
+    Full,
+    Half,
+    Sway,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SpinDirection {
+    Left,
+    Right,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Chart {
+    /// Loads the chart at the given path, choosing the format from the
+    /// extension of the file
+    pub fn load<P>(path: P) -> Result<Chart, ChartLoadError>
+    where P: AsRef<Path> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_ref().map(|e| e.as_str()) {
+            Some("ksh") => ksh::load(path),
+            Some("kson") => kson::load(path),
+            _ => Err(ChartLoadError::UnknownFormat),
+        }
+    }
+
+    /// Returns the tick of the last event in the chart
+    pub fn last_tick(&self) -> Tick {
+        let notes = self
+            .bt
+            .iter()
+            .chain(self.fx.iter())
+            .flat_map(|lane| lane.last())
+            .map(|note| note.tick + note.length);
+
+        let lasers = self
+            .lasers
+            .iter()
+            .flat_map(|side| side.last())
+            .map(|section| section.tick + section.last_relative_tick());
+
+        notes.chain(lasers).max().unwrap_or(0)
+    }
+
+    /// Returns a hash of everything in the chart. It's the same for the same
+    /// chart, whichever format it was loaded from.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write(kson::to_string(self).as_bytes());
+        hasher.finish()
+    }
+}
+
+impl FxEffect {
+    /// Returns the name of the effect, as it's written in KSON
+    pub fn name(&self) -> &'static str {
+        use FxEffect::*;
+
+        match self {
+            Retrigger(_) => "retrigger",
+            Gate(_) => "gate",
+            Flanger => "flanger",
+            BitCrusher(_) => "bitcrusher",
+            Wobble(_) => "wobble",
+            Phaser => "phaser",
+            TapeStop(_) => "tapestop",
+            Echo(..) => "echo",
+            SideChain => "sidechain",
+            PitchShift(_) => "pitch_shift",
+        }
+    }
+
+    pub fn params(&self) -> Vec<i64> {
+        use FxEffect::*;
+
+        match *self {
+            Retrigger(division) | Gate(division) | Wobble(division) => {
+                vec![division as i64]
+            },
+            BitCrusher(reduction) => vec![reduction as i64],
+            TapeStop(speed) => vec![speed as i64],
+            Echo(division, feedback) => vec![division as i64, feedback as i64],
+            PitchShift(semitones) => vec![semitones as i64],
+            Flanger | Phaser | SideChain => vec![],
+        }
+    }
+
+    /// Makes an effect from its name and parameters. The case and the
+    /// underscores of the name don't matter, so both the KSH and the KSON
+    /// names are understood. Missing parameters are given their defaults.
+    pub fn from_parts(
+        name: &str,
+        params: &[i64],
+    ) -> Option<FxEffect>
+    {
+        use FxEffect::*;
+
+        let name = name.replace('_', "").to_ascii_lowercase();
+        let param = |idx: usize, default: i64| {
+            params.get(idx).cloned().unwrap_or(default)
+        };
+        let positive = |idx: usize, default: i64| {
+            let value = param(idx, default);
+
+            if 0 < value {
+                Some(value as u32)
+            }
+            else {
+                None
+            }
+        };
+
+        let effect = match name.as_str() {
+            "retrigger" => Retrigger(positive(0, 8)?),
+            "gate" => Gate(positive(0, 4)?),
+            "flanger" => Flanger,
+            "bitcrusher" => BitCrusher(positive(0, 5)?),
+            "wobble" => Wobble(positive(0, 12)?),
+            "phaser" => Phaser,
+            "tapestop" => TapeStop(positive(0, 50)?.min(100)),
+            "echo" => {
+                let feedback = param(1, 60).max(0).min(100);
+                Echo(positive(0, 4)?, feedback as u32)
+            },
+            "sidechain" => SideChain,
+            "pitchshift" => PitchShift(param(0, 12).max(-48).min(48) as i32),
+            _ => return None,
+        };
+
+        Some(effect)
+    }
+}
+
+impl FxSound {
+    /// Returns the name of the sound, which is the file name of a custom one
+    pub fn name(&self) -> &str {
+        match self {
+            FxSound::Clap => "clap",
+            FxSound::ClapImpact => "clap_impact",
+            FxSound::ClapPunchy => "clap_punchy",
+            FxSound::Snare => "snare",
+            FxSound::SnareLo => "snare_lo",
+            FxSound::File(file) => file,
+        }
+    }
+
+    /// Makes a sound from its name. Anything that isn't a built-in sound is
+    /// taken as a file.
+    pub fn from_name(name: &str) -> FxSound {
+        match name {
+            "clap" => FxSound::Clap,
+            "clap_impact" => FxSound::ClapImpact,
+            "clap_punchy" => FxSound::ClapPunchy,
+            "snare" => FxSound::Snare,
+            "snare_lo" => FxSound::SnareLo,
+            file => FxSound::File(file.to_owned()),
+        }
+    }
+}
+
+impl LaserFilter {
+    /// Returns the name of the filter, as it's written in KSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            LaserFilter::Peak => "peaking_filter",
+            LaserFilter::LowPass => "low_pass_filter",
+            LaserFilter::HighPass => "high_pass_filter",
+            LaserFilter::BitCrusher => "bitcrusher",
+        }
+    }
+
+    /// Makes a filter from its name, in either KSH or KSON
+    pub fn from_name(name: &str) -> Option<LaserFilter> {
+        let filter = match name {
+            "peak" | "peaking_filter" => LaserFilter::Peak,
+            "lpf1" | "low_pass_filter" => LaserFilter::LowPass,
+            "hpf1" | "high_pass_filter" => LaserFilter::HighPass,
+            "bitc" | "bitcrusher" => LaserFilter::BitCrusher,
+            _ => return None,
+        };
+
+        Some(filter)
+    }
+}
+
+impl TiltMode {
+    /// Returns the name of the mode, as it's written in either KSH or KSON.
+    /// The manual tilt is written as its values instead.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TiltMode::Zero => "zero",
+            TiltMode::Normal => "normal",
+            TiltMode::Bigger => "bigger",
+            TiltMode::Biggest => "biggest",
+            TiltMode::KeepNormal => "keep_normal",
+            TiltMode::KeepBigger => "keep_bigger",
+            TiltMode::KeepBiggest => "keep_biggest",
+            TiltMode::Manual => "manual",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TiltMode> {
+        let mode = match name {
+            "zero" => TiltMode::Zero,
+            "normal" => TiltMode::Normal,
+            "bigger" => TiltMode::Bigger,
+            "biggest" => TiltMode::Biggest,
+            "keep_normal" => TiltMode::KeepNormal,
+            "keep_bigger" => TiltMode::KeepBigger,
+            "keep_biggest" => TiltMode::KeepBiggest,
+            "manual" => TiltMode::Manual,
+            _ => return None,
+        };
+
+        Some(mode)
+    }
+
+    /// Returns how far the lanes tilt towards the lasers, relative to the
+    /// normal tilt
+    pub fn scale(&self) -> f64 {
+        match self {
+            TiltMode::Zero | TiltMode::Manual => 0.,
+            TiltMode::Normal | TiltMode::KeepNormal => 1.,
+            TiltMode::Bigger | TiltMode::KeepBigger => 1.75,
+            TiltMode::Biggest | TiltMode::KeepBiggest => 2.5,
+        }
+    }
+
+    /// Returns true if the tilt is held once the lasers are gone
+    pub fn keeps(&self) -> bool {
+        match self {
+            TiltMode::KeepNormal |
+            TiltMode::KeepBigger |
+            TiltMode::KeepBiggest => true,
+            _ => false,
+        }
+    }
+}
+
+impl Default for TiltMode {
+    fn default() -> TiltMode {
+        TiltMode::Normal
+    }
+}
+
+impl Default for LaserFilter {
+    fn default() -> LaserFilter {
+        LaserFilter::Peak
+    }
+}
+
+impl Default for ChartMeta {
+    fn default() -> ChartMeta {
+        ChartMeta {
+            title:        String::new(),
+            artist:       String::new(),
+            effector:     String::new(),
+            jacket:       String::new(),
+            illustrator:  String::new(),
+            difficulty:   0,
+            level:        0,
+            display_bpm:  String::new(),
+            music_file:   String::new(),
+            music_volume: 100,
+            offset_ms:    0,
+            slam_volume:  50,
+        }
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> TimeSignature {
+        TimeSignature {
+            numerator:   4,
+            denominator: 4,
+        }
+    }
+}
+
+impl TimeSignature {
+    /// Returns the number of ticks in a measure with this time signature
+    pub fn measure_length(&self) -> Tick {
+        TICKS_PER_WHOLE * self.numerator / self.denominator
+    }
+}
+
+impl Interval {
+    pub fn is_chip(&self) -> bool {
+        self.length == 0
+    }
+}
+
+impl LaserSection {
+    /// Returns the tick, relative to the start of the section, of the last
+    /// point of the section
+    pub fn last_relative_tick(&self) -> Tick {
+        self.points.last().map(|p| p.tick).unwrap_or(0)
+    }
+}
+
+impl GraphValue {
+    pub fn new(v: f64) -> GraphValue {
+        GraphValue {
+            v,
+            vf: v,
+        }
+    }
+
+    pub fn is_slam(&self) -> bool {
+        self.v != self.vf
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum ChartLoadError {
+    Io(io::Error),
+    Ksh(ksh::KshError),
+    Kson(kson::KsonError),
+    UnknownFormat,
+}
+
+impl fmt::Display for ChartLoadError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        match self {
+            ChartLoadError::Io(e) => write!(f, "could not read chart: {}", e),
+            ChartLoadError::Ksh(e) => write!(f, "{}", e),
+            ChartLoadError::Kson(e) => write!(f, "{}", e),
+            ChartLoadError::UnknownFormat => {
+                write!(f, "the chart is neither a KSH nor a KSON file")
+            },
+        }
+    }
+}
+
+impl std::error::Error for ChartLoadError {
+}
+
+impl From<io::Error> for ChartLoadError {
+    fn from(e: io::Error) -> ChartLoadError {
+        ChartLoadError::Io(e)
+    }
+}
+
+impl From<ksh::KshError> for ChartLoadError {
+    fn from(e: ksh::KshError) -> ChartLoadError {
+        ChartLoadError::Ksh(e)
+    }
+}
+
+impl From<kson::KsonError> for ChartLoadError {
+    fn from(e: kson::KsonError) -> ChartLoadError {
+        ChartLoadError::Kson(e)
+    }
+}
+
+/// Reads the file as text, dropping the byte order mark some editors leave in
+/// front of it
+fn read_chart_text<P>(path: P) -> io::Result<String>
+where P: AsRef<Path> {
+    let bytes = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    Ok(text.trim_start_matches('\u{feff}').to_owned())
+}
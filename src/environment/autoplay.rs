@@ -0,0 +1,331 @@
+use crate::{
+    chart::{
+        Chart,
+        Interval,
+        LaserSection,
+    },
+    environment::key_bindings::{
+        BindRoles,
+        ComposedKeystroke,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use bidir_map::BidirMap;
+use piston_window::{
+    ButtonArgs,
+    ButtonState,
+    Input,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how long the chips are held down
+const CHIP_HOLD_MS: f64 = 30.;
+
+// how long the knobs are turned for a slam
+const SLAM_HOLD_MS: f64 = 10.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Plays a chart by itself.
+///
+/// The inputs are made from the chart ahead of time, as the button presses and
+/// releases a player would make with perfect timing. They are then handed out
+/// as the song goes on to be handled like any other input.
+#[derive(Debug, Clone)]
+pub struct Autoplay {
+    inputs: Vec<(SongTime, Input)>,
+
+    // every input before this one has been handed out
+    next: usize,
+}
+
+// a role being held down from the first time up to the second
+type Span = (SongTime, SongTime, BindRoles);
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Autoplay {
+    pub fn new(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+        keybindings: &BidirMap<BindRoles, ComposedKeystroke>,
+    ) -> Autoplay
+    {
+        use BindRoles::*;
+
+        let ms = |ms: f64| SongTime::from_seconds(ms / 1000., tempo_map.freq());
+        let chip_hold = ms(CHIP_HOLD_MS);
+        let slam_hold = ms(SLAM_HOLD_MS);
+
+        let mut spans = vec![];
+
+        let buttons = [BT_A, BT_B, BT_C, BT_D]
+            .iter()
+            .zip(chart.bt.iter())
+            .chain([FX_L, FX_R].iter().zip(chart.fx.iter()));
+        for (role, notes) in buttons {
+            spans.extend(note_spans(*role, notes, tempo_map, chip_hold));
+        }
+
+        let knobs = [(KN_L_CCW, KN_L_CW), (KN_R_CCW, KN_R_CW)];
+        for (roles, sections) in knobs.iter().zip(chart.lasers.iter()) {
+            spans.extend(laser_spans(*roles, sections, tempo_map, slam_hold));
+        }
+
+        // the releases go before the presses that happen at the same time
+        let mut actions = spans
+            .into_iter()
+            .flat_map(|(start, end, role)| {
+                vec![
+                    (start, ButtonState::Press, role),
+                    (end, ButtonState::Release, role),
+                ]
+            })
+            .collect::<Vec<_>>();
+        actions.sort_by_key(|(time, state, _)| {
+            (*time, *state == ButtonState::Press)
+        });
+
+        let inputs = actions
+            .into_iter()
+            .filter_map(|(time, state, role)| {
+                let button = keybindings.get_by_first(&role)?.to_button()?;
+
+                let input = Input::Button(ButtonArgs {
+                    state,
+                    button,
+                    scancode: None,
+                });
+
+                Some((time, input))
+            })
+            .collect();
+
+        Autoplay {
+            inputs,
+            next: 0,
+        }
+    }
+
+    /// Hands out the inputs that are due by the given time, along with the
+    /// times they're supposed to happen
+    pub fn poll(
+        &mut self,
+        time: SongTime,
+    ) -> Vec<(SongTime, Input)>
+    {
+        let due = self.inputs[self.next ..]
+            .iter()
+            .take_while(|(at, _)| *at <= time)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.next += due.len();
+        due
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.inputs.len()
+    }
+}
+
+/// Holds the button for each of the notes of a lane
+fn note_spans(
+    role: BindRoles,
+    notes: &[Interval],
+    tempo_map: &TempoMap,
+    chip_hold: SongTime,
+) -> Vec<Span>
+{
+    let times = notes
+        .iter()
+        .map(|note| {
+            let start = tempo_map.tick_to_song_time(note.tick);
+            let end = if note.is_chip() {
+                start + chip_hold
+            }
+            else {
+                tempo_map.tick_to_song_time(note.tick + note.length)
+            };
+
+            (start, end)
+        })
+        .collect::<Vec<_>>();
+
+    // a chip is let go early if the next note comes too soon
+    let next_starts = times
+        .iter()
+        .skip(1)
+        .map(|(start, _)| Some(*start))
+        .chain(Some(None));
+
+    times
+        .iter()
+        .zip(next_starts)
+        .map(|((start, end), next)| {
+            let end = next.map_or(*end, |next| next.min(*end));
+            (*start, end, role)
+        })
+        .collect()
+}
+
+/// Turns the knob of a side whichever way its lasers go
+fn laser_spans(
+    (left, right): (BindRoles, BindRoles),
+    sections: &[LaserSection],
+    tempo_map: &TempoMap,
+    slam_hold: SongTime,
+) -> Vec<Span>
+{
+    let turn_of = |from: f64, to: f64| {
+        if from < to {
+            Some(right)
+        }
+        else if to < from {
+            Some(left)
+        }
+        else {
+            None
+        }
+    };
+
+    // the turns wanted, which may still overlap each other
+    let mut turns = vec![];
+    for section in sections.iter() {
+        let points = section
+            .points
+            .iter()
+            .map(|p| (tempo_map.tick_to_song_time(section.tick + p.tick), p))
+            .collect::<Vec<_>>();
+
+        for (idx, (time, point)) in points.iter().enumerate() {
+            if let Some(role) = turn_of(point.value.v, point.value.vf) {
+                turns.push((*time, *time + slam_hold, role, true));
+            }
+
+            if let Some((next_time, next)) = points.get(idx + 1) {
+                if let Some(role) = turn_of(point.value.vf, next.value.v) {
+                    turns.push((*time, *next_time, role, false));
+                }
+            }
+        }
+    }
+
+    // the sort is stable, so the slams stay ahead of the turns that start
+    // along with them
+    turns.sort_by_key(|(start, ..)| *start);
+
+    // a turn only starts once the one before it is over, unless they go the
+    // same way
+    let mut spans: Vec<Span> = vec![];
+    for (start, end, role, is_slam) in turns.into_iter() {
+        match spans.last_mut() {
+            // a slam only counts if the knob is turned for it, so the knob is
+            // let go of just before
+            Some(last) if last.2 == role && is_slam && last.0 < start => {
+                last.1 = last.1.min(start);
+                spans.push((start, end, role));
+            },
+
+            Some(last) if last.2 == role && start <= last.1 => {
+                last.1 = last.1.max(end);
+            },
+
+            Some(last) => {
+                let start = start.max(last.1);
+                if start < end {
+                    spans.push((start, end, role));
+                }
+            },
+
+            None => spans.push((start, end, role)),
+        }
+    }
+
+    spans
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chart::ksh,
+        environment::key_bindings::GeneralizedKeystroke,
+        judgment::Judgment,
+        song_player::session::{
+            PlayInput,
+            PlaySettings,
+            SongSession,
+        },
+    };
+
+    const FIXTURE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/chart.ksh",
+    ));
+
+    // a thousand frames a second, stepped through a frame at a time
+    const FREQ: u32 = 1000;
+
+    fn play_input(
+        input: &Input,
+        keybindings: &BidirMap<BindRoles, ComposedKeystroke>,
+    ) -> Option<PlayInput>
+    {
+        let args = match input {
+            Input::Button(args) => args,
+            _ => return None,
+        };
+
+        let keystroke = GeneralizedKeystroke::from_button(&args.button)?;
+        let keystroke = ComposedKeystroke::new(keystroke);
+        let role = *keybindings.get_by_second(&keystroke)?;
+
+        match args.state {
+            ButtonState::Press => Some(PlayInput::Press(role)),
+            ButtonState::Release => Some(PlayInput::Release(role)),
+        }
+    }
+
+    #[test]
+    fn plays_the_whole_chart_perfectly() {
+        let chart = ksh::parse(FIXTURE).unwrap();
+        let tempo_map = TempoMap::from_chart(&chart, FREQ);
+        let keybindings = BindRoles::default_keyboard_binding();
+
+        let mut autoplay = Autoplay::new(&chart, &tempo_map, &keybindings);
+        let mut session =
+            SongSession::new(chart, tempo_map, PlaySettings::default());
+
+        let end = session.end_time();
+        let mut now = SongTime(-(FREQ as i64));
+
+        while now <= end && !session.is_over() {
+            for (time, input) in autoplay.poll(now) {
+                if let Some(input) = play_input(&input, &keybindings) {
+                    session.input(input, time);
+                }
+            }
+
+            session.update(now);
+            now = now + SongTime(1);
+        }
+
+        assert!(session.is_over());
+        assert!(autoplay.is_finished());
+
+        let counts = session.judgment().note_counts();
+        let events = session.events();
+        assert_eq!(events.len(), counts.chips + counts.ticks);
+
+        for event in events.iter() {
+            assert_eq!(event.judgment, Judgment::Critical, "{:?}", event);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use bidir_map::BidirMap;
 use piston_window::{
     Button,
+    ControllerButton,
     Key,
 };
 
@@ -99,6 +100,18 @@ impl GeneralizedKeystroke {
             _ => None,
         }
     }
+
+    /// Returns the button that makes this keystroke. The controllers are
+    /// assumed to be the first one.
+    pub fn to_button(&self) -> Button {
+        use Button as B;
+        use GeneralizedKeystroke as GK;
+
+        match self {
+            GK::Keyboard(k) => B::Keyboard(k.clone()),
+            GK::Controller(c) => B::Controller(ControllerButton::new(0, *c)),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -130,4 +143,12 @@ impl ComposedKeystroke {
     {
         self.0.binary_search(&key).is_ok()
     }
+
+    /// Returns the button of a keystroke made of a single key
+    pub fn to_button(&self) -> Option<Button> {
+        match self.0.as_slice() {
+            [key] => Some(key.to_button()),
+            _ => None,
+        }
+    }
 }
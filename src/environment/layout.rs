@@ -0,0 +1,261 @@
+use cgmath::{
+    Matrix4,
+    Vector3,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the sizes the window opens at, in pixels
+const PORTRAIT_WINDOW: [u32; 2] = [540, 960];
+const LANDSCAPE_WINDOW: [u32; 2] = [1280, 720];
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How the lanes and the HUD are put on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    // a tall screen like a cabinet's, with the lanes across the bottom of it
+    // and the HUD above them
+    Portrait,
+
+    // a wide screen, with the lanes in the middle and the HUD at either side
+    Landscape,
+}
+
+/// A part of the screen, in pixels from the top left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x:      f32,
+    pub y:      f32,
+    pub width:  f32,
+    pub height: f32,
+}
+
+/// Where everything goes on a screen of some size
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arrangement {
+    // the lanes are always drawn in a square, as big as the screen allows
+    pub lanes: Rect,
+
+    // whatever the lanes leave of the screen
+    pub hud: Vec<Rect>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Layout {
+    /// Returns the layout picked with `--layout=`, which is landscape unless
+    /// said otherwise
+    pub fn from_args() -> Layout {
+        std::env::args()
+            .find(|arg| arg.starts_with("--layout="))
+            .and_then(|arg| {
+                let name = &arg["--layout=".len() ..];
+
+                let layout = Layout::from_name(name);
+                if layout.is_none() {
+                    eprintln!("There's no layout called {}", name);
+                }
+
+                layout
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "portrait" => Some(Layout::Portrait),
+            "landscape" => Some(Layout::Landscape),
+            _ => None,
+        }
+    }
+
+    /// Returns the size the window opens at
+    pub fn window_size(&self) -> [u32; 2] {
+        match self {
+            Layout::Portrait => PORTRAIT_WINDOW,
+            Layout::Landscape => LANDSCAPE_WINDOW,
+        }
+    }
+
+    /// Puts the lanes and the HUD on a screen of the given size
+    pub fn arrange(
+        &self,
+        width: f32,
+        height: f32,
+    ) -> Arrangement
+    {
+        let side = width.min(height);
+
+        match self {
+            Layout::Portrait => {
+                let lanes = Rect {
+                    x:      (width - side) / 2.,
+                    y:      height - side,
+                    width:  side,
+                    height: side,
+                };
+
+                let hud = vec![Rect {
+                    x:      0.,
+                    y:      0.,
+                    width,
+                    height: height - side,
+                }];
+
+                Arrangement {
+                    lanes,
+                    hud,
+                }
+            },
+
+            Layout::Landscape => {
+                let gutter = (width - side) / 2.;
+
+                let lanes = Rect {
+                    x:      gutter,
+                    y:      height - side,
+                    width:  side,
+                    height: side,
+                };
+
+                let hud = vec![
+                    Rect {
+                        x:      0.,
+                        y:      0.,
+                        width:  gutter,
+                        height,
+                    },
+                    Rect {
+                        x:      gutter + side,
+                        y:      0.,
+                        width:  gutter,
+                        height,
+                    },
+                ];
+
+                Arrangement {
+                    lanes,
+                    hud,
+                }
+            },
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout::Landscape
+    }
+}
+
+impl Rect {
+    /// Returns the matrix that squeezes the whole of the clip space into this
+    /// part of a screen of the given size
+    pub fn clip_transform(
+        &self,
+        screen_width: f32,
+        screen_height: f32,
+    ) -> Matrix4<f32>
+    {
+        // the center of the rectangle in the clip space, where y goes up
+        let x = (self.x + self.width / 2.) / screen_width * 2. - 1.;
+        let y = 1. - (self.y + self.height / 2.) / screen_height * 2.;
+
+        // the translation is scaled by w along with the rest, so it holds
+        // after the perspective divide
+        Matrix4::from_translation(Vector3::new(x, y, 0.)) *
+            Matrix4::from_nonuniform_scale(
+                self.width / screen_width,
+                self.height / screen_height,
+                1.,
+            )
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector4;
+
+    fn rect(
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Rect
+    {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn arranges_portrait_screens() {
+        let arrangement = Layout::Portrait.arrange(540., 960.);
+
+        assert_eq!(arrangement.lanes, rect(0., 420., 540., 540.));
+        assert_eq!(arrangement.hud, vec![rect(0., 0., 540., 420.)]);
+    }
+
+    #[test]
+    fn arranges_landscape_screens() {
+        let arrangement = Layout::Landscape.arrange(1280., 720.);
+
+        assert_eq!(arrangement.lanes, rect(280., 0., 720., 720.));
+        assert_eq!(arrangement.hud, vec![
+            rect(0., 0., 280., 720.),
+            rect(1000., 0., 280., 720.),
+        ]);
+    }
+
+    // where a point of the clip space ends up on the screen, in pixels
+    fn on_screen(
+        transform: Matrix4<f32>,
+        point: Vector4<f32>,
+        screen: [f32; 2],
+    ) -> [f32; 2]
+    {
+        let clip = transform * point;
+        let x = clip.x / clip.w;
+        let y = clip.y / clip.w;
+
+        [(x + 1.) / 2. * screen[0], (1. - y) / 2. * screen[1]]
+    }
+
+    #[test]
+    fn squeezes_the_clip_space_into_rects() {
+        let screen = [1280., 720.];
+        let rects = [
+            rect(280., 0., 720., 720.),
+            rect(0., 420., 540., 300.),
+            rect(1000., 100., 280., 20.),
+        ];
+
+        for rect in rects.iter() {
+            let transform = rect.clip_transform(screen[0], screen[1]);
+            let top_left = [rect.x, rect.y];
+            let bottom_right = [rect.x + rect.width, rect.y + rect.height];
+
+            // the corners stay put through the perspective divide
+            for &w in [1., 2.].iter() {
+                let corners = [
+                    (Vector4::new(-w, w, 0., w), top_left),
+                    (Vector4::new(w, -w, 0., w), bottom_right),
+                ];
+
+                for (point, expected) in corners.iter() {
+                    let found = on_screen(transform, *point, screen);
+
+                    assert!((found[0] - expected[0]).abs() < 1e-3);
+                    assert!((found[1] - expected[1]).abs() < 1e-3);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,16 @@
 pub mod actor_wrapper;
+pub mod autoplay;
 pub mod key_bindings;
+pub mod layout;
 pub mod state;
 pub mod update_routine;
 
 ////////////////////////////////////////////////////////////////////////////////
 
-use self::state::GameState;
+use self::{
+    layout::Layout,
+    state::GameState,
+};
 use crate::{
     environment::{
         actor_wrapper::{
@@ -17,7 +22,10 @@ use crate::{
         },
         update_routine::UpdateEnvelope,
     },
-    song_player::song_timer::SongTime,
+    song_player::{
+        governor::CURRENT_SONG_TIMER,
+        song_timer::SongTime,
+    },
 };
 use futures::{
     future::Future as _,
@@ -30,12 +38,14 @@ use futures::{
 use gfx::{
     format::{
         DepthStencil,
+        Formatted,
         Srgba8,
     },
     handle::{
         DepthStencilView,
         RenderTargetView,
     },
+    memory::Typed,
 };
 use gfx_device_gl::{
     CommandBuffer,
@@ -50,7 +60,10 @@ use gfx_graphics::{
 use glutin_window::GlutinWindow;
 use piston::{
     event_loop::EventLoop as _,
-    window::OpenGLWindow as _,
+    window::{
+        OpenGLWindow as _,
+        Window as _,
+    },
 };
 use piston_window::{
     Events,
@@ -100,9 +113,10 @@ impl GamePrelude {
             .build();
 
         // we'll be changing the samples, and vsync soon using settings
-        // declare the window
+        // declare the window. it starts at the size of the layout.
+        let layout = Layout::from_args();
         let pistonwindow: PistonWindow =
-            WindowSettings::new("YASC Project", [360, 360])
+            WindowSettings::new("YASC Project", layout.window_size())
                 .srgb(true)
                 .samples(4)
                 .vsync(true)
@@ -177,7 +191,7 @@ impl GamePrelude {
     fn get_game_time(&self) -> GameTime {
         GameTime {
             instant:   Instant::now(),
-            song_time: None,
+            song_time: CURRENT_SONG_TIMER.get_current_song_time(),
         }
     }
 
@@ -247,6 +261,7 @@ impl GamePrelude {
         // this segment is just PistonWindow::draw_3d() but repurposed
 
         self.window.make_current();
+        self.resize_main_targets();
 
         // clear window to black
         self.tex_ctx.encoder.clear(&self.output_color, [0., 0., 0., 1.]);
@@ -268,6 +283,26 @@ impl GamePrelude {
 
         self.tex_ctx.encoder.flush(&mut self.device);
     }
+
+    // makes the targets of the window again once it has been resized, as
+    // PistonWindow::draw_3d() would have
+    fn resize_main_targets(&mut self) {
+        let size = self.window.draw_size();
+        let (width, height, _, samples) = self.output_stencil.get_dimensions();
+
+        if width == size.width as u16 && height == size.height as u16 {
+            return;
+        }
+
+        let (color, stencil) = gfx_device_gl::create_main_targets_raw(
+            (size.width as u16, size.height as u16, 1, samples),
+            <Srgba8 as Formatted>::get_format().0,
+            <DepthStencil as Formatted>::get_format().0,
+        );
+
+        self.output_color = Typed::new(color);
+        self.output_stencil = Typed::new(stencil);
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
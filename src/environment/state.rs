@@ -1,4 +1,14 @@
 use crate::{
+    audio::{
+        music::Music,
+        output::{
+            AudioOutput,
+            NullOutput,
+            RodioOutput,
+        },
+        song::SongAudio,
+    },
+    chart::Chart,
     environment::{
         actor_wrapper::{
             ActorWrapper,
@@ -10,17 +20,42 @@ use crate::{
             UpdatePayload,
             WrappedAddr,
         },
+        autoplay::Autoplay,
         key_bindings::{
             BindRoles,
             ComposedKeystroke,
+            GeneralizedKeystroke,
         },
+        layout::Layout,
         update_routine::CanBeWindowHandled as _,
         RenderWindowParts,
     },
-    song_player::governor::{
-        LGInitRequest,
-        LaneGovernor,
+    replay::{
+        Replay,
+        ReplayPlayer,
+    },
+    score::{
+        lamp::PlayResult,
+        record::{
+            chart_key,
+            BestLamps,
+        },
+    },
+    song_player::{
+        governor::{
+            LGInitRequest,
+            LGRenderPayload,
+            LaneGovernor,
+        },
+        session::{
+            PlayInput,
+            PlaySettings,
+            SongSession,
+        },
+        song_timer::SongTime,
+        tempo_map::TempoMap,
     },
+    utils::block_fn,
 };
 use bidir_map::BidirMap;
 use futures::future::Future as _;
@@ -28,23 +63,279 @@ use piston_window::{
     Button,
     Input,
 };
-use std::time::Instant;
+use std::{
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Instant,
+};
 
 ////////////////////////////////////////////////////////////////////////////////
 
+// where the best lamps of the charts are kept
+const BEST_LAMPS_PATH: &str = "best_lamps.json";
+
+// where the replays of the songs played are kept
+const REPLAYS_DIR: &str = "replays";
+
+// the song starts at least this long before its first measure, so there's time
+// to get ready
+const LEAD_IN_MS: f64 = 1500.;
+
 pub struct GameState {
     keybindings: BidirMap<BindRoles, ComposedKeystroke>,
     state: StateEnum,
     buttons_pressed: Vec<(Button, Instant)>,
+    best_lamps: BestLamps,
 }
 
 impl GameState {
     pub fn start() -> GameState {
+        let best_lamps = BestLamps::load(BEST_LAMPS_PATH).unwrap_or_else(|e| {
+            eprintln!("Failed to load {}: {}", BEST_LAMPS_PATH, e);
+            BestLamps::default()
+        });
+
         GameState {
             // TODO: should be read from a config file
             keybindings: BindRoles::default_keyboard_binding(),
             state: StateEnum::Uninitialized,
             buttons_pressed: Vec::with_capacity(8),
+            best_lamps,
+        }
+    }
+
+    /// Leaves the song for its results. The play is kept as a replay, and its
+    /// lamp is kept if it's the best one of the chart.
+    ///
+    /// Nothing is kept if the song wasn't played by the player.
+    fn finish_song(
+        &mut self,
+        key: String,
+        result: PlayResult,
+        replay: Option<Replay>,
+    )
+    {
+        if let Some(replay) = replay.as_ref() {
+            save_replay(replay);
+        }
+
+        // the songs slowed down for practice don't count for the lamps
+        let is_full_speed =
+            replay.as_ref().map_or(false, |r| 1. <= r.settings.rate);
+        let is_new_best =
+            is_full_speed && self.best_lamps.update(&key, result.lamp);
+
+        if is_new_best {
+            if let Err(e) = self.best_lamps.save(BEST_LAMPS_PATH) {
+                eprintln!("Failed to save {}: {}", BEST_LAMPS_PATH, e);
+            }
+        }
+
+        self.state = StateEnum::Results {
+            result,
+            is_new_best,
+        };
+    }
+
+    /// Keeps track of the buttons being held. Returns the role of the button
+    /// that has just been pressed or released, along with whether it was
+    /// pressed.
+    fn handle_input(
+        &mut self,
+        input: &Input,
+        instant: Instant,
+    ) -> Option<(BindRoles, bool)>
+    {
+        use piston_window::ButtonState;
+
+        let b = match input {
+            Input::Button(b) => b,
+            _ => return None,
+        };
+
+        let is_held = self.buttons_pressed.iter().any(|x| x.0 == b.button);
+
+        if b.state == ButtonState::Press {
+            // ignore the repeats of a held key
+            if is_held {
+                return None;
+            }
+
+            self.buttons_pressed.push((b.button.clone(), instant));
+            self.role_of(&b.button).map(|role| (role, true))
+        }
+        else if is_held {
+            self.buttons_pressed.retain(|x| x.0 != b.button);
+            self.role_of(&b.button).map(|role| (role, false))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns the role bound to the given button, if any
+    fn role_of(
+        &self,
+        button: &Button,
+    ) -> Option<BindRoles>
+    {
+        let keystroke = GeneralizedKeystroke::from_button(button)?;
+
+        self.keybindings
+            .get_by_second(&ComposedKeystroke::new(keystroke))
+            .cloned()
+    }
+
+    fn load_song(
+        &self,
+        payload: &mut UpdatePayload<()>,
+        ctx: &ContextWrapper<Self>,
+    ) -> StateEnum
+    {
+        // TODO: there's no song selection yet, so the chart is taken from the
+        // command line
+        let mut args = std::env::args().skip(1);
+        let chart = args.find(|arg| !arg.starts_with("--")).and_then(|path| {
+            let chart = Chart::load(&path)
+                .map_err(|e| eprintln!("Failed to load {}: {}", path, e))
+                .ok()?;
+
+            Some((chart, PathBuf::from(path)))
+        });
+        let is_autoplay = std::env::args().any(|arg| arg == "--autoplay");
+        let is_muted = std::env::args().any(|arg| arg == "--null-audio");
+        let layout = Layout::from_args();
+
+        // the song may be slowed down for practice, in percent of its speed
+        let keeps_pitch = std::env::args().any(|arg| arg == "--keep-pitch");
+        let rate = std::env::args()
+            .find(|arg| arg.starts_with("--rate="))
+            .and_then(|arg| arg["--rate=".len() ..].parse::<u32>().ok())
+            .map_or(1., |percent| percent.max(50).min(100) as f32 / 100.);
+        let replay = std::env::args()
+            .find(|arg| arg.starts_with("--replay="))
+            .and_then(|arg| {
+                let path = &arg["--replay=".len() ..];
+
+                Replay::load(path)
+                    .map_err(|e| eprintln!("Failed to load {}: {}", path, e))
+                    .ok()
+            });
+
+        let (lg_init, session, autoplay, replay, audio) = match chart {
+            Some((chart, chart_path)) => {
+                // the song is timed by its music
+                let music = Music::of_chart(&chart, &chart_path);
+                let tempo_map =
+                    TempoMap::from_chart(&chart, music.sample_rate());
+
+                // a replay of another chart, or one timed by another timer,
+                // can't be played back
+                let replay = replay.filter(|replay| {
+                    let is_of_chart = replay.chart_hash == chart.hash() &&
+                        replay.freq == tempo_map.freq();
+                    if !is_of_chart {
+                        eprintln!("The replay is not of the chart");
+                    }
+
+                    is_of_chart
+                });
+
+                let autoplay = if is_autoplay && replay.is_none() {
+                    Some(Autoplay::new(&chart, &tempo_map, &self.keybindings))
+                }
+                else {
+                    None
+                };
+
+                // a replay is played with the settings it was recorded with
+                // TODO: the settings of the player should come from a config
+                // file
+                let settings = replay.as_ref().map_or_else(
+                    || {
+                        PlaySettings {
+                            rate,
+                            ..PlaySettings::default()
+                        }
+                    },
+                    |r| r.settings,
+                );
+
+                let lg_init = LGInitRequest::from_chart(
+                    &chart,
+                    &tempo_map,
+                    &settings,
+                    layout,
+                    &mut payload.tx,
+                    ctx.threadpool().clone(),
+                );
+
+                // the song time goes negative if the chart starts too soon
+                // into the music. it's slower along with the song.
+                let freq = tempo_map.freq();
+                let lead_in = SongTime::from_seconds(
+                    LEAD_IN_MS / 1000. * settings.rate as f64,
+                    freq,
+                );
+                let start =
+                    SongTime(0).min(tempo_map.tick_to_song_time(0) - lead_in);
+
+                // the samples of the chart are next to it
+                let directory =
+                    chart_path.parent().unwrap_or_else(|| Path::new(""));
+                let output = open_output(is_muted);
+                let audio = SongAudio::new(
+                    &chart,
+                    directory,
+                    &tempo_map,
+                    music,
+                    start,
+                    output,
+                );
+                audio.player().set_rate(settings.rate, keeps_pitch);
+
+                let session = SongSession::new(chart, tempo_map, settings);
+
+                (
+                    lg_init,
+                    Some(session),
+                    autoplay,
+                    replay.map(|r| r.player()),
+                    Some(audio),
+                )
+            },
+
+            None => {
+                let lg_init = LGInitRequest::debug_new(
+                    layout,
+                    &mut payload.tx,
+                    ctx.threadpool().clone(),
+                );
+
+                (lg_init, None, None, None, None)
+            },
+        };
+
+        let governor = lg_init
+            .send_then_receive(&mut payload.tx)
+            .unwrap() // can't be cancelled
+            .unwrap() // idk what this is
+            .start_actor(Default::default(), ctx.threadpool().clone());
+
+        // the lanes are ready, so the song can start
+        if let Some(audio) = audio.as_ref() {
+            audio.player().play();
+        }
+
+        StateEnum::Song {
+            governor,
+            session,
+            autoplay,
+            replay,
+            audio,
         }
     }
 }
@@ -59,41 +350,137 @@ impl ActorWrapper for GameState {
     )
     {
         use self::StateEnum::*;
-        use piston_window::ButtonState;
 
-        // update the buttons_pressed
-        if let &Some(Input::Button(b)) = &payload.event {
-            if b.state == ButtonState::Press {
-                self.buttons_pressed.push((
-                    b.button.clone(),
-                    payload.game_time.instant.clone(),
-                ));
-            }
-            else {
-                self.buttons_pressed.retain(|x| x.0 != b.button);
-            }
+        let instant = payload.game_time.instant.clone();
+        let song_time = payload.game_time.song_time;
+
+        // the autoplay hands out its inputs along with the times they happen,
+        // while the inputs of the player happen now. the player is ignored
+        // while the autoplay or a replay is playing.
+        let inputs = match (&mut self.state, song_time) {
+            (
+                Song {
+                    autoplay: Some(autoplay),
+                    ..
+                },
+                Some(time),
+            ) => {
+                autoplay
+                    .poll(time)
+                    .into_iter()
+                    .map(|(time, input)| (Some(time), input))
+                    .collect()
+            },
+
+            (
+                Song {
+                    autoplay: Some(_),
+                    ..
+                },
+                None,
+            )
+            | (
+                Song {
+                    replay: Some(_),
+                    ..
+                },
+                _,
+            ) => vec![],
+
+            _ => payload.event.iter().map(|i| (None, i.clone())).collect(),
+        };
+
+        // update the buttons_pressed. the roles of the buttons that have just
+        // been pressed or released are kept for the song.
+        let actions = inputs
+            .iter()
+            .filter_map(|(time, input)| {
+                let (role, is_press) = self.handle_input(input, instant)?;
+
+                let input = if is_press {
+                    PlayInput::Press(role)
+                }
+                else {
+                    PlayInput::Release(role)
+                };
+
+                Some((*time, input))
+            })
+            .collect::<Vec<_>>();
+
+        // the lanes make their targets again at the new size of the window
+        if let (Some(Input::Resize(..)), Song { governor, .. }) =
+            (&payload.event, &mut self.state)
+        {
+            let resized = governor.send(payload.another(()));
+            block_fn(|| resized.wait()).unwrap();
         }
 
+        // the song that has just ended, if any
+        let mut finished = None;
+
         match &mut self.state {
-            Song(lg_addr) => {}, // unimplemented
+            Song {
+                session: Some(session),
+                autoplay,
+                replay,
+                audio,
+                ..
+            } => {
+                // nothing can be judged until the song has started
+                if let Some(now) = song_time {
+                    // the inputs of the player are taken as late as their
+                    // offset says
+                    let freq = session.tempo_map().freq();
+                    let late = now - session.settings().input_offset(freq);
+                    let mut actions = actions
+                        .into_iter()
+                        .map(|(time, input)| (time.unwrap_or(late), input))
+                        .collect::<Vec<_>>();
+
+                    if let Some(replay) = replay.as_mut() {
+                        actions.extend(replay.poll(now));
+                    }
+
+                    for (time, input) in actions.into_iter() {
+                        session.input(input, time);
+                    }
+
+                    session.update(now);
+
+                    if let Some(audio) = audio.as_mut() {
+                        audio.sync(session);
+                    }
+                }
+
+                if let Some(result) = session.result() {
+                    let key = chart_key(session.chart());
+
+                    // only the plays of the player are kept
+                    let is_played = autoplay.is_none() && replay.is_none();
+                    let replay = if is_played {
+                        Some(Replay::from_session(session))
+                    }
+                    else {
+                        None
+                    };
+
+                    finished = Some((key, result.clone(), replay));
+                }
+            },
 
             Uninitialized => {
                 // if not initialized yet, initialize to the song state
                 // TODO: we don't initialize to the song state too fast.
-                let lg_addr = LGInitRequest::debug_new(
-                        &mut payload.tx,
-                        ctx.threadpool().clone(),
-                    )
-                    .send_then_receive(&mut payload.tx)
-                    .unwrap() // can't be cancelled
-                    .unwrap() // idk what this is
-                    .start_actor(Default::default(), ctx.threadpool().clone());
-
-                self.state = Song(lg_addr);
+                self.state = self.load_song(&mut payload, ctx);
             },
 
             _ => {},
         }
+
+        if let Some((key, result, replay)) = finished {
+            self.finish_song(key, result, replay);
+        }
     }
 }
 
@@ -117,7 +504,29 @@ impl RenderableActorWrapper for GameState {
             SE::TitleScreen => GSRD::TitleScreen,
             SE::Settings => GSRD::Settings,
             SE::SongSelection => GSRD::SongSelection,
-            SE::Song(ref mut addr) => GSRD::Song(addr.send(payload)),
+            SE::Song {
+                ref mut governor,
+                session,
+                ..
+            } => {
+                let lanes = session.as_ref().map_or_else(
+                    LGRenderPayload::default,
+                    |session| {
+                        let judgment = session.judgment();
+
+                        LGRenderPayload {
+                            spin:     session.current_spin().cloned(),
+                            bt_holds: judgment.bt_holds().hit_types(),
+                            fx_holds: judgment.fx_holds().hit_types(),
+                        }
+                    },
+                );
+
+                GSRD::Song(governor.send(payload.set_payload(lanes)))
+            },
+            SE::Results {
+                ..
+            } => GSRD::Results,
         }
     }
 }
@@ -134,6 +543,8 @@ pub enum GameStateRenderDetails {
     SongSelection,
 
     Song(RenderResponseFuture<LaneGovernor>),
+
+    Results,
 }
 
 impl RenderDetails for GameStateRenderDetails {
@@ -160,5 +571,60 @@ pub enum StateEnum {
     TitleScreen,
     Settings,
     SongSelection,
-    Song(WrappedAddr<LaneGovernor>),
+    Song {
+        governor: WrappedAddr<LaneGovernor>,
+
+        // there's no session if the song is only for show
+        session: Option<SongSession>,
+
+        // plays the song in place of the player
+        autoplay: Option<Autoplay>,
+
+        // plays back the inputs of a replay in place of the player
+        replay: Option<ReplayPlayer>,
+
+        // drives the song timer. the song doesn't move without it.
+        audio: Option<SongAudio>,
+    },
+    Results {
+        result: PlayResult,
+
+        // whether the lamp is the best one of the chart so far
+        is_new_best: bool,
+    },
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Saves a replay under the hash of its chart and the time it was saved
+fn save_replay(replay: &Replay) {
+    let name = format!(
+        "{:016x}-{}.ysr",
+        replay.chart_hash,
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    );
+    let path = PathBuf::from(REPLAYS_DIR).join(name);
+
+    let saved = fs::create_dir_all(REPLAYS_DIR)
+        .map_err(Into::into)
+        .and_then(|_| replay.save(&path));
+    if let Err(e) = saved {
+        eprintln!("Failed to save {}: {}", path.display(), e);
+    }
+}
+
+/// Opens the audio device, or an output that plays nothing if there's none or
+/// if it's not wanted
+fn open_output(is_muted: bool) -> Box<dyn AudioOutput> {
+    if !is_muted {
+        match RodioOutput::new() {
+            Ok(output) => return Box::new(output),
+            Err(e) => eprintln!("Failed to open the audio device: {}", e),
+        }
+    }
+
+    // the song still has to move on without being heard
+    let output = NullOutput::new();
+    output.start_clock();
+    Box::new(output)
 }
@@ -0,0 +1,270 @@
+use crate::{
+    chart::Interval,
+    judgment::{
+        Judgment,
+        JudgmentWindows,
+        SongTimeWindows,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Judges the chips of a set of lanes
+#[derive(Debug, Clone)]
+pub struct ChipJudge {
+    windows: SongTimeWindows,
+    lanes:   Vec<ChipLane>,
+}
+
+#[derive(Debug, Clone)]
+struct ChipLane {
+    chips: Vec<ChipState>,
+
+    // every chip before this one has been judged
+    next: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChipState {
+    pub time:   SongTime,
+    pub result: Option<Judgment>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl ChipJudge {
+    /// Creates the judge from the notes of each lane. Holds are skipped.
+    pub fn new(
+        lanes: &[Vec<Interval>],
+        tempo_map: &TempoMap,
+        windows: &JudgmentWindows,
+    ) -> ChipJudge
+    {
+        let lanes = lanes
+            .iter()
+            .map(|notes| {
+                notes
+                    .iter()
+                    .filter(|note| note.is_chip())
+                    .map(|note| tempo_map.tick_to_song_time(note.tick))
+                    .collect()
+            })
+            .collect();
+
+        ChipJudge::from_times(lanes, windows.to_song_time(tempo_map.freq()))
+    }
+
+    /// Creates the judge from the sorted times of the chips of each lane
+    pub fn from_times(
+        lanes: Vec<Vec<SongTime>>,
+        windows: SongTimeWindows,
+    ) -> ChipJudge
+    {
+        let lanes = lanes
+            .into_iter()
+            .map(|times| {
+                ChipLane {
+                    chips: times
+                        .into_iter()
+                        .map(|time| {
+                            ChipState {
+                                time,
+                                result: None,
+                            }
+                        })
+                        .collect(),
+                    next:  0,
+                }
+            })
+            .collect();
+
+        ChipJudge {
+            windows,
+            lanes,
+        }
+    }
+
+    pub fn lane(
+        &self,
+        lane: usize,
+    ) -> &[ChipState]
+    {
+        &self.lanes[lane].chips
+    }
+
+    pub fn lane_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Judges a press against the nearest unjudged chip of the lane. Returns
+    /// the time of the chip and its judgment, if there's a chip close enough.
+    pub fn press(
+        &mut self,
+        lane: usize,
+        time: SongTime,
+    ) -> Option<(SongTime, Judgment)>
+    {
+        let windows = self.windows;
+        let lane = self.lanes.get_mut(lane)?;
+
+        // only the chips within the error window are candidates. since the
+        // chips are sorted, we can stop once we're past the window.
+        let nearest = lane.chips[lane.next ..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, chip)| chip.time <= time + windows.error)
+            .filter(|(_, chip)| chip.result.is_none())
+            .filter(|(_, chip)| time - chip.time <= windows.error)
+            .min_by_key(|(_, chip)| (time - chip.time).0.abs())
+            .map(|(idx, _)| lane.next + idx)?;
+
+        let chip = &mut lane.chips[nearest];
+        let judgment = windows.judge(time - chip.time)?;
+        chip.result = Some(judgment);
+
+        Some((chip.time, judgment))
+    }
+
+    /// Marks the chips that have gone past the near window unhit as errors.
+    /// Returns the lane and time of each of them.
+    pub fn advance(
+        &mut self,
+        time: SongTime,
+    ) -> Vec<(usize, SongTime)>
+    {
+        let near = self.windows.near;
+        let mut missed = vec![];
+
+        for (idx, lane) in self.lanes.iter_mut().enumerate() {
+            while let Some(chip) = lane.chips.get_mut(lane.next) {
+                if time <= chip.time + near {
+                    break;
+                }
+
+                if chip.result.is_none() {
+                    chip.result = Some(Judgment::Error);
+                    missed.push((idx, chip.time));
+                }
+
+                lane.next += 1;
+            }
+        }
+
+        // keep the misses in the order they happened
+        missed.sort_by_key(|(_, time)| *time);
+        missed
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.lanes.iter().all(|lane| {
+            lane.chips[lane.next ..].iter().all(|c| c.result.is_some())
+        })
+    }
+
+    /// Returns the number of chips in all lanes
+    pub fn total(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.chips.len()).sum()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judgment::Timing;
+
+    // on a timer of a thousand frames a second, so the windows are of 46, 92
+    // and 150 frames
+    fn chip_judge(lanes: Vec<Vec<i64>>) -> ChipJudge {
+        let lanes = lanes
+            .into_iter()
+            .map(|times| times.into_iter().map(SongTime).collect())
+            .collect();
+
+        let windows = JudgmentWindows::default().to_song_time(1000);
+        ChipJudge::from_times(lanes, windows)
+    }
+
+    fn press_once(time: i64) -> Option<Judgment> {
+        chip_judge(vec![vec![1000]])
+            .press(0, SongTime(time))
+            .map(|(_, judgment)| judgment)
+    }
+
+    #[test]
+    fn presses_the_nearest_chip() {
+        let mut judge = chip_judge(vec![vec![1000, 1100], vec![1060]]);
+
+        assert_eq!(
+            judge.press(0, SongTime(1060)),
+            Some((SongTime(1100), Judgment::Critical))
+        );
+        assert_eq!(
+            judge.press(0, SongTime(1070)),
+            Some((SongTime(1000), Judgment::Near(Timing::Late)))
+        );
+
+        // the chips are only hit once, and only in their own lane
+        assert_eq!(judge.press(0, SongTime(1060)), None);
+        assert_eq!(
+            judge.press(1, SongTime(1060)),
+            Some((SongTime(1060), Judgment::Critical))
+        );
+    }
+
+    #[test]
+    fn judges_at_the_edges_of_the_windows() {
+        let early = Judgment::Near(Timing::Early);
+        let late = Judgment::Near(Timing::Late);
+
+        assert_eq!(press_once(1000), Some(Judgment::Critical));
+        assert_eq!(press_once(954), Some(Judgment::Critical));
+        assert_eq!(press_once(1046), Some(Judgment::Critical));
+
+        assert_eq!(press_once(953), Some(early));
+        assert_eq!(press_once(908), Some(early));
+        assert_eq!(press_once(1047), Some(late));
+        assert_eq!(press_once(1092), Some(late));
+
+        assert_eq!(press_once(907), Some(Judgment::Error));
+        assert_eq!(press_once(850), Some(Judgment::Error));
+        assert_eq!(press_once(1093), Some(Judgment::Error));
+
+        // too early to be for the chip at all
+        assert_eq!(press_once(849), None);
+    }
+
+    #[test]
+    fn misses_the_chips_that_scroll_past() {
+        let mut judge = chip_judge(vec![vec![1000, 2000, 3000], vec![1500]]);
+        judge.press(0, SongTime(3000));
+
+        // the chips can still be hit up to the end of the near window
+        assert!(judge.advance(SongTime(1092)).is_empty());
+        assert_eq!(judge.advance(SongTime(1093)), vec![(0, SongTime(1000))]);
+        assert!(judge.press(0, SongTime(1093)).is_none());
+
+        assert!(!judge.is_finished());
+        assert_eq!(judge.advance(SongTime(4000)), vec![
+            (1, SongTime(1500)),
+            (0, SongTime(2000)),
+        ]);
+        assert!(judge.is_finished());
+
+        let results = judge
+            .lane(0)
+            .iter()
+            .map(|chip| chip.result)
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![
+            Some(Judgment::Error),
+            Some(Judgment::Error),
+            Some(Judgment::Critical),
+        ]);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    chart::Interval,
+    judgment::{
+        chain_ticks,
+        Judgment,
+        JudgmentWindows,
+        SongTimeWindows,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LongHitType {
+    Incoming,
+    Active,
+    Miss,
+    Cleared,
+}
+
+/// Judges the holds of a set of lanes.
+///
+/// A hold earns a chain tick every so often while it's held down. Every tick
+/// that passes while the hold isn't held is an error.
+#[derive(Debug, Clone)]
+pub struct HoldJudge {
+    windows: SongTimeWindows,
+    lanes:   Vec<HoldLane>,
+}
+
+#[derive(Debug, Clone)]
+struct HoldLane {
+    holds: Vec<HoldState>,
+
+    // every hold before this one is over
+    next: usize,
+
+    is_held: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HoldState {
+    start: SongTime,
+    end:   SongTime,
+
+    ticks:     Vec<SongTime>,
+    next_tick: usize,
+
+    hit_type: LongHitType,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl HoldJudge {
+    /// Creates the judge from the notes of each lane. Chips are skipped.
+    pub fn new(
+        lanes: &[Vec<Interval>],
+        tempo_map: &TempoMap,
+        windows: &JudgmentWindows,
+    ) -> HoldJudge
+    {
+        let lanes = lanes
+            .iter()
+            .map(|notes| {
+                HoldLane {
+                    holds:   notes
+                        .iter()
+                        .filter(|note| !note.is_chip())
+                        .map(|note| HoldState::new(note, tempo_map))
+                        .collect(),
+                    next:    0,
+                    is_held: false,
+                }
+            })
+            .collect();
+
+        HoldJudge {
+            windows: windows.to_song_time(tempo_map.freq()),
+            lanes,
+        }
+    }
+
+    pub fn lane(
+        &self,
+        lane: usize,
+    ) -> &[HoldState]
+    {
+        &self.lanes[lane].holds
+    }
+
+    /// Returns how far along each of the holds is, by lane
+    pub fn hit_types(&self) -> Vec<Vec<LongHitType>> {
+        self.lanes
+            .iter()
+            .map(|lane| lane.holds.iter().map(HoldState::hit_type).collect())
+            .collect()
+    }
+
+    pub fn press(
+        &mut self,
+        lane: usize,
+        time: SongTime,
+    )
+    {
+        let near = self.windows.near;
+
+        if let Some(lane) = self.lanes.get_mut(lane) {
+            lane.is_held = true;
+
+            if let Some(hold) = lane.holds.get_mut(lane.next) {
+                if hold.start - near <= time && time < hold.end {
+                    hold.hit_type = LongHitType::Active;
+                }
+            }
+        }
+    }
+
+    pub fn release(
+        &mut self,
+        lane: usize,
+        _time: SongTime,
+    )
+    {
+        if let Some(lane) = self.lanes.get_mut(lane) {
+            lane.is_held = false;
+
+            if let Some(hold) = lane.holds.get_mut(lane.next) {
+                if hold.hit_type == LongHitType::Active {
+                    hold.hit_type = LongHitType::Miss;
+                }
+            }
+        }
+    }
+
+    /// Judges all of the ticks up to the given time. Returns the lane, the time
+    /// and the judgment of each tick.
+    pub fn advance(
+        &mut self,
+        time: SongTime,
+    ) -> Vec<(usize, SongTime, Judgment)>
+    {
+        let near = self.windows.near;
+        let mut judged = vec![];
+
+        for (idx, lane) in self.lanes.iter_mut().enumerate() {
+            while let Some(hold) = lane.holds.get_mut(lane.next) {
+                // a button that's been held since before the hold came also
+                // counts
+                if lane.is_held && hold.start - near <= time {
+                    hold.hit_type = LongHitType::Active;
+                }
+
+                // a hold that hasn't been pressed yet can still be caught a
+                // little late
+                let judged_until = if hold.hit_type == LongHitType::Incoming {
+                    time - near
+                }
+                else {
+                    time
+                };
+
+                while let Some(&tick) = hold.ticks.get(hold.next_tick) {
+                    if judged_until < tick {
+                        break;
+                    }
+
+                    let judgment = match hold.hit_type {
+                        LongHitType::Active => Judgment::Critical,
+                        _ => {
+                            hold.hit_type = LongHitType::Miss;
+                            Judgment::Error
+                        },
+                    };
+
+                    judged.push((idx, tick, judgment));
+                    hold.next_tick += 1;
+                }
+
+                if time < hold.end || hold.next_tick < hold.ticks.len() {
+                    break;
+                }
+
+                if hold.hit_type == LongHitType::Active {
+                    hold.hit_type = LongHitType::Cleared;
+                }
+
+                lane.next += 1;
+            }
+        }
+
+        judged.sort_by_key(|(_, time, _)| *time);
+        judged
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.lanes.iter().all(|lane| lane.next == lane.holds.len())
+    }
+
+    /// Returns the number of ticks in all of the holds
+    pub fn total_ticks(&self) -> usize {
+        self.lanes
+            .iter()
+            .flat_map(|lane| lane.holds.iter())
+            .map(|hold| hold.ticks.len())
+            .sum()
+    }
+}
+
+impl HoldState {
+    fn new(
+        note: &Interval,
+        tempo_map: &TempoMap,
+    ) -> HoldState
+    {
+        let end_tick = note.tick + note.length;
+
+        HoldState {
+            start: tempo_map.tick_to_song_time(note.tick),
+            end: tempo_map.tick_to_song_time(end_tick),
+            ticks: chain_ticks(tempo_map, note.tick, end_tick),
+            next_tick: 0,
+            hit_type: LongHitType::Incoming,
+        }
+    }
+
+    pub fn start(&self) -> SongTime {
+        self.start
+    }
+
+    pub fn end(&self) -> SongTime {
+        self.end
+    }
+
+    pub fn hit_type(&self) -> LongHitType {
+        self.hit_type
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // on a timer of a thousand frames a second
+    fn hold_judge(
+        bpm: f64,
+        hold: Interval,
+    ) -> HoldJudge
+    {
+        let tempo_map = TempoMap::new(&[(0, bpm)], &[], &[], 0, 1000);
+        HoldJudge::new(&[vec![hold]], &tempo_map, &JudgmentWindows::default())
+    }
+
+    // a hold from one second to two, at 120 BPM, so it ticks every 125 frames
+    fn one_second_hold() -> HoldJudge {
+        hold_judge(120., Interval {
+            tick:   480,
+            length: 480,
+        })
+    }
+
+    fn ticks(
+        judged: &[(usize, SongTime, Judgment)],
+        judgment: Judgment,
+    ) -> Vec<i64>
+    {
+        judged
+            .iter()
+            .filter(|(_, _, j)| *j == judgment)
+            .map(|(_, time, _)| time.0)
+            .collect()
+    }
+
+    #[test]
+    fn activates_holds_pressed_near_their_start() {
+        let mut judge = one_second_hold();
+        judge.press(0, SongTime(950));
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Active);
+
+        // a press before the window only counts once the hold comes
+        let mut judge = one_second_hold();
+        judge.press(0, SongTime(900));
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Incoming);
+
+        judge.advance(SongTime(908));
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Active);
+    }
+
+    #[test]
+    fn ticks_at_the_rate_of_the_tempo_map() {
+        let mut judge = one_second_hold();
+        assert_eq!(judge.total_ticks(), 8);
+
+        judge.press(0, SongTime(1000));
+        let judged = judge.advance(SongTime(1500));
+        assert_eq!(ticks(&judged, Judgment::Critical), vec![
+            1000, 1125, 1250, 1375, 1500,
+        ]);
+
+        let judged = judge.advance(SongTime(2000));
+        assert_eq!(ticks(&judged, Judgment::Critical), vec![1625, 1750, 1875]);
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Cleared);
+        assert!(judge.is_finished());
+
+        // fast songs tick every eighth note instead, which is every 100
+        // frames at 300 BPM
+        let judge = hold_judge(300., Interval {
+            tick:   0,
+            length: 960,
+        });
+        let times = judge.lane(0)[0]
+            .ticks
+            .iter()
+            .map(|time| time.0)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![0, 100, 200, 300, 400, 500, 600, 700]);
+    }
+
+    #[test]
+    fn errors_the_ticks_after_an_early_release() {
+        let mut judge = one_second_hold();
+        judge.press(0, SongTime(1000));
+
+        let judged = judge.advance(SongTime(1300));
+        assert_eq!(ticks(&judged, Judgment::Critical), vec![1000, 1125, 1250]);
+
+        judge.release(0, SongTime(1300));
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Miss);
+
+        let judged = judge.advance(SongTime(2000));
+        assert_eq!(ticks(&judged, Judgment::Error), vec![
+            1375, 1500, 1625, 1750, 1875,
+        ]);
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Miss);
+        assert!(judge.is_finished());
+    }
+
+    #[test]
+    fn errors_the_ticks_of_holds_never_pressed() {
+        let mut judge = one_second_hold();
+
+        // the first tick can still be caught until the near window is over
+        assert!(judge.advance(SongTime(1091)).is_empty());
+
+        let judged = judge.advance(SongTime(1092));
+        assert_eq!(ticks(&judged, Judgment::Error), vec![1000]);
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Miss);
+    }
+
+    #[test]
+    fn recovers_when_pressed_again() {
+        let mut judge = one_second_hold();
+        judge.press(0, SongTime(1000));
+        judge.advance(SongTime(1300));
+        judge.release(0, SongTime(1300));
+
+        let judged = judge.advance(SongTime(1400));
+        assert_eq!(ticks(&judged, Judgment::Error), vec![1375]);
+
+        judge.press(0, SongTime(1400));
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Active);
+
+        let judged = judge.advance(SongTime(2000));
+        assert_eq!(ticks(&judged, Judgment::Critical), vec![
+            1500, 1625, 1750, 1875,
+        ]);
+        assert!(ticks(&judged, Judgment::Error).is_empty());
+        assert_eq!(judge.lane(0)[0].hit_type(), LongHitType::Cleared);
+    }
+}
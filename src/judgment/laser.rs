@@ -0,0 +1,724 @@
+use crate::{
+    chart::{
+        GraphValue,
+        LaserSection,
+    },
+    judgment::{
+        chain_ticks,
+        JudgedObject,
+        Judgment,
+        JudgmentEvent,
+        JudgmentWindows,
+        SongTimeWindows,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how fast the cursor moves while a knob is turned, in laser widths per second
+const KNOB_SPEED: f64 = 4.;
+
+// how far the cursor may be from the laser while still being on it
+const CURSOR_TOLERANCE: f64 = 0.1;
+
+// how long the cursor stays locked onto the laser after being turned the right
+// way
+const ASSIST_MS: f64 = 100.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Turn {
+    // towards the right side of the lanes
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Judges the lasers by following them with a cursor for each side.
+///
+/// The knobs move the cursor. Turning them towards where the laser is going
+/// locks the cursor onto the laser for a while, and a laser that isn't moving
+/// keeps a cursor that is already on it. The lasers earn a chain tick every so
+/// often while the cursor is on them.
+///
+/// The slams are judged separately. They only need the knob to be turned their
+/// way around the time they come, after which the cursor is locked onto them.
+#[derive(Debug, Clone)]
+pub struct LaserJudge {
+    freq:    u32,
+    windows: SongTimeWindows,
+    assist:  SongTime,
+    sides:   Vec<LaserSide>,
+}
+
+#[derive(Debug, Clone)]
+struct LaserSide {
+    tracks: Vec<LaserTrack>,
+
+    // every track before this one is over
+    next: usize,
+
+    // only shown while there's a laser to follow
+    cursor: Option<f64>,
+
+    // the times the knob started being turned each way, while it's turned
+    clockwise:         Option<SongTime>,
+    counter_clockwise: Option<SongTime>,
+    locked_until:      Option<SongTime>,
+
+    // the way the knob has just been turned at once, if it has
+    flick: Option<Turn>,
+
+    // the time the side has been advanced up to
+    time: SongTime,
+}
+
+/// A laser section, converted into song time
+#[derive(Debug, Clone, PartialEq)]
+struct LaserTrack {
+    points: Vec<(SongTime, GraphValue)>,
+
+    ticks:     Vec<SongTime>,
+    next_tick: usize,
+
+    slams:     Vec<SlamState>,
+    next_slam: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SlamState {
+    time: SongTime,
+    turn: Turn,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl LaserJudge {
+    pub fn new(
+        sides: &[Vec<LaserSection>],
+        tempo_map: &TempoMap,
+        windows: &JudgmentWindows,
+    ) -> LaserJudge
+    {
+        let sides = sides
+            .iter()
+            .map(|sections| {
+                LaserSide {
+                    tracks:            sections
+                        .iter()
+                        .map(|section| LaserTrack::new(section, tempo_map))
+                        .collect(),
+                    next:              0,
+                    cursor:            None,
+                    clockwise:         None,
+                    counter_clockwise: None,
+                    locked_until:      None,
+                    flick:             None,
+                    time:              SongTime(i64::min_value()),
+                }
+            })
+            .collect();
+
+        let freq = tempo_map.freq();
+
+        LaserJudge {
+            freq,
+            windows: windows.to_song_time(freq),
+            assist: SongTime::from_seconds(ASSIST_MS / 1000., freq),
+            sides,
+        }
+    }
+
+    /// Returns the position of the cursor of the side, from 0 on the left to
+    /// 1 on the right
+    pub fn cursor(
+        &self,
+        side: usize,
+    ) -> Option<f64>
+    {
+        self.sides.get(side)?.cursor
+    }
+
+    /// Starts turning a knob. The side is advanced to the given time first.
+    pub fn press(
+        &mut self,
+        side: usize,
+        turn: Turn,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        self.set_knob(side, turn, true, time)
+    }
+
+    /// Stops turning a knob. The side is advanced to the given time first.
+    pub fn release(
+        &mut self,
+        side: usize,
+        turn: Turn,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        self.set_knob(side, turn, false, time)
+    }
+
+    /// Turns a knob by the given amount at once, as a knob controller would.
+    /// Turning it by 1 moves the cursor across the whole laser to the right.
+    pub fn turn(
+        &mut self,
+        side: usize,
+        delta: f64,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        if side >= self.sides.len() {
+            return vec![];
+        }
+
+        let turn = match delta {
+            d if d > 0. => Turn::Clockwise,
+            d if d < 0. => Turn::CounterClockwise,
+            _ => return vec![],
+        };
+
+        let mut judged = self.advance_side(side, time);
+
+        {
+            let side = &mut self.sides[side];
+            side.cursor = side.cursor.map(|c| (c + delta).max(0.).min(1.));
+            side.flick = Some(turn);
+        }
+
+        // the turn may have just hit a slam
+        judged.extend(self.advance_side(side, time));
+        self.sides[side].flick = None;
+
+        judged
+    }
+
+    /// Moves the cursors and judges the ticks and slams up to the given time
+    pub fn advance(
+        &mut self,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        let mut judged = vec![];
+
+        for side in 0 .. self.sides.len() {
+            judged.extend(self.advance_side(side, time));
+        }
+
+        judged.sort_by_key(|event| event.time);
+        judged
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.sides.iter().all(|side| side.next == side.tracks.len())
+    }
+
+    /// Returns the number of ticks in all of the lasers
+    pub fn total_ticks(&self) -> usize {
+        self.sides
+            .iter()
+            .flat_map(|side| side.tracks.iter())
+            .map(|track| track.ticks.len())
+            .sum()
+    }
+
+    /// Returns the number of slams in all of the lasers
+    pub fn total_slams(&self) -> usize {
+        self.sides
+            .iter()
+            .flat_map(|side| side.tracks.iter())
+            .map(|track| track.slams.len())
+            .sum()
+    }
+
+    fn set_knob(
+        &mut self,
+        side: usize,
+        turn: Turn,
+        is_held: bool,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        if side >= self.sides.len() {
+            return vec![];
+        }
+
+        // the knob was in its old state up until now
+        let mut judged = self.advance_side(side, time);
+
+        {
+            let side = &mut self.sides[side];
+            let since = if is_held { Some(time) } else { None };
+            match turn {
+                Turn::Clockwise => side.clockwise = since,
+                Turn::CounterClockwise => side.counter_clockwise = since,
+            }
+        }
+
+        // the new state of the knob may have just hit a slam
+        judged.extend(self.advance_side(side, time));
+        judged
+    }
+
+    /// Advances a side up to the given time. It stops at each of the ticks on
+    /// the way, so that they're judged against where the laser and the cursor
+    /// are at their own time.
+    fn advance_side(
+        &mut self,
+        idx: usize,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        let mut judged = vec![];
+
+        while let Some(tick) = self.next_tick(idx, time) {
+            judged.extend(self.step_side(idx, tick));
+        }
+
+        judged.extend(self.step_side(idx, time));
+        judged
+    }
+
+    /// Returns the first tick after the time the side has been advanced to,
+    /// if it's before the given time
+    fn next_tick(
+        &self,
+        idx: usize,
+        time: SongTime,
+    ) -> Option<SongTime>
+    {
+        let side = &self.sides[idx];
+
+        side.tracks[side.next ..]
+            .iter()
+            .flat_map(|track| track.ticks[track.next_tick ..].iter())
+            .find(|&&tick| side.time < tick)
+            .filter(|&&tick| tick < time)
+            .cloned()
+    }
+
+    fn step_side(
+        &mut self,
+        idx: usize,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        let freq = self.freq;
+        let near = self.windows.near;
+        let assist = self.assist;
+        let side = &mut self.sides[idx];
+        let mut judged = vec![];
+
+        if time < side.time {
+            return judged;
+        }
+
+        let last_time = side.time;
+        side.time = time;
+
+        while let Some(track) = side.tracks.get_mut(side.next) {
+            // the cursor only shows up a bit before the laser does
+            if time < track.start() - near {
+                break;
+            }
+
+            let before = track.value_at(last_time);
+            let now = track.value_at(time);
+            let cursor = side.cursor.unwrap_or_else(|| track.start_value());
+
+            // -1 to the left, 1 to the right
+            let knob = side.clockwise.is_some() as i8 -
+                side.counter_clockwise.is_some() as i8;
+            let direction = match now - before {
+                d if d > 0. => 1,
+                d if d < 0. => -1,
+                _ => 0,
+            };
+
+            let elapsed = (time - last_time.max(track.start() - near))
+                .as_seconds(freq);
+            let mut cursor =
+                (cursor + knob as f64 * KNOB_SPEED * elapsed).max(0.).min(1.);
+
+            if knob != 0 && knob == direction {
+                side.locked_until = Some(time + assist);
+            }
+
+            // the flicks are compared with where the laser is about to go
+            if let Some(flick) = side.flick {
+                let ahead = track.value_at(time + assist) - now;
+                let is_along = match flick {
+                    Turn::Clockwise => ahead > 0.,
+                    Turn::CounterClockwise => ahead < 0.,
+                };
+
+                if is_along {
+                    side.locked_until = Some(time + assist);
+                }
+            }
+
+            let is_locked = side.locked_until.map_or(false, |t| time <= t);
+            let is_on_laser = (cursor - now).abs() <= CURSOR_TOLERANCE;
+            if is_locked || (direction == 0 && is_on_laser) {
+                cursor = now;
+            }
+
+            while let Some(slam) = track.slams.get(track.next_slam) {
+                if time < slam.time - near {
+                    break;
+                }
+
+                // the knob has to be turned around the time of the slam. a
+                // knob that has been turned since long before doesn't count.
+                let window = slam.time - near ..= slam.time + near;
+                let turned_at = match slam.turn {
+                    Turn::Clockwise => side.clockwise,
+                    Turn::CounterClockwise => side.counter_clockwise,
+                };
+                let is_flicked =
+                    side.flick == Some(slam.turn) && window.contains(&time);
+                let is_turning = is_flicked ||
+                    turned_at.map_or(false, |at| window.contains(&at));
+
+                let judgment = if is_turning {
+                    // follow the laser through the slam
+                    side.locked_until = Some(slam.time.max(time) + assist);
+                    cursor = now;
+
+                    Judgment::Critical
+                }
+                else if slam.time + near < time {
+                    Judgment::Error
+                }
+                else {
+                    break;
+                };
+
+                judged.push(JudgmentEvent {
+                    time: slam.time,
+                    object: JudgedObject::Slam(idx),
+                    judgment,
+                });
+                track.next_slam += 1;
+            }
+
+            side.cursor = Some(cursor);
+
+            while let Some(&tick) = track.ticks.get(track.next_tick) {
+                if time < tick {
+                    break;
+                }
+
+                let judgment = if (cursor - now).abs() <= CURSOR_TOLERANCE {
+                    Judgment::Critical
+                }
+                else {
+                    Judgment::Error
+                };
+
+                judged.push(JudgmentEvent {
+                    time: tick,
+                    object: JudgedObject::Laser(idx),
+                    judgment,
+                });
+                track.next_tick += 1;
+            }
+
+            // the slam at the end of a laser can still be hit late
+            if time < track.end() || track.next_slam < track.slams.len() {
+                break;
+            }
+
+            side.cursor = None;
+            side.locked_until = None;
+            side.next += 1;
+        }
+
+        judged
+    }
+}
+
+impl LaserTrack {
+    fn new(
+        section: &LaserSection,
+        tempo_map: &TempoMap,
+    ) -> LaserTrack
+    {
+        let points: Vec<_> = section
+            .points
+            .iter()
+            .map(|p| {
+                let tick = section.tick + p.tick;
+                (tempo_map.tick_to_song_time(tick), p.value)
+            })
+            .collect();
+
+        let slams = points
+            .iter()
+            .filter(|(_, value)| value.is_slam())
+            .map(|(time, value)| {
+                SlamState {
+                    time: *time,
+                    turn: if value.v < value.vf {
+                        Turn::Clockwise
+                    }
+                    else {
+                        Turn::CounterClockwise
+                    },
+                }
+            })
+            .collect();
+
+        let end_tick = section.tick + section.last_relative_tick();
+
+        LaserTrack {
+            points,
+            ticks: chain_ticks(tempo_map, section.tick, end_tick),
+            next_tick: 0,
+            slams,
+            next_slam: 0,
+        }
+    }
+
+    fn start(&self) -> SongTime {
+        self.points.first().map_or(SongTime(0), |p| p.0)
+    }
+
+    fn end(&self) -> SongTime {
+        self.points.last().map_or(SongTime(0), |p| p.0)
+    }
+
+    fn start_value(&self) -> f64 {
+        self.points.first().map_or(0.5, |p| p.1.v)
+    }
+
+    /// Returns the position of the laser at the given time. The laser stays
+    /// where it is past either of its ends.
+    fn value_at(
+        &self,
+        time: SongTime,
+    ) -> f64
+    {
+        let idx = self.points.partition_point(|p| p.0 <= time);
+
+        match (idx, self.points.get(idx)) {
+            (0, _) => self.start_value(),
+            (_, None) => self.points[idx - 1].1.vf,
+            (_, Some(next)) => {
+                let prev = &self.points[idx - 1];
+                let progress =
+                    (time - prev.0).0 as f64 / (next.0 - prev.0).0 as f64;
+
+                prev.1.vf + (next.1.v - prev.1.vf) * progress
+            },
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::{
+        GraphPoint,
+        TimeSignature,
+    };
+
+    // a slam to the right at 2 seconds, on a timer of a thousand frames a
+    // second
+    fn slam_judge() -> LaserJudge {
+        let tempo_map = TempoMap::new(
+            &[(0, 120.)],
+            &[(0, TimeSignature {
+                numerator:   4,
+                denominator: 4,
+            })],
+            &[],
+            0,
+            1000,
+        );
+
+        let point = |tick, v, vf| {
+            GraphPoint {
+                tick,
+                value: GraphValue {
+                    v,
+                    vf,
+                },
+            }
+        };
+        let section = LaserSection {
+            tick:   960,
+            points: vec![point(0, 0., 1.), point(240, 1., 1.)],
+            wide:   false,
+        };
+
+        LaserJudge::new(
+            &[vec![section], vec![]],
+            &tempo_map,
+            &JudgmentWindows::default(),
+        )
+    }
+
+    fn slams(events: &[JudgmentEvent]) -> Vec<Judgment> {
+        events
+            .iter()
+            .filter(|event| event.object == JudgedObject::Slam(0))
+            .map(|event| event.judgment)
+            .collect()
+    }
+
+    #[test]
+    fn hits_slams_turned_around_their_time() {
+        let mut judge = slam_judge();
+        let mut events = judge.press(0, Turn::Clockwise, SongTime(1950));
+        events.extend(judge.advance(SongTime(3000)));
+        assert_eq!(slams(&events), vec![Judgment::Critical]);
+
+        let mut judge = slam_judge();
+        let mut events = judge.turn(0, 0.5, SongTime(2050));
+        events.extend(judge.advance(SongTime(3000)));
+        assert_eq!(slams(&events), vec![Judgment::Critical]);
+    }
+
+    #[test]
+    fn misses_slams_turned_long_before() {
+        let mut judge = slam_judge();
+        let mut events = judge.press(0, Turn::Clockwise, SongTime(0));
+        events.extend(judge.advance(SongTime(3000)));
+        assert_eq!(slams(&events), vec![Judgment::Error]);
+
+        // nor do the turns after the slam is over
+        let mut judge = slam_judge();
+        let mut events = judge.advance(SongTime(1000));
+        events.extend(judge.turn(0, 0.5, SongTime(2500)));
+        events.extend(judge.advance(SongTime(3000)));
+        assert_eq!(slams(&events), vec![Judgment::Error]);
+    }
+
+    // a laser going from the left at 1 second to the right at 1.5 seconds and
+    // back to the left at 2 seconds, ticking every 125 frames
+    fn curve_judge() -> LaserJudge {
+        let tempo_map = TempoMap::new(&[(0, 120.)], &[], &[], 0, 1000);
+
+        let point = |tick, v| {
+            GraphPoint {
+                tick,
+                value: GraphValue::new(v),
+            }
+        };
+        let section = LaserSection {
+            tick:   480,
+            points: vec![point(0, 0.), point(240, 1.), point(480, 0.)],
+            wide:   false,
+        };
+
+        LaserJudge::new(
+            &[vec![section], vec![]],
+            &tempo_map,
+            &JudgmentWindows::default(),
+        )
+    }
+
+    fn ticks(events: &[JudgmentEvent]) -> Vec<(i64, Judgment)> {
+        events
+            .iter()
+            .filter(|event| event.object == JudgedObject::Laser(0))
+            .map(|event| (event.time.0, event.judgment))
+            .collect()
+    }
+
+    #[test]
+    fn judges_ticks_at_their_own_time() {
+        // the cursor stays on the left, where the laser is at the ends but not
+        // at any of the ticks in between
+        let mut judge = curve_judge();
+        let coarse = ticks(&judge.advance(SongTime(2000)));
+
+        assert_eq!(coarse[0], (1000, Judgment::Critical));
+        assert_eq!(coarse.len(), 8);
+        assert!(coarse[1 ..].iter().all(|(_, j)| *j == Judgment::Error));
+
+        // which is the same as advancing it a frame at a time
+        let mut judge = curve_judge();
+        let fine = (900 ..= 2000)
+            .flat_map(|time| judge.advance(SongTime(time)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(ticks(&fine), coarse);
+    }
+
+    #[test]
+    fn follows_the_laser_with_the_knob_held() {
+        let mut judge = curve_judge();
+        let mut events = judge.press(0, Turn::Clockwise, SongTime(1000));
+        events.extend(judge.release(0, Turn::Clockwise, SongTime(1500)));
+        events.extend(judge.press(0, Turn::CounterClockwise, SongTime(1500)));
+        events.extend(judge.release(0, Turn::CounterClockwise, SongTime(2000)));
+
+        let judged = ticks(&events);
+        assert_eq!(judged.len(), 8);
+        assert!(judged.iter().all(|(_, j)| *j == Judgment::Critical));
+    }
+
+    #[test]
+    fn misses_ticks_away_from_the_laser() {
+        // the knob is still turned right once the laser goes back left, so
+        // the cursor stays on the right
+        let mut judge = curve_judge();
+        let mut events = judge.press(0, Turn::Clockwise, SongTime(1000));
+        events.extend(judge.advance(SongTime(2000)));
+
+        assert_eq!(ticks(&events), vec![
+            (1000, Judgment::Critical),
+            (1125, Judgment::Critical),
+            (1250, Judgment::Critical),
+            (1375, Judgment::Critical),
+            (1500, Judgment::Critical),
+            (1625, Judgment::Error),
+            (1750, Judgment::Error),
+            (1875, Judgment::Error),
+        ]);
+    }
+
+    #[test]
+    fn locks_onto_the_laser_after_turning_its_way() {
+        let mut judge = curve_judge();
+        judge.advance(SongTime(1000));
+        judge.turn(0, 0.01, SongTime(1000));
+
+        let events = judge.advance(SongTime(1100));
+        assert!(ticks(&events).is_empty());
+        assert!((judge.cursor(0).unwrap() - 0.2).abs() < 1e-9);
+
+        // the lock is over after ASSIST_MS, so the cursor is left behind
+        let events = judge.advance(SongTime(1250));
+        assert_eq!(ticks(&events), vec![
+            (1125, Judgment::Critical),
+            (1250, Judgment::Error),
+        ]);
+        assert!((judge.cursor(0).unwrap() - 0.2).abs() < 1e-9);
+
+        // turning the other way doesn't lock it
+        let mut judge = curve_judge();
+        judge.advance(SongTime(1000));
+        judge.turn(0, -0.01, SongTime(1000));
+        judge.advance(SongTime(1100));
+        assert_eq!(judge.cursor(0), Some(0.));
+    }
+
+    #[test]
+    fn misses_slams_turned_the_other_way() {
+        let mut judge = slam_judge();
+        let mut events = judge.press(0, Turn::CounterClockwise, SongTime(2000));
+        events.extend(judge.advance(SongTime(3000)));
+        assert_eq!(slams(&events), vec![Judgment::Error]);
+    }
+}
@@ -0,0 +1,535 @@
+pub mod chip;
+pub mod hold;
+pub mod laser;
+
+////////////////////////////////////////////////////////////////////////////////
+
+use crate::{
+    chart::{
+        Chart,
+        Tick,
+        TICKS_PER_WHOLE,
+    },
+    environment::key_bindings::BindRoles,
+    score::NoteCounts,
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use self::{
+    chip::ChipJudge,
+    hold::HoldJudge,
+    laser::{
+        LaserJudge,
+        Turn,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the holds and lasers tick every 16th note, or every 8th on fast songs
+const TICK_INTERVAL: Tick = TICKS_PER_WHOLE / 16;
+const FAST_TICK_INTERVAL: Tick = TICKS_PER_WHOLE / 8;
+const FAST_BPM: f64 = 256.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Judgment {
+    Critical,
+    Near(Timing),
+
+    // this includes the misses
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Timing {
+    Early,
+    Late,
+}
+
+/// The kind of object that has been judged, along with its lane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JudgedObject {
+    BtChip(usize),
+    FxChip(usize),
+
+    // a tick of the chain of a hold
+    BtHold(usize),
+    FxHold(usize),
+    Laser(usize),
+
+    Slam(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JudgmentEvent {
+    // the time of the judged object, not of the input
+    pub time:     SongTime,
+    pub object:   JudgedObject,
+    pub judgment: Judgment,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The timing windows, in milliseconds on either side of the note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JudgmentWindows {
+    pub critical_ms: f64,
+    pub near_ms:     f64,
+
+    // presses earlier than the near window but within this one are errors
+    // instead of being ignored
+    pub error_ms: f64,
+}
+
+impl Default for JudgmentWindows {
+    fn default() -> JudgmentWindows {
+        JudgmentWindows {
+            critical_ms: 46.,
+            near_ms:     92.,
+            error_ms:    150.,
+        }
+    }
+}
+
+/// The windows, converted into the ticks of the song timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SongTimeWindows {
+    pub critical: SongTime,
+    pub near:     SongTime,
+    pub error:    SongTime,
+}
+
+impl JudgmentWindows {
+    /// Scales every window by the given factor
+    pub fn scaled(
+        &self,
+        factor: f64,
+    ) -> JudgmentWindows
+    {
+        JudgmentWindows {
+            critical_ms: self.critical_ms * factor,
+            near_ms:     self.near_ms * factor,
+            error_ms:    self.error_ms * factor,
+        }
+    }
+
+    pub fn to_song_time(
+        &self,
+        freq: u32,
+    ) -> SongTimeWindows
+    {
+        let convert = |ms: f64| SongTime::from_seconds(ms / 1000., freq);
+
+        SongTimeWindows {
+            critical: convert(self.critical_ms),
+            near:     convert(self.near_ms),
+            error:    convert(self.error_ms),
+        }
+    }
+}
+
+impl SongTimeWindows {
+    /// Judges an input given its offset from the note. Returns `None` if the
+    /// input is too far from the note to be considered for it.
+    pub fn judge(
+        &self,
+        delta: SongTime,
+    ) -> Option<Judgment>
+    {
+        let timing = if delta.0 < 0 {
+            Timing::Early
+        }
+        else {
+            Timing::Late
+        };
+
+        match delta.0.abs() {
+            d if d <= self.critical.0 => Some(Judgment::Critical),
+            d if d <= self.near.0 => Some(Judgment::Near(timing)),
+            d if d <= self.error.0 => Some(Judgment::Error),
+            _ => None,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Judges all of the notes of a chart against the inputs of the player.
+///
+/// This doesn't need a window. The inputs only need to be timestamped in song
+/// time, and the engine has to be told how far the song has gone so it can
+/// mark the notes that have been missed.
+pub struct JudgmentEngine {
+    bt_chips: ChipJudge,
+    bt_holds: HoldJudge,
+    fx_chips: ChipJudge,
+    fx_holds: HoldJudge,
+    lasers:   LaserJudge,
+}
+
+impl JudgmentEngine {
+    pub fn new(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+        windows: &JudgmentWindows,
+    ) -> JudgmentEngine
+    {
+        JudgmentEngine {
+            bt_chips: ChipJudge::new(&chart.bt, tempo_map, windows),
+            bt_holds: HoldJudge::new(&chart.bt, tempo_map, windows),
+            fx_chips: ChipJudge::new(&chart.fx, tempo_map, windows),
+            fx_holds: HoldJudge::new(&chart.fx, tempo_map, windows),
+            lasers:   LaserJudge::new(&chart.lasers, tempo_map, windows),
+        }
+    }
+
+    pub fn bt_chips(&self) -> &ChipJudge {
+        &self.bt_chips
+    }
+
+    pub fn bt_holds(&self) -> &HoldJudge {
+        &self.bt_holds
+    }
+
+    pub fn fx_chips(&self) -> &ChipJudge {
+        &self.fx_chips
+    }
+
+    pub fn fx_holds(&self) -> &HoldJudge {
+        &self.fx_holds
+    }
+
+    pub fn lasers(&self) -> &LaserJudge {
+        &self.lasers
+    }
+
+    /// Returns the number of objects to be judged
+    pub fn note_counts(&self) -> NoteCounts {
+        NoteCounts {
+            chips: self.bt_chips.total() + self.fx_chips.total(),
+            ticks: self.bt_holds.total_ticks() +
+                self.fx_holds.total_ticks() +
+                self.lasers.total_ticks() +
+                self.lasers.total_slams(),
+        }
+    }
+
+    /// Judges a press of the given button
+    pub fn press(
+        &mut self,
+        role: BindRoles,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        if let Some((side, turn)) = knob_turn(role) {
+            return self.lasers.press(side, turn, time);
+        }
+
+        let (chips, holds, lane, object) = match self.lanes_of(role) {
+            Some(found) => found,
+            None => return vec![],
+        };
+
+        holds.press(lane, time);
+
+        chips
+            .press(lane, time)
+            .map(|(time, judgment)| {
+                JudgmentEvent {
+                    time,
+                    object: object(lane),
+                    judgment,
+                }
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Lets go of the given button. The ticks of the holds are only judged
+    /// once the engine is advanced.
+    pub fn release(
+        &mut self,
+        role: BindRoles,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        if let Some((side, turn)) = knob_turn(role) {
+            return self.lasers.release(side, turn, time);
+        }
+
+        if let Some((_, holds, lane, _)) = self.lanes_of(role) {
+            holds.release(lane, time);
+        }
+
+        vec![]
+    }
+
+    /// Turns the knob of a side by the given amount at once
+    pub fn turn_knob(
+        &mut self,
+        side: usize,
+        delta: f64,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        self.lasers.turn(side, delta, time)
+    }
+
+    /// Judges the ticks of the holds and lasers up to the given time, and marks
+    /// the chips and slams that can no longer be hit as missed
+    pub fn advance(
+        &mut self,
+        time: SongTime,
+    ) -> Vec<JudgmentEvent>
+    {
+        let missed = |object: fn(usize) -> JudgedObject| {
+            move |(lane, time)| {
+                JudgmentEvent {
+                    time,
+                    object: object(lane),
+                    judgment: Judgment::Error,
+                }
+            }
+        };
+
+        let mut events = vec![];
+        events.extend(
+            self.bt_chips
+                .advance(time)
+                .into_iter()
+                .map(missed(JudgedObject::BtChip)),
+        );
+        events.extend(
+            self.fx_chips
+                .advance(time)
+                .into_iter()
+                .map(missed(JudgedObject::FxChip)),
+        );
+        events.extend(
+            self.bt_holds
+                .advance(time)
+                .into_iter()
+                .map(ticked(JudgedObject::BtHold)),
+        );
+        events.extend(
+            self.fx_holds
+                .advance(time)
+                .into_iter()
+                .map(ticked(JudgedObject::FxHold)),
+        );
+        events.extend(self.lasers.advance(time));
+
+        // keep the events in the order they happened. the sort is stable so
+        // the chips still come before the ticks at the same time.
+        events.sort_by_key(|event| event.time);
+        events
+    }
+
+    /// Returns true if all of the notes have been judged
+    pub fn is_finished(&self) -> bool {
+        self.bt_chips.is_finished() &&
+            self.bt_holds.is_finished() &&
+            self.fx_chips.is_finished() &&
+            self.fx_holds.is_finished() &&
+            self.lasers.is_finished()
+    }
+
+    fn lanes_of(
+        &mut self,
+        role: BindRoles,
+    ) -> Option<(
+        &mut ChipJudge,
+        &mut HoldJudge,
+        usize,
+        fn(usize) -> JudgedObject,
+    )>
+    {
+        if let Some(lane) = bt_lane(role) {
+            return Some((
+                &mut self.bt_chips,
+                &mut self.bt_holds,
+                lane,
+                JudgedObject::BtChip,
+            ));
+        }
+
+        if let Some(lane) = fx_lane(role) {
+            return Some((
+                &mut self.fx_chips,
+                &mut self.fx_holds,
+                lane,
+                JudgedObject::FxChip,
+            ));
+        }
+
+        None
+    }
+}
+
+fn ticked(
+    object: fn(usize) -> JudgedObject,
+) -> impl Fn((usize, SongTime, Judgment)) -> JudgmentEvent
+{
+    move |(lane, time, judgment)| {
+        JudgmentEvent {
+            time,
+            object: object(lane),
+            judgment,
+        }
+    }
+}
+
+/// Returns the times of the chain ticks of a hold or a laser going from the
+/// start up to, but not including, the end
+pub(crate) fn chain_ticks(
+    tempo_map: &TempoMap,
+    start: Tick,
+    end: Tick,
+) -> Vec<SongTime>
+{
+    let mut ticks = vec![];
+    let mut tick = start;
+
+    while tick < end {
+        ticks.push(tempo_map.tick_to_song_time(tick));
+
+        tick += if tempo_map.bpm_at(tick as f64) < FAST_BPM {
+            TICK_INTERVAL
+        }
+        else {
+            FAST_TICK_INTERVAL
+        };
+    }
+
+    ticks
+}
+
+pub fn bt_lane(role: BindRoles) -> Option<usize> {
+    match role {
+        BindRoles::BT_A => Some(0),
+        BindRoles::BT_B => Some(1),
+        BindRoles::BT_C => Some(2),
+        BindRoles::BT_D => Some(3),
+        _ => None,
+    }
+}
+
+pub fn fx_lane(role: BindRoles) -> Option<usize> {
+    match role {
+        BindRoles::FX_L => Some(0),
+        BindRoles::FX_R => Some(1),
+        _ => None,
+    }
+}
+
+pub fn knob_turn(role: BindRoles) -> Option<(usize, Turn)> {
+    match role {
+        BindRoles::KN_L_CW => Some((0, Turn::Clockwise)),
+        BindRoles::KN_L_CCW => Some((0, Turn::CounterClockwise)),
+        BindRoles::KN_R_CW => Some((1, Turn::Clockwise)),
+        BindRoles::KN_R_CCW => Some((1, Turn::CounterClockwise)),
+        _ => None,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::Interval;
+
+    #[test]
+    fn judges_by_the_configured_windows() {
+        let windows = JudgmentWindows {
+            critical_ms: 20.,
+            near_ms:     50.,
+            error_ms:    100.,
+        };
+
+        // 48 frames a millisecond
+        let windows = windows.to_song_time(48_000);
+        let judge = |frames: i64| windows.judge(SongTime(frames));
+        let early = Some(Judgment::Near(Timing::Early));
+        let late = Some(Judgment::Near(Timing::Late));
+
+        assert_eq!(judge(-20 * 48), Some(Judgment::Critical));
+        assert_eq!(judge(20 * 48), Some(Judgment::Critical));
+        assert_eq!(judge(-20 * 48 - 1), early);
+        assert_eq!(judge(20 * 48 + 1), late);
+        assert_eq!(judge(-50 * 48), early);
+        assert_eq!(judge(50 * 48), late);
+        assert_eq!(judge(50 * 48 + 1), Some(Judgment::Error));
+        assert_eq!(judge(-100 * 48), Some(Judgment::Error));
+        assert_eq!(judge(100 * 48), Some(Judgment::Error));
+        assert_eq!(judge(100 * 48 + 1), None);
+    }
+
+    // a chip on the first two BT lanes and the left FX lane, at a second and
+    // at two seconds, on a timer of a thousand frames a second
+    fn engine() -> JudgmentEngine {
+        let chip = |tick| {
+            Interval {
+                tick,
+                length: 0,
+            }
+        };
+
+        let mut chart = Chart::default();
+        chart.bpm_changes = vec![(0, 120.)];
+        chart.bt[0] = vec![chip(480)];
+        chart.bt[1] = vec![chip(960)];
+        chart.fx[0] = vec![chip(480)];
+
+        let tempo_map = TempoMap::from_chart(&chart, 1000);
+        JudgmentEngine::new(&chart, &tempo_map, &JudgmentWindows::default())
+    }
+
+    #[test]
+    fn judges_presses_without_a_window() {
+        let mut engine = engine();
+
+        assert_eq!(engine.press(BindRoles::BT_A, SongTime(1030)), vec![
+            JudgmentEvent {
+                time:     SongTime(1000),
+                object:   JudgedObject::BtChip(0),
+                judgment: Judgment::Critical,
+            },
+        ]);
+        assert_eq!(engine.press(BindRoles::FX_L, SongTime(940)), vec![
+            JudgmentEvent {
+                time:     SongTime(1000),
+                object:   JudgedObject::FxChip(0),
+                judgment: Judgment::Near(Timing::Early),
+            },
+        ]);
+
+        // nothing is near enough on the other lanes
+        assert!(engine.press(BindRoles::BT_C, SongTime(1000)).is_empty());
+        assert!(engine.press(BindRoles::BT_B, SongTime(1000)).is_empty());
+    }
+
+    #[test]
+    fn misses_the_chips_left_unhit() {
+        let mut engine = engine();
+        engine.press(BindRoles::BT_A, SongTime(1000));
+
+        assert_eq!(engine.advance(SongTime(1500)), vec![JudgmentEvent {
+            time:     SongTime(1000),
+            object:   JudgedObject::FxChip(0),
+            judgment: Judgment::Error,
+        }]);
+        assert!(!engine.is_finished());
+
+        assert_eq!(engine.advance(SongTime(3000)), vec![JudgmentEvent {
+            time:     SongTime(2000),
+            object:   JudgedObject::BtChip(1),
+            judgment: Judgment::Error,
+        }]);
+        assert!(engine.is_finished());
+    }
+}
@@ -5,14 +5,51 @@ extern crate lazy_static;
 
 ////////////////////////////////////////////////////////////////////////////////
 
+mod audio;
+mod chart;
 mod environment;
+mod judgment;
 mod pipelines;
+mod render;
+mod replay;
+mod score;
 mod song_player;
 mod utils;
 
 ////////////////////////////////////////////////////////////////////////////////
 
 fn main() {
+    // a chart is rendered without ever opening the window
+    let render_to = std::env::args()
+        .find(|arg| arg.starts_with("--render="))
+        .map(|arg| arg["--render=".len() ..].to_owned());
+
+    if let Some(out_path) = render_to {
+        let mut args = std::env::args().skip(1);
+        let chart_path = match args.find(|arg| !arg.starts_with("--")) {
+            Some(path) => path,
+            None => {
+                eprintln!("There's no chart to render");
+                std::process::exit(1);
+            },
+        };
+
+        match render::render(&chart_path, &out_path) {
+            Ok(render) => {
+                println!(
+                    "Rendered {} frames at {} Hz to {} (hash {:016x})",
+                    render.frames, render.sample_rate, out_path, render.hash
+                );
+            },
+            Err(e) => {
+                eprintln!("Failed to render {}: {}", chart_path, e);
+                std::process::exit(1);
+            },
+        }
+
+        return;
+    }
+
     let mut prelude = environment::GamePrelude::new();
     prelude.spin_loop();
 }
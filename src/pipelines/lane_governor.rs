@@ -16,19 +16,32 @@ gfx_pipeline!( LaneGovernorRenderPipeline {
     lasers_texture: gfx::TextureSampler<[f32; 4]> = "laser_texture",
 
     lasers_cutoff: gfx::Global<f32> = "laser_cutoff",
+
+    // how far apart the halves of the lanes are, and how much of the lanes is
+    // shown
+    center_split: gfx::Global<f32> = "center_split",
+    lanes_visibility: gfx::Global<f32> = "lanes_visibility",
 });
 
 gfx_vertex_struct!(Corner {
     // the name must be the same as declared in the glslv file
     vertex_pos: [f32; 2] = "vertex_pos",
+
+    // -1 for the left half of the lanes and 1 for the right half
+    side: f32 = "side",
 });
 
 ////////////////////////////////////////////////////////////////////////////////
 
 impl Corner {
-    pub fn new(vertex_pos: [f32; 2]) -> Corner {
+    pub fn new(
+        vertex_pos: [f32; 2],
+        side: f32,
+    ) -> Corner
+    {
         Corner {
             vertex_pos,
+            side,
         }
     }
 }
@@ -1,3 +1,3 @@
 pub mod lane_governor;
 pub mod lanes;
-//pub mod notes;
+pub mod notes;
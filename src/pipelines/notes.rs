@@ -6,8 +6,13 @@ gfx_pipeline!( note_pipe {
     note_buffer: gfx::VertexBuffer<NoteLocation> = (),
     transform: gfx::Global<[[f32; 4]; 4]> = "transform",
     hi_speed: gfx::Global<f32> = "hi_speed",
+    // the scroll position of the current song time. see ScrollMap.
     song_offset: gfx::Global<f32> = "song_offset",
-    out_color: gfx::RenderTarget<::gfx::format::Srgba8> = "color",
+    out_color: gfx::BlendTarget<::gfx::format::Srgba8> = (
+        "color",
+        gfx::state::ColorMask::all(),
+        gfx::preset::blend::ALPHA,
+    ),
     texture_buffer: gfx::TextureSampler<[f32; 4]> = "raster_texture",
 
     note_graphic_height: gfx::Global<f32> = "note_graphic_height",
@@ -18,6 +23,31 @@ gfx_vertex_struct!(NoteLocation {
     corner_type: i32 = "corner_type",
 });
 
+// everything else that scrolls along with the notes is drawn as plain colored
+// shapes: the FX chips, the holds, the lasers and the lines of the measures
+gfx_pipeline!( scroll_pipe {
+    vbuf: gfx::VertexBuffer<ScrollVertex> = (),
+    transform: gfx::Global<[[f32; 4]; 4]> = "transform",
+    hi_speed: gfx::Global<f32> = "hi_speed",
+    song_offset: gfx::Global<f32> = "song_offset",
+    out_color: gfx::BlendTarget<::gfx::format::Srgba8> = (
+        "color",
+        gfx::state::ColorMask::all(),
+        gfx::preset::blend::ALPHA,
+    ),
+});
+
+gfx_vertex_struct!(ScrollVertex {
+    // the position across the lanes and the scroll position along them
+    scroll_pos: [f32; 2] = "scroll_pos",
+
+    // how far the vertex is moved up the lanes after the hi-speed is applied,
+    // so the chips and the slams keep their height whatever the speed
+    lift: f32 = "lift",
+
+    fill: [f32; 4] = "fill",
+});
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct HostNote {
     pub position: f32,
@@ -34,8 +64,8 @@ impl NoteLocation {
     ) -> [NoteLocation; 4]
     {
         assert!(
-            note_kind > 4,
-            "`note_kind` should have a value from 0 to 4, inclusive!"
+            note_kind < 4,
+            "`note_kind` should have a value from 0 to 3, inclusive!"
         );
 
         let x_pos =
@@ -68,6 +98,9 @@ impl NoteLocation {
 
     /// Returns a vector of NoteLocation and a remapped [Vec; 4] of the inputs
     ///
+    /// The positions are scroll positions, as given by the ScrollMap, and not
+    /// times.
+    ///
     /// The first part of the return is the vector that will be uploaded into
     /// the vertex buffer.
     /// The second part is an ordered vector of the buttons. Their visibility
@@ -79,9 +112,9 @@ impl NoteLocation {
 
         // collect the notes' positions
         let mut flattened_positions = positions
-            .into_iter()
+            .iter()
             .enumerate()
-            .flat_map(|(bt, vec)| core::iter::repeat(bt).zip(vec.into_iter()))
+            .flat_map(|(bt, vec)| core::iter::repeat(bt).zip(vec.iter()))
             .collect::<Vec<_>>();
 
         // sort the notes' ordering
@@ -116,3 +149,30 @@ impl NoteLocation {
         (buffer, indexable)
     }
 }
+
+impl ScrollVertex {
+    /// Returns the corners of a quad, in the order of lower left, lower right,
+    /// upper left and upper right. The upper corners are lifted by `height`.
+    pub fn quad(
+        lower: ([f32; 2], [f32; 2]),
+        upper: ([f32; 2], [f32; 2]),
+        height: f32,
+        fill: [f32; 4],
+    ) -> [ScrollVertex; 4]
+    {
+        let vertex = |scroll_pos, lift| {
+            ScrollVertex {
+                scroll_pos,
+                lift,
+                fill,
+            }
+        };
+
+        [
+            vertex(lower.0, 0.),
+            vertex(lower.1, 0.),
+            vertex(upper.0, height),
+            vertex(upper.1, height),
+        ]
+    }
+}
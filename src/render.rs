@@ -0,0 +1,341 @@
+use crate::{
+    audio::{
+        music::Music,
+        output::NullOutput,
+        song::SongAudio,
+        CHANNELS,
+    },
+    chart::{
+        Chart,
+        ChartLoadError,
+    },
+    environment::{
+        autoplay::Autoplay,
+        key_bindings::{
+            BindRoles,
+            ComposedKeystroke,
+            GeneralizedKeystroke,
+        },
+    },
+    song_player::{
+        governor::CURRENT_SONG_TIMER,
+        session::{
+            PlayInput,
+            PlaySettings,
+            SongSession,
+        },
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use bidir_map::BidirMap;
+use fnv::FnvHasher;
+use piston_window::{
+    ButtonState,
+    Input,
+};
+use std::{
+    fmt,
+    fs::File,
+    hash::Hasher as _,
+    io::{
+        self,
+        BufWriter,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how many frames are rendered between every update of the song. the samples
+// of the chips are only as late as this.
+const RENDER_CHUNK_FRAMES: usize = 64;
+
+// how long the render goes on after both the music and the chart are over
+const RENDER_TAIL_SECONDS: f64 = 2.;
+
+// the samples are written as 16-bit PCM
+const BITS_PER_SAMPLE: u16 = 16;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// What the chart sounds like played by the autoplay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Render {
+    pub frames:      u64,
+    pub sample_rate: u32,
+
+    // a hash of the samples before they're written, which only changes if
+    // something about the sound does
+    pub hash: u64,
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    Chart(ChartLoadError),
+    Io(io::Error),
+}
+
+// writes the samples into a WAV file, filling in its sizes once it's done
+struct WavWriter {
+    writer:  BufWriter<File>,
+    samples: u32,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Plays the chart at the given path by the autoplay, without an audio device,
+/// and writes everything that would have been heard into a WAV file.
+///
+/// The music is played along with the effects of the FX holds, the laser
+/// filters and the samples of the chips. Nothing depends on how fast it's
+/// rendered, so the same chart always renders the same.
+pub fn render<P, Q>(
+    chart_path: P,
+    out_path: Q,
+) -> Result<Render, RenderError>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let chart_path = chart_path.as_ref();
+    let chart = Chart::load(chart_path)?;
+
+    let music = Music::of_chart(&chart, chart_path);
+    let sample_rate = music.sample_rate();
+    let tempo_map = TempoMap::from_chart(&chart, sample_rate);
+
+    let keybindings = BindRoles::default_keyboard_binding();
+    let mut autoplay = Autoplay::new(&chart, &tempo_map, &keybindings);
+
+    // the render starts with the music, unless the chart starts before it
+    let start = SongTime(0).min(tempo_map.tick_to_song_time(0));
+    let tail = SongTime::from_seconds(RENDER_TAIL_SECONDS, sample_rate);
+
+    let output = NullOutput::new();
+    let mut audio = SongAudio::new(
+        &chart,
+        chart_path.parent().unwrap_or_else(|| Path::new("")),
+        &tempo_map,
+        music,
+        start,
+        Box::new(output.clone()),
+    );
+
+    let mut session =
+        SongSession::new(chart, tempo_map, PlaySettings::default());
+    let end = session.end_time().max(audio.player().length()) + tail;
+
+    let mut writer = WavWriter::create(out_path, sample_rate)?;
+    let mut hasher = FnvHasher::default();
+    let mut frames = 0;
+
+    audio.player().play();
+
+    loop {
+        let now = match CURRENT_SONG_TIMER.get_current_song_time() {
+            Some(now) if now < end => now,
+            _ => break,
+        };
+
+        for (time, input) in autoplay.poll(now) {
+            if let Some(input) = play_input(&input, &keybindings) {
+                session.input(input, time);
+            }
+        }
+
+        session.update(now);
+        audio.sync(&session);
+
+        let samples = output.pull(RENDER_CHUNK_FRAMES);
+        if samples.is_empty() {
+            break;
+        }
+
+        for sample in samples.iter() {
+            hasher.write_u32(sample.to_bits());
+        }
+
+        writer.write(&samples)?;
+        frames += (samples.len() / CHANNELS as usize) as u64;
+    }
+
+    writer.finish()?;
+
+    Ok(Render {
+        frames,
+        sample_rate,
+        hash: hasher.finish(),
+    })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl fmt::Display for RenderError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        match self {
+            RenderError::Chart(e) => write!(f, "{}", e),
+            RenderError::Io(e) => {
+                write!(f, "could not write the render: {}", e)
+            },
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+}
+
+impl From<ChartLoadError> for RenderError {
+    fn from(e: ChartLoadError) -> RenderError {
+        RenderError::Chart(e)
+    }
+}
+
+impl From<io::Error> for RenderError {
+    fn from(e: io::Error) -> RenderError {
+        RenderError::Io(e)
+    }
+}
+
+impl WavWriter {
+    fn create<P>(
+        path: P,
+        sample_rate: u32,
+    ) -> io::Result<WavWriter>
+    where
+        P: AsRef<Path>,
+    {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        // the sizes are filled in once the samples are all written
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            writer,
+            samples: 0,
+        })
+    }
+
+    fn write(
+        &mut self,
+        samples: &[f32],
+    ) -> io::Result<()>
+    {
+        for sample in samples.iter() {
+            let sample = (sample.max(-1.).min(1.) * i16::max_value() as f32)
+                .round() as i16;
+
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let data_size = self.samples * BITS_PER_SAMPLE as u32 / 8;
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+
+        self.writer.flush()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Turns an input of the autoplay back into the role it was made for
+fn play_input(
+    input: &Input,
+    keybindings: &BidirMap<BindRoles, ComposedKeystroke>,
+) -> Option<PlayInput>
+{
+    let args = match input {
+        Input::Button(args) => args,
+        _ => return None,
+    };
+
+    let keystroke = GeneralizedKeystroke::from_button(&args.button)?;
+    let role = *keybindings.get_by_second(&ComposedKeystroke::new(keystroke))?;
+
+    match args.state {
+        ButtonState::Press => Some(PlayInput::Press(role)),
+        ButtonState::Release => Some(PlayInput::Release(role)),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::SILENCE_SAMPLE_RATE,
+        song_player::governor::SONG_TIMER_LOCK,
+    };
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn render_to_temp(
+        chart: &str,
+        out: &str,
+    ) -> Render
+    {
+        let _lock = SONG_TIMER_LOCK.lock();
+        let out_path = std::env::temp_dir().join(out);
+
+        let render = render(fixture(chart), &out_path).unwrap();
+        std::fs::remove_file(&out_path).unwrap();
+        render
+    }
+
+    #[test]
+    fn renders_the_same_every_time() {
+        let first = render_to_temp("chart.ksh", "yasc_render_first.wav");
+        let second = render_to_temp("chart.ksh", "yasc_render_second.wav");
+
+        assert!(0 < first.frames);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn renders_silence_to_a_pinned_hash() {
+        let render = render_to_temp("silence.ksh", "yasc_render_silence.wav");
+
+        // the music is missing, so it's four seconds of silence: two of the
+        // music and two of the tail, rounded up to a whole chunk. the hash is
+        // of nothing but zeros.
+        assert_eq!(render.sample_rate, SILENCE_SAMPLE_RATE);
+        assert_eq!(render.frames, 176_448);
+        assert_eq!(render.hash, 0x41a4_f2e0_7eff_eb25);
+    }
+}
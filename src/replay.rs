@@ -0,0 +1,635 @@
+use crate::{
+    chart::Chart,
+    environment::key_bindings::BindRoles,
+    judgment::JudgmentWindows,
+    score::gauge::{
+        GaugeOptions,
+        GaugeType,
+    },
+    song_player::{
+        session::{
+            PlayInput,
+            PlaySettings,
+            SongSession,
+        },
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use std::{
+    fmt,
+    fs,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+const MAGIC: &[u8; 4] = b"YSCR";
+// the first version has no rate, since every song was played at full speed
+const VERSION: u16 = 2;
+
+// the roles by the order they're written in
+const ROLES: [BindRoles; 12] = [
+    BindRoles::BT_A,
+    BindRoles::BT_B,
+    BindRoles::BT_C,
+    BindRoles::BT_D,
+    BindRoles::FX_L,
+    BindRoles::FX_R,
+    BindRoles::KN_L_CW,
+    BindRoles::KN_L_CCW,
+    BindRoles::KN_R_CW,
+    BindRoles::KN_R_CCW,
+    BindRoles::START,
+    BindRoles::BACK,
+];
+
+// the kinds of inputs
+const PRESS: u8 = 0;
+const RELEASE: u8 = 1;
+const KNOB: u8 = 2;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The inputs of a play, along with everything needed to play them back.
+///
+/// The file starts with a header holding the chart hash, the settings and the
+/// claimed scores. The inputs follow, each with its time written as the
+/// difference from the input before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub chart_hash: u64,
+
+    // the frequency of the song timer the times are counted in
+    pub freq:     u32,
+    pub settings: PlaySettings,
+
+    pub score:    u32,
+    pub ex_score: u64,
+
+    pub inputs: Vec<(SongTime, PlayInput)>,
+}
+
+/// Hands out the inputs of a replay as the song goes on
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer {
+    inputs: Vec<(SongTime, PlayInput)>,
+
+    // every input before this one has been handed out
+    next: usize,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    NotAReplay,
+    UnsupportedVersion(u16),
+    Corrupt,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Replay {
+    /// Takes the inputs and the score of a session so far
+    pub fn from_session(session: &SongSession) -> Replay {
+        Replay {
+            chart_hash: session.chart().hash(),
+            freq:       session.tempo_map().freq(),
+            settings:   *session.settings(),
+            score:      session.score().score(),
+            ex_score:   session.score().ex_score(),
+            inputs:     session.inputs().to_vec(),
+        }
+    }
+
+    pub fn load<P>(path: P) -> Result<Replay, ReplayError>
+    where P: AsRef<Path> {
+        let bytes = fs::read(path)?;
+        Replay::read(&mut bytes.as_slice())
+    }
+
+    pub fn save<P>(
+        &self,
+        path: P,
+    ) -> Result<(), ReplayError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut bytes = vec![];
+        self.write(&mut bytes)?;
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+
+    pub fn write<W>(
+        &self,
+        w: &mut W,
+    ) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let settings = &self.settings;
+        let (gauge_type, gauge_level) = match settings.gauge.gauge_type {
+            GaugeType::Effective => (0, 0),
+            GaugeType::Excessive => (1, 0),
+            GaugeType::Permissive(level) => (2, level),
+            GaugeType::Blastive(level) => (3, level),
+        };
+
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&self.chart_hash.to_le_bytes())?;
+        w.write_all(&self.freq.to_le_bytes())?;
+
+        w.write_all(&settings.hi_speed.to_le_bytes())?;
+        w.write_all(&settings.visual_offset_ms.to_le_bytes())?;
+        w.write_all(&settings.input_offset_ms.to_le_bytes())?;
+        let downgrade = settings.gauge.downgrade as u8;
+        w.write_all(&[gauge_type, gauge_level, downgrade])?;
+        w.write_all(&settings.windows.critical_ms.to_le_bytes())?;
+        w.write_all(&settings.windows.near_ms.to_le_bytes())?;
+        w.write_all(&settings.windows.error_ms.to_le_bytes())?;
+        w.write_all(&settings.rate.to_le_bytes())?;
+
+        w.write_all(&self.score.to_le_bytes())?;
+        w.write_all(&self.ex_score.to_le_bytes())?;
+
+        write_varint(w, self.inputs.len() as u64)?;
+        let mut last_time = 0;
+        for (time, input) in self.inputs.iter() {
+            write_varint(w, zigzag(time.0 - last_time))?;
+            last_time = time.0;
+
+            match input {
+                PlayInput::Press(role) => {
+                    w.write_all(&[PRESS, role_index(*role)])?
+                },
+                PlayInput::Release(role) => {
+                    w.write_all(&[RELEASE, role_index(*role)])?
+                },
+                PlayInput::Knob(side, delta) => {
+                    w.write_all(&[KNOB, *side as u8])?;
+                    w.write_all(&delta.to_le_bytes())?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read<R>(r: &mut R) -> Result<Replay, ReplayError>
+    where R: Read {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+
+        let version = u16::from_le_bytes(read_array(r)?);
+        if version == 0 || VERSION < version {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let chart_hash = u64::from_le_bytes(read_array(r)?);
+        let freq = u32::from_le_bytes(read_array(r)?);
+
+        let hi_speed = f32::from_le_bytes(read_array(r)?);
+        let visual_offset_ms = i32::from_le_bytes(read_array(r)?);
+        let input_offset_ms = i32::from_le_bytes(read_array(r)?);
+        let [gauge_type, gauge_level, downgrade] = read_array::<_, [u8; 3]>(r)?;
+        let critical_ms = f64::from_le_bytes(read_array(r)?);
+        let near_ms = f64::from_le_bytes(read_array(r)?);
+        let error_ms = f64::from_le_bytes(read_array(r)?);
+        let rate = if 2 <= version {
+            f32::from_le_bytes(read_array(r)?)
+        }
+        else {
+            1.
+        };
+
+        if !(0.5 <= rate && rate <= 1.) {
+            return Err(ReplayError::Corrupt);
+        }
+
+        let gauge_type = match gauge_type {
+            0 => GaugeType::Effective,
+            1 => GaugeType::Excessive,
+            2 => GaugeType::Permissive(gauge_level),
+            3 => GaugeType::Blastive(gauge_level),
+            _ => return Err(ReplayError::Corrupt),
+        };
+
+        let settings = PlaySettings {
+            hi_speed,
+            visual_offset_ms,
+            input_offset_ms,
+            gauge: GaugeOptions {
+                gauge_type,
+                downgrade: downgrade != 0,
+            },
+            windows: JudgmentWindows {
+                critical_ms,
+                near_ms,
+                error_ms,
+            },
+            rate,
+        };
+
+        let score = u32::from_le_bytes(read_array(r)?);
+        let ex_score = u64::from_le_bytes(read_array(r)?);
+
+        let count = read_varint(r)?;
+        let mut inputs = vec![];
+        let mut last_time = 0;
+        for _ in 0 .. count {
+            last_time += unzigzag(read_varint(r)?);
+
+            let [kind, idx] = read_array::<_, [u8; 2]>(r)?;
+            let input = match kind {
+                PRESS => PlayInput::Press(role_of(idx)?),
+                RELEASE => PlayInput::Release(role_of(idx)?),
+                KNOB => {
+                    let delta = f32::from_le_bytes(read_array(r)?);
+                    PlayInput::Knob(idx as usize, delta)
+                },
+                _ => return Err(ReplayError::Corrupt),
+            };
+
+            inputs.push((SongTime(last_time), input));
+        }
+
+        Ok(Replay {
+            chart_hash,
+            freq,
+            settings,
+            score,
+            ex_score,
+            inputs,
+        })
+    }
+
+    /// Plays the inputs against the chart from start to end, without a
+    /// window. Returns `None` if the replay isn't of the chart.
+    pub fn simulate(
+        &self,
+        chart: Chart,
+    ) -> Option<SongSession>
+    {
+        if chart.hash() != self.chart_hash {
+            return None;
+        }
+
+        let tempo_map = TempoMap::from_chart(&chart, self.freq);
+        let mut session = SongSession::new(chart, tempo_map, self.settings);
+
+        for (time, input) in self.inputs.iter() {
+            session.input(*input, *time);
+        }
+
+        let end = session.end_time();
+        session.update(end);
+
+        Some(session)
+    }
+
+    /// Returns true if playing the inputs against the chart gives the scores
+    /// the replay claims
+    pub fn is_genuine(
+        &self,
+        chart: Chart,
+    ) -> bool
+    {
+        match self.simulate(chart) {
+            Some(session) => {
+                session.score().score() == self.score &&
+                    session.score().ex_score() == self.ex_score
+            },
+            None => false,
+        }
+    }
+
+    pub fn player(&self) -> ReplayPlayer {
+        ReplayPlayer {
+            inputs: self.inputs.clone(),
+            next:   0,
+        }
+    }
+}
+
+impl ReplayPlayer {
+    /// Hands out the inputs that are due by the given time, along with the
+    /// times they're judged at
+    pub fn poll(
+        &mut self,
+        time: SongTime,
+    ) -> Vec<(SongTime, PlayInput)>
+    {
+        let due = self.inputs[self.next ..]
+            .iter()
+            .take_while(|(at, _)| *at <= time)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.next += due.len();
+        due
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        use ReplayError::*;
+
+        match self {
+            Io(e) => write!(f, "{}", e),
+            NotAReplay => write!(f, "not a replay file"),
+            UnsupportedVersion(v) => {
+                write!(f, "unsupported replay version {}", v)
+            },
+            Corrupt => write!(f, "the replay is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        match e.kind() {
+            // the file ended before it should have
+            io::ErrorKind::UnexpectedEof => ReplayError::Corrupt,
+            _ => ReplayError::Io(e),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn role_index(role: BindRoles) -> u8 {
+    ROLES.iter().position(|r| *r == role).unwrap() as u8
+}
+
+fn role_of(idx: u8) -> Result<BindRoles, ReplayError> {
+    ROLES.get(idx as usize).cloned().ok_or(ReplayError::Corrupt)
+}
+
+fn read_array<R, A>(r: &mut R) -> io::Result<A>
+where
+    R: Read,
+    A: Default + AsMut<[u8]>,
+{
+    let mut array = A::default();
+    r.read_exact(array.as_mut())?;
+    Ok(array)
+}
+
+// the times are written in as few bytes as they need, 7 bits at a time
+fn write_varint<W>(
+    w: &mut W,
+    mut value: u64,
+) -> io::Result<()>
+where
+    W: Write,
+{
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R>(r: &mut R) -> Result<u64, ReplayError>
+where R: Read {
+    let mut value = 0;
+
+    for shift in (0 .. 64).step_by(7) {
+        let [byte] = read_array::<_, [u8; 1]>(r)?;
+        value |= ((byte & 0x7F) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ReplayError::Corrupt)
+}
+
+// keeps the small negative numbers small
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chart::ksh,
+        environment::key_bindings::BindRoles::*,
+    };
+
+    const FIXTURE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/chart.ksh",
+    ));
+
+    // the rate is the last of the settings in the header
+    const RATE_OFFSET: usize = 57;
+
+    // the session is timed by a thousand frames a second
+    const FREQ: u32 = 1000;
+
+    fn replay(rate: f32) -> Replay {
+        Replay {
+            chart_hash: 0x0123_4567_89AB_CDEF,
+            freq:       44_100,
+            settings:   PlaySettings {
+                hi_speed:         2.5,
+                visual_offset_ms: -12,
+                input_offset_ms:  30,
+                gauge:            GaugeOptions {
+                    gauge_type: GaugeType::Permissive(3),
+                    downgrade:  true,
+                },
+                rate,
+                ..PlaySettings::default()
+            },
+            score:      9_876_543,
+            ex_score:   1_234,
+            inputs:     vec![
+                (SongTime(-500), PlayInput::Press(BT_A)),
+                (SongTime(1_000), PlayInput::Knob(1, -0.25)),
+                (SongTime(1_000), PlayInput::Release(BT_A)),
+                (SongTime(1_000_000), PlayInput::Press(BACK)),
+            ],
+        }
+    }
+
+    fn bytes_of(replay: &Replay) -> Vec<u8> {
+        let mut bytes = vec![];
+        replay.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn read(bytes: &[u8]) -> Result<Replay, ReplayError> {
+        Replay::read(&mut &bytes[..])
+    }
+
+    fn assert_corrupt(bytes: &[u8]) {
+        match read(bytes) {
+            Err(ReplayError::Corrupt) => {},
+            other => panic!("expected a corrupt replay, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let replay = replay(0.75);
+        assert_eq!(read(&bytes_of(&replay)).unwrap(), replay);
+
+        let empty = Replay {
+            inputs: vec![],
+            ..replay
+        };
+        assert_eq!(read(&bytes_of(&empty)).unwrap(), empty);
+    }
+
+    #[test]
+    fn reads_the_first_version_at_full_speed() {
+        let replay = replay(1.);
+
+        let mut bytes = bytes_of(&replay);
+        bytes[4 .. 6].copy_from_slice(&1u16.to_le_bytes());
+        bytes.drain(RATE_OFFSET .. RATE_OFFSET + 4);
+
+        assert_eq!(read(&bytes).unwrap(), replay);
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_replay() {
+        let mut bytes = bytes_of(&replay(1.));
+
+        bytes[4 .. 6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        match read(&bytes) {
+            Err(ReplayError::UnsupportedVersion(v)) => {
+                assert_eq!(v, VERSION + 1)
+            },
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+
+        bytes[0] = b'X';
+        match read(&bytes) {
+            Err(ReplayError::NotAReplay) => {},
+            other => panic!("expected not a replay, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_replays() {
+        let bytes = bytes_of(&replay(1.));
+
+        // cut short, in the header and in the inputs
+        assert_corrupt(&bytes[.. RATE_OFFSET]);
+        assert_corrupt(&bytes[.. bytes.len() - 1]);
+
+        // a gauge that doesn't exist
+        let mut bad_gauge = bytes.clone();
+        bad_gauge[30] = 4;
+        assert_corrupt(&bad_gauge);
+
+        // a rate too slow to be played at
+        let mut bad_rate = bytes.clone();
+        bad_rate[RATE_OFFSET .. RATE_OFFSET + 4]
+            .copy_from_slice(&0.25f32.to_le_bytes());
+        assert_corrupt(&bad_rate);
+
+        // the last input is a press of a role that doesn't exist
+        let mut bad_role = bytes.clone();
+        *bad_role.last_mut().unwrap() = ROLES.len() as u8;
+        assert_corrupt(&bad_role);
+    }
+
+    #[test]
+    fn hands_out_the_inputs_as_theyre_due() {
+        let mut player = replay(1.).player();
+
+        assert!(player.poll(SongTime(-501)).is_empty());
+        assert_eq!(player.poll(SongTime(1_000)).len(), 3);
+
+        assert_eq!(player.poll(SongTime(2_000_000)), vec![(
+            SongTime(1_000_000),
+            PlayInput::Press(BACK)
+        )]);
+        assert!(player.poll(SongTime(i64::max_value())).is_empty());
+    }
+
+    #[test]
+    fn checks_the_scores_against_the_chart() {
+        let chart = || ksh::parse(FIXTURE).unwrap();
+        let tempo_map = TempoMap::from_chart(&chart(), FREQ);
+        let mut session =
+            SongSession::new(chart(), tempo_map, PlaySettings::default());
+
+        // the first chip is hit on time, and the second one a little late
+        session.input(PlayInput::Press(BT_A), SongTime(0));
+        session.input(PlayInput::Release(BT_A), SongTime(50));
+        session.input(PlayInput::Press(BT_B), SongTime(560));
+        session.input(PlayInput::Release(BT_B), SongTime(600));
+        session.update(session.end_time());
+        assert!(0 < session.score().score());
+
+        let path = std::env::temp_dir().join("yasc_replay_genuine.ysr");
+        Replay::from_session(&session).save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replay.inputs, session.inputs());
+        assert_eq!(replay.score, session.score().score());
+        assert_eq!(replay.ex_score, session.score().ex_score());
+        assert!(replay.is_genuine(chart()));
+
+        let simulated = replay.simulate(chart()).unwrap();
+        assert_eq!(simulated.events(), session.events());
+
+        // the scores can't be claimed higher than they are
+        let tampered = Replay {
+            score: replay.score + 1,
+            ..replay.clone()
+        };
+        assert!(!tampered.is_genuine(chart()));
+
+        let tampered = Replay {
+            ex_score: replay.ex_score + 5,
+            ..replay.clone()
+        };
+        assert!(!tampered.is_genuine(chart()));
+
+        // nor be played on another chart
+        let other = Replay {
+            chart_hash: replay.chart_hash + 1,
+            ..replay
+        };
+        assert!(other.simulate(chart()).is_none());
+        assert!(!other.is_genuine(chart()));
+    }
+}
@@ -0,0 +1,481 @@
+use crate::{
+    judgment::{
+        JudgedObject,
+        Judgment,
+        JudgmentEvent,
+    },
+    score::NoteCounts,
+    song_player::song_timer::SongTime,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the normal gauges are cleared at this point by the end of the song
+const CLEAR_THRESHOLD: f64 = 0.7;
+
+// how much the gauges fill up over a play where everything is critical
+const EFFECTIVE_TOTAL_GAIN: f64 = 2.1;
+const EXCESSIVE_TOTAL_GAIN: f64 = 1.05;
+
+// a tick of a hold or a laser is only worth a fraction of a chip
+const TICK_WEIGHT: f64 = 0.25;
+
+// how much an error on a chip drains. the errors on the ticks drain by their
+// weight.
+const EFFECTIVE_DRAIN: f64 = 0.02;
+const EXCESSIVE_DRAIN: f64 = 0.09;
+
+// the hard gauges drain slower when they're about to run out
+const LOW_THRESHOLD: f64 = 0.3;
+const LOW_DRAIN_FACTOR: f64 = 0.5;
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GaugeType {
+    /// Starts empty and has to be at least 70% full by the end of the song
+    Effective,
+
+    /// Starts full and fails the song once it runs out
+    Excessive,
+
+    /// Like the effective gauge, but drains less the higher its level. The
+    /// levels go from 1 to 5.
+    Permissive(u8),
+
+    /// Like the excessive gauge, but drains more the higher its level. The
+    /// level is counted in halves, so 4 is level 2.0. It goes from 1 to 10.
+    Blastive(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GaugeOptions {
+    pub gauge_type: GaugeType,
+
+    // whether a hard gauge turns into the effective gauge instead of failing
+    pub downgrade: bool,
+}
+
+/// The gauge of a play, filled and drained by the judgments.
+///
+/// Like the score, it's only ever built from the judgment events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gauge {
+    options: GaugeOptions,
+    current: GaugeMeter,
+
+    // the effective gauge, kept alongside a hard one in case it's downgraded
+    fallback: Option<GaugeMeter>,
+
+    downgraded_at: Option<SongTime>,
+    failed_at:     Option<SongTime>,
+
+    // the value of the gauge after each judgment, for the graph on the results
+    history: Vec<(SongTime, f32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GaugeMeter {
+    gauge_type: GaugeType,
+    rates:      GaugeRates,
+    value:      f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GaugeRates {
+    chip_gain:  f64,
+    chip_drain: f64,
+
+    // hard gauges fail at 0 and drain slower when they're low
+    is_hard: bool,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl Default for GaugeOptions {
+    fn default() -> GaugeOptions {
+        GaugeOptions {
+            gauge_type: GaugeType::Effective,
+            downgrade:  false,
+        }
+    }
+}
+
+impl GaugeType {
+    pub fn is_hard(&self) -> bool {
+        match self {
+            GaugeType::Excessive | GaugeType::Blastive(_) => true,
+            GaugeType::Effective | GaugeType::Permissive(_) => false,
+        }
+    }
+
+    fn rates(
+        &self,
+        counts: NoteCounts,
+    ) -> GaugeRates
+    {
+        let weight = counts.chips as f64 + counts.ticks as f64 * TICK_WEIGHT;
+        let weight = weight.max(1.);
+
+        let (total_gain, drain) = match *self {
+            GaugeType::Effective => (EFFECTIVE_TOTAL_GAIN, EFFECTIVE_DRAIN),
+            GaugeType::Excessive => (EXCESSIVE_TOTAL_GAIN, EXCESSIVE_DRAIN),
+            GaugeType::Permissive(level) => {
+                let level = level.max(1).min(5) as f64;
+                (EFFECTIVE_TOTAL_GAIN, EFFECTIVE_DRAIN / (1. + level))
+            },
+            GaugeType::Blastive(level) => {
+                // level 2.0 drains as much as the excessive gauge
+                let level = level.max(1).min(10) as f64;
+                (EXCESSIVE_TOTAL_GAIN, EXCESSIVE_DRAIN * level / 4.)
+            },
+        };
+
+        GaugeRates {
+            chip_gain:  total_gain / weight,
+            chip_drain: drain,
+            is_hard:    self.is_hard(),
+        }
+    }
+}
+
+impl Gauge {
+    pub fn new(
+        options: GaugeOptions,
+        counts: NoteCounts,
+    ) -> Gauge
+    {
+        let current = GaugeMeter::new(options.gauge_type, counts);
+
+        let fallback = if options.downgrade && options.gauge_type.is_hard() {
+            Some(GaugeMeter::new(GaugeType::Effective, counts))
+        }
+        else {
+            None
+        };
+
+        Gauge {
+            options,
+            current,
+            fallback,
+            downgraded_at: None,
+            failed_at: None,
+            history: vec![],
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        event: &JudgmentEvent,
+    )
+    {
+        // nothing more counts once the gauge has failed
+        if self.has_failed() {
+            return;
+        }
+
+        self.current.record(event);
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.record(event);
+        }
+
+        if self.current.is_empty() {
+            match self.fallback.take() {
+                Some(fallback) => {
+                    self.current = fallback;
+                    self.downgraded_at = Some(event.time);
+                },
+                None => self.failed_at = Some(event.time),
+            }
+        }
+
+        self.history.push((event.time, self.current.value as f32));
+    }
+
+    pub fn options(&self) -> GaugeOptions {
+        self.options
+    }
+
+    /// Returns the type of the gauge, which may have changed from the one it
+    /// started with because of a downgrade
+    pub fn gauge_type(&self) -> GaugeType {
+        self.current.gauge_type
+    }
+
+    /// Returns how full the gauge is, from 0 to 1
+    pub fn value(&self) -> f32 {
+        self.current.value as f32
+    }
+
+    pub fn history(&self) -> &[(SongTime, f32)] {
+        &self.history
+    }
+
+    pub fn downgraded_at(&self) -> Option<SongTime> {
+        self.downgraded_at
+    }
+
+    /// Returns true if a hard gauge has run out. The song should end then.
+    pub fn has_failed(&self) -> bool {
+        self.failed_at.is_some()
+    }
+
+    pub fn failed_at(&self) -> Option<SongTime> {
+        self.failed_at
+    }
+
+    /// Returns true if the gauge clears the song, were it to end now
+    pub fn is_cleared(&self) -> bool {
+        if self.current.gauge_type.is_hard() {
+            !self.has_failed()
+        }
+        else {
+            self.current.value >= CLEAR_THRESHOLD
+        }
+    }
+}
+
+impl GaugeMeter {
+    fn new(
+        gauge_type: GaugeType,
+        counts: NoteCounts,
+    ) -> GaugeMeter
+    {
+        // the hard gauges start full
+        let value = if gauge_type.is_hard() {
+            1.
+        }
+        else {
+            0.
+        };
+
+        GaugeMeter {
+            gauge_type,
+            rates: gauge_type.rates(counts),
+            value,
+        }
+    }
+
+    fn record(
+        &mut self,
+        event: &JudgmentEvent,
+    )
+    {
+        let weight = match event.object {
+            JudgedObject::BtChip(_) | JudgedObject::FxChip(_) => 1.,
+            _ => TICK_WEIGHT,
+        };
+
+        let rates = &self.rates;
+        let change = match event.judgment {
+            Judgment::Critical => rates.chip_gain * weight,
+            Judgment::Near(_) => rates.chip_gain * weight / 2.,
+            Judgment::Error => {
+                let drain = rates.chip_drain * weight;
+
+                if rates.is_hard && self.value < LOW_THRESHOLD {
+                    -drain * LOW_DRAIN_FACTOR
+                }
+                else {
+                    -drain
+                }
+            },
+        };
+
+        self.value = (self.value + change).max(0.).min(1.);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rates.is_hard && self.value <= 0.
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judgment::Timing;
+
+    const CHIP: JudgedObject = JudgedObject::BtChip(0);
+    const TICK: JudgedObject = JudgedObject::Laser(0);
+
+    fn gauge(
+        gauge_type: GaugeType,
+        downgrade: bool,
+        chips: usize,
+    ) -> Gauge
+    {
+        let options = GaugeOptions {
+            gauge_type,
+            downgrade,
+        };
+
+        Gauge::new(options, NoteCounts {
+            chips,
+            ticks: 0,
+        })
+    }
+
+    // records the judgments one after another, a frame apart
+    fn record(
+        gauge: &mut Gauge,
+        object: JudgedObject,
+        judgment: Judgment,
+        times: usize,
+    )
+    {
+        for _ in 0 .. times {
+            let time = SongTime(gauge.history().len() as i64);
+
+            gauge.record(&JudgmentEvent {
+                time,
+                object,
+                judgment,
+            });
+        }
+    }
+
+    fn assert_close(
+        actual: f32,
+        expected: f32,
+    )
+    {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn gains_by_the_chips_and_ticks() {
+        // the ticks weigh a quarter of a chip, so it's as if there were 7
+        // chips, each worth 0.3
+        let mut gauge = Gauge::new(GaugeOptions::default(), NoteCounts {
+            chips: 6,
+            ticks: 4,
+        });
+
+        record(&mut gauge, CHIP, Judgment::Critical, 1);
+        assert_close(gauge.value(), 0.3);
+
+        record(&mut gauge, TICK, Judgment::Critical, 1);
+        assert_close(gauge.value(), 0.375);
+
+        record(&mut gauge, CHIP, Judgment::Near(Timing::Early), 1);
+        assert_close(gauge.value(), 0.525);
+
+        record(&mut gauge, CHIP, Judgment::Error, 1);
+        assert_close(gauge.value(), 0.505);
+
+        record(&mut gauge, TICK, Judgment::Error, 1);
+        assert_close(gauge.value(), 0.5);
+    }
+
+    #[test]
+    fn clears_the_effective_gauge_at_70_percent() {
+        let mut gauge = gauge(GaugeType::Effective, false, 10);
+        assert_eq!(gauge.value(), 0.);
+
+        // each chip is worth 0.21
+        record(&mut gauge, CHIP, Judgment::Critical, 3);
+        assert!(!gauge.is_cleared());
+
+        record(&mut gauge, CHIP, Judgment::Critical, 1);
+        assert!(gauge.is_cleared());
+
+        // the normal gauges never fail
+        record(&mut gauge, CHIP, Judgment::Error, 100);
+        assert_eq!(gauge.value(), 0.);
+        assert!(!gauge.has_failed());
+        assert!(!gauge.is_cleared());
+    }
+
+    #[test]
+    fn fails_the_excessive_gauge_once_it_runs_out() {
+        let mut gauge = gauge(GaugeType::Excessive, false, 100);
+        assert_eq!(gauge.value(), 1.);
+        assert!(gauge.is_cleared());
+
+        // eight errors take it under 30%, after which it drains half as fast
+        record(&mut gauge, CHIP, Judgment::Error, 8);
+        assert_close(gauge.value(), 0.28);
+
+        record(&mut gauge, CHIP, Judgment::Error, 6);
+        assert_close(gauge.value(), 0.01);
+        assert!(!gauge.has_failed());
+
+        record(&mut gauge, CHIP, Judgment::Error, 1);
+        assert!(gauge.has_failed());
+        assert!(!gauge.is_cleared());
+        assert_eq!(gauge.failed_at(), Some(SongTime(14)));
+
+        // nothing more counts
+        record(&mut gauge, CHIP, Judgment::Critical, 10);
+        assert_eq!(gauge.value(), 0.);
+        assert_eq!(gauge.history().len(), 15);
+    }
+
+    #[test]
+    fn drains_by_the_level() {
+        let drained = |gauge_type| {
+            let mut gauge = gauge(gauge_type, false, 10);
+            record(&mut gauge, CHIP, Judgment::Critical, 1);
+            record(&mut gauge, CHIP, Judgment::Error, 1);
+            gauge.value()
+        };
+
+        // the higher permissive levels drain less
+        assert_close(drained(GaugeType::Effective), 0.19);
+        assert_close(drained(GaugeType::Permissive(1)), 0.2);
+        assert_close(drained(GaugeType::Permissive(4)), 0.206);
+        assert_close(drained(GaugeType::Permissive(9)), 0.20667);
+
+        // and the higher blastive levels drain more
+        assert_close(drained(GaugeType::Excessive), 0.91);
+        assert_close(drained(GaugeType::Blastive(4)), 0.91);
+        assert_close(drained(GaugeType::Blastive(2)), 0.955);
+        assert_close(drained(GaugeType::Blastive(10)), 0.775);
+        assert!(GaugeType::Blastive(1).is_hard());
+        assert!(!GaugeType::Permissive(1).is_hard());
+    }
+
+    #[test]
+    fn downgrades_to_the_effective_gauge() {
+        let mut gauge = gauge(GaugeType::Excessive, true, 100);
+
+        // the effective gauge is filled alongside
+        record(&mut gauge, CHIP, Judgment::Critical, 50);
+        record(&mut gauge, CHIP, Judgment::Error, 14);
+        assert_eq!(gauge.gauge_type(), GaugeType::Excessive);
+        assert_eq!(gauge.downgraded_at(), None);
+
+        record(&mut gauge, CHIP, Judgment::Error, 1);
+        assert_eq!(gauge.gauge_type(), GaugeType::Effective);
+        assert_eq!(gauge.downgraded_at(), Some(SongTime(64)));
+        assert_close(gauge.value(), 0.7);
+        assert!(!gauge.has_failed());
+        assert_eq!(gauge.options().gauge_type, GaugeType::Excessive);
+
+        // it can't be downgraded any further
+        record(&mut gauge, CHIP, Judgment::Error, 100);
+        assert!(!gauge.has_failed());
+    }
+
+    #[test]
+    fn keeps_the_history_of_the_values() {
+        let mut gauge = gauge(GaugeType::Effective, false, 10);
+        record(&mut gauge, CHIP, Judgment::Critical, 2);
+        record(&mut gauge, CHIP, Judgment::Error, 1);
+
+        let history = gauge.history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].0, SongTime(0));
+        assert_close(history[0].1, 0.21);
+        assert_close(history[1].1, 0.42);
+        assert_eq!(history[2].0, SongTime(2));
+        assert_close(history[2].1, 0.4);
+    }
+}
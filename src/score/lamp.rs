@@ -0,0 +1,361 @@
+use crate::{
+    judgment::{
+        Judgment,
+        JudgmentEvent,
+    },
+    score::{
+        gauge::Gauge,
+        Score,
+    },
+};
+use std::fmt;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// How well a chart has been cleared, from worst to best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClearLamp {
+    Failed,
+    Clear,
+    HardClear,
+    UltimateChain,
+    Perfect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Grade {
+    D,
+    C,
+    B,
+    A,
+    APlus,
+    AA,
+    AAPlus,
+    AAA,
+    AAAPlus,
+    S,
+}
+
+/// Counts the objects judged in a row without an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Chain {
+    current: usize,
+    max:     usize,
+}
+
+/// Everything about a play that's shown on the results
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayResult {
+    pub score:     Score,
+    pub gauge:     Gauge,
+    pub max_chain: usize,
+    pub lamp:      ClearLamp,
+    pub grade:     Grade,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl ClearLamp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClearLamp::Failed => "FAILED",
+            ClearLamp::Clear => "CLEAR",
+            ClearLamp::HardClear => "HARD CLEAR",
+            ClearLamp::UltimateChain => "ULTIMATE CHAIN",
+            ClearLamp::Perfect => "PERFECT",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ClearLamp> {
+        let lamp = match name {
+            "FAILED" => ClearLamp::Failed,
+            "CLEAR" => ClearLamp::Clear,
+            "HARD CLEAR" => ClearLamp::HardClear,
+            "ULTIMATE CHAIN" => ClearLamp::UltimateChain,
+            "PERFECT" => ClearLamp::Perfect,
+            _ => return None,
+        };
+
+        Some(lamp)
+    }
+}
+
+impl fmt::Display for ClearLamp {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Grade {
+    pub fn from_score(score: u32) -> Grade {
+        match score {
+            s if s >= 9_900_000 => Grade::S,
+            s if s >= 9_800_000 => Grade::AAAPlus,
+            s if s >= 9_700_000 => Grade::AAA,
+            s if s >= 9_500_000 => Grade::AAPlus,
+            s if s >= 9_300_000 => Grade::AA,
+            s if s >= 9_000_000 => Grade::APlus,
+            s if s >= 8_700_000 => Grade::A,
+            s if s >= 7_500_000 => Grade::B,
+            s if s >= 6_500_000 => Grade::C,
+            _ => Grade::D,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grade::D => "D",
+            Grade::C => "C",
+            Grade::B => "B",
+            Grade::A => "A",
+            Grade::APlus => "A+",
+            Grade::AA => "AA",
+            Grade::AAPlus => "AA+",
+            Grade::AAA => "AAA",
+            Grade::AAAPlus => "AAA+",
+            Grade::S => "S",
+        }
+    }
+}
+
+impl fmt::Display for Grade {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result
+    {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Chain {
+    pub fn record(
+        &mut self,
+        event: &JudgmentEvent,
+    )
+    {
+        match event.judgment {
+            Judgment::Error => self.current = 0,
+            _ => {
+                self.current += 1;
+                self.max = self.max.max(self.current);
+            },
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+impl PlayResult {
+    pub fn new(
+        score: &Score,
+        gauge: &Gauge,
+        chain: &Chain,
+    ) -> PlayResult
+    {
+        let is_complete = score.judged() == score.counts().total();
+
+        let lamp = if !gauge.is_cleared() || !is_complete {
+            ClearLamp::Failed
+        }
+        else if score.errors() == 0 && score.nears() == 0 {
+            ClearLamp::Perfect
+        }
+        else if score.errors() == 0 {
+            ClearLamp::UltimateChain
+        }
+        else if gauge.gauge_type().is_hard() {
+            ClearLamp::HardClear
+        }
+        else {
+            ClearLamp::Clear
+        };
+
+        PlayResult {
+            score: score.clone(),
+            gauge: gauge.clone(),
+            max_chain: chain.max(),
+            lamp,
+            grade: Grade::from_score(score.score()),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        judgment::{
+            JudgedObject,
+            Timing,
+        },
+        score::{
+            gauge::{
+                GaugeOptions,
+                GaugeType,
+            },
+            NoteCounts,
+        },
+        song_player::song_timer::SongTime,
+    };
+
+    const CRITICAL: Judgment = Judgment::Critical;
+    const NEAR: Judgment = Judgment::Near(Timing::Early);
+    const ERROR: Judgment = Judgment::Error;
+
+    fn event(
+        idx: usize,
+        judgment: Judgment,
+    ) -> JudgmentEvent
+    {
+        JudgmentEvent {
+            time: SongTime(idx as i64),
+            object: JudgedObject::BtChip(0),
+            judgment,
+        }
+    }
+
+    // plays a chart of chips with the given judgments
+    fn play(
+        gauge_type: GaugeType,
+        chips: usize,
+        judgments: &[Judgment],
+    ) -> PlayResult
+    {
+        let counts = NoteCounts {
+            chips,
+            ticks: 0,
+        };
+        let options = GaugeOptions {
+            gauge_type,
+            downgrade: false,
+        };
+
+        let mut score = Score::new(counts);
+        let mut gauge = Gauge::new(options, counts);
+        let mut chain = Chain::default();
+
+        for (idx, judgment) in judgments.iter().enumerate() {
+            let event = event(idx, *judgment);
+            score.record(&event);
+            gauge.record(&event);
+            chain.record(&event);
+        }
+
+        PlayResult::new(&score, &gauge, &chain)
+    }
+
+    fn lamp(
+        gauge_type: GaugeType,
+        judgments: &[Judgment],
+    ) -> ClearLamp
+    {
+        play(gauge_type, judgments.len(), judgments).lamp
+    }
+
+    #[test]
+    fn grades_the_score() {
+        let grades = [
+            (10_000_000, Grade::S),
+            (9_900_000, Grade::S),
+            (9_899_999, Grade::AAAPlus),
+            (9_800_000, Grade::AAAPlus),
+            (9_700_000, Grade::AAA),
+            (9_699_999, Grade::AAPlus),
+            (9_500_000, Grade::AAPlus),
+            (9_300_000, Grade::AA),
+            (9_000_000, Grade::APlus),
+            (8_700_000, Grade::A),
+            (8_699_999, Grade::B),
+            (7_500_000, Grade::B),
+            (6_500_000, Grade::C),
+            (6_499_999, Grade::D),
+            (0, Grade::D),
+        ];
+
+        for (score, grade) in grades.iter() {
+            assert_eq!(Grade::from_score(*score), *grade, "at {}", score);
+        }
+    }
+
+    #[test]
+    fn picks_the_lamp() {
+        let effective = GaugeType::Effective;
+        let excessive = GaugeType::Excessive;
+
+        let mut judgments = vec![CRITICAL; 10];
+        assert_eq!(lamp(effective, &judgments), ClearLamp::Perfect);
+        assert_eq!(lamp(excessive, &judgments), ClearLamp::Perfect);
+
+        judgments[3] = NEAR;
+        assert_eq!(lamp(effective, &judgments), ClearLamp::UltimateChain);
+
+        judgments[5] = ERROR;
+        assert_eq!(lamp(effective, &judgments), ClearLamp::Clear);
+        assert_eq!(lamp(excessive, &judgments), ClearLamp::HardClear);
+
+        // the gauge ends under 70%
+        let mut judgments = vec![ERROR; 10];
+        judgments[.. 3].copy_from_slice(&[CRITICAL; 3]);
+        assert_eq!(lamp(effective, &judgments), ClearLamp::Failed);
+
+        // the excessive gauge runs out
+        assert_eq!(lamp(excessive, &[ERROR; 14]), ClearLamp::HardClear);
+        assert_eq!(lamp(excessive, &[ERROR; 15]), ClearLamp::Failed);
+
+        // the song hasn't been played to the end
+        let unfinished = play(effective, 10, &[CRITICAL; 9]);
+        assert_eq!(unfinished.lamp, ClearLamp::Failed);
+    }
+
+    #[test]
+    fn breaks_the_chain_on_errors() {
+        let judgments = [CRITICAL, NEAR, CRITICAL, ERROR, CRITICAL, NEAR];
+        let mut chain = Chain::default();
+
+        for (idx, judgment) in judgments[.. 4].iter().enumerate() {
+            chain.record(&event(idx, *judgment));
+        }
+
+        assert_eq!(chain.current(), 0);
+        assert_eq!(chain.max(), 3);
+
+        for (idx, judgment) in judgments[4 ..].iter().enumerate() {
+            chain.record(&event(idx, *judgment));
+        }
+
+        assert_eq!(chain.current(), 2);
+        assert_eq!(chain.max(), 3);
+        assert_eq!(play(GaugeType::Effective, 6, &judgments).max_chain, 3);
+    }
+
+    #[test]
+    fn names_the_lamps() {
+        let lamps = [
+            ClearLamp::Failed,
+            ClearLamp::Clear,
+            ClearLamp::HardClear,
+            ClearLamp::UltimateChain,
+            ClearLamp::Perfect,
+        ];
+
+        for lamp in lamps.iter() {
+            assert_eq!(ClearLamp::from_name(lamp.name()), Some(*lamp));
+        }
+
+        assert_eq!(ClearLamp::from_name("CLEARED"), None);
+        assert!(ClearLamp::HardClear < ClearLamp::UltimateChain);
+    }
+}
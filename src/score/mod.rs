@@ -0,0 +1,239 @@
+pub mod gauge;
+pub mod lamp;
+pub mod record;
+
+////////////////////////////////////////////////////////////////////////////////
+
+use crate::judgment::{
+    JudgedObject,
+    Judgment,
+    JudgmentEvent,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub const MAX_SCORE: u32 = 10_000_000;
+
+// the points a judgment is worth towards the normal score
+const CRITICAL_POINTS: u64 = 2;
+const NEAR_POINTS: u64 = 1;
+
+// the points a judgment is worth towards the EX score. the ticks of the holds
+// and lasers, along with the slams, are only ever critical or error.
+const EX_CHIP_CRITICAL: u64 = 5;
+const EX_CHIP_NEAR: u64 = 2;
+const EX_TICK_CRITICAL: u64 = 2;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The number of objects of a chart that can be judged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NoteCounts {
+    pub chips: usize,
+
+    // the ticks of the holds and lasers, along with the slams
+    pub ticks: usize,
+}
+
+/// The normal and EX scores of a play.
+///
+/// The scores are only ever built from the judgment events, so the same events
+/// from a replay always give the same scores.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Score {
+    counts: NoteCounts,
+
+    points:    u64,
+    ex_points: u64,
+
+    criticals: usize,
+    nears:     usize,
+    errors:    usize,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl NoteCounts {
+    pub fn total(&self) -> usize {
+        self.chips + self.ticks
+    }
+}
+
+impl Score {
+    pub fn new(counts: NoteCounts) -> Score {
+        Score {
+            counts,
+            points: 0,
+            ex_points: 0,
+            criticals: 0,
+            nears: 0,
+            errors: 0,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        event: &JudgmentEvent,
+    )
+    {
+        let is_chip = match event.object {
+            JudgedObject::BtChip(_) | JudgedObject::FxChip(_) => true,
+            _ => false,
+        };
+
+        match event.judgment {
+            Judgment::Critical => {
+                self.criticals += 1;
+                self.points += CRITICAL_POINTS;
+                self.ex_points += if is_chip {
+                    EX_CHIP_CRITICAL
+                }
+                else {
+                    EX_TICK_CRITICAL
+                };
+            },
+
+            Judgment::Near(_) => {
+                self.nears += 1;
+                self.points += NEAR_POINTS;
+                if is_chip {
+                    self.ex_points += EX_CHIP_NEAR;
+                }
+            },
+
+            Judgment::Error => self.errors += 1,
+        }
+    }
+
+    pub fn counts(&self) -> NoteCounts {
+        self.counts
+    }
+
+    /// Returns the normal score, out of 10,000,000
+    pub fn score(&self) -> u32 {
+        let max_points = self.counts.total() as u64 * CRITICAL_POINTS;
+        if max_points == 0 {
+            return 0;
+        }
+
+        let score = self.points * MAX_SCORE as u64 / max_points;
+        score.min(MAX_SCORE as u64) as u32
+    }
+
+    pub fn ex_score(&self) -> u64 {
+        self.ex_points
+    }
+
+    pub fn max_ex_score(&self) -> u64 {
+        self.counts.chips as u64 * EX_CHIP_CRITICAL +
+            self.counts.ticks as u64 * EX_TICK_CRITICAL
+    }
+
+    pub fn criticals(&self) -> usize {
+        self.criticals
+    }
+
+    pub fn nears(&self) -> usize {
+        self.nears
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Returns the number of objects that have been judged so far
+    pub fn judged(&self) -> usize {
+        self.criticals + self.nears + self.errors
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        judgment::Timing,
+        song_player::song_timer::SongTime,
+    };
+
+    fn score_of(
+        chips: usize,
+        ticks: usize,
+        events: &[(JudgedObject, Judgment)],
+    ) -> Score
+    {
+        let mut score = Score::new(NoteCounts {
+            chips,
+            ticks,
+        });
+
+        for (object, judgment) in events.iter() {
+            score.record(&JudgmentEvent {
+                time:     SongTime(0),
+                object:   *object,
+                judgment: *judgment,
+            });
+        }
+
+        score
+    }
+
+    #[test]
+    fn weighs_the_criticals_twice_the_nears() {
+        let near = Judgment::Near(Timing::Early);
+        let score = score_of(2, 2, &[
+            (JudgedObject::BtChip(0), Judgment::Critical),
+            (JudgedObject::FxChip(1), near),
+            (JudgedObject::Laser(0), Judgment::Error),
+        ]);
+
+        // 3 points out of 8
+        assert_eq!(score.score(), 3_750_000);
+        assert_eq!(score.criticals(), 1);
+        assert_eq!(score.nears(), 1);
+        assert_eq!(score.errors(), 1);
+        assert_eq!(score.judged(), 3);
+    }
+
+    #[test]
+    fn rounds_the_score_down_up_to_the_max() {
+        let critical = (JudgedObject::BtChip(0), Judgment::Critical);
+
+        // 2 points out of 6
+        assert_eq!(score_of(3, 0, &[critical]).score(), 3_333_333);
+        assert_eq!(
+            score_of(3, 0, &[critical, critical, critical]).score(),
+            MAX_SCORE
+        );
+
+        // more judgments than objects never go past the max
+        assert_eq!(score_of(1, 0, &[critical, critical]).score(), MAX_SCORE);
+    }
+
+    #[test]
+    fn counts_the_ex_score_by_the_kind_of_object() {
+        let near = Judgment::Near(Timing::Late);
+        let score = score_of(3, 4, &[
+            (JudgedObject::BtChip(0), Judgment::Critical),
+            (JudgedObject::FxChip(0), near),
+            (JudgedObject::BtChip(1), Judgment::Error),
+            (JudgedObject::BtHold(2), Judgment::Critical),
+            (JudgedObject::FxHold(1), Judgment::Critical),
+            (JudgedObject::Slam(1), Judgment::Critical),
+            (JudgedObject::Laser(0), Judgment::Error),
+        ]);
+
+        assert_eq!(score.ex_score(), 5 + 2 + 2 * 3);
+        assert_eq!(score.max_ex_score(), 3 * 5 + 4 * 2);
+    }
+
+    #[test]
+    fn scores_nothing_without_objects() {
+        let score = score_of(0, 0, &[]);
+
+        assert_eq!(score.score(), 0);
+        assert_eq!(score.ex_score(), 0);
+        assert_eq!(score.max_ex_score(), 0);
+    }
+}
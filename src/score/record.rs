@@ -0,0 +1,173 @@
+use crate::{
+    chart::Chart,
+    score::lamp::ClearLamp,
+};
+use serde_json::{
+    Map,
+    Value,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::Path,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The best clear lamp of every chart that has been played
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BestLamps {
+    lamps: HashMap<String, ClearLamp>,
+}
+
+impl BestLamps {
+    /// Reads the record from a file. A missing file is just an empty record.
+    pub fn load<P>(path: P) -> io::Result<BestLamps>
+    where P: AsRef<Path> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(BestLamps::default());
+            },
+            Err(e) => return Err(e),
+        };
+
+        let value: Value = serde_json::from_str(&text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // entries that can't be read are dropped
+        let lamps = value
+            .as_object()
+            .into_iter()
+            .flat_map(|map| map.iter())
+            .filter_map(|(key, lamp)| {
+                let lamp = ClearLamp::from_name(lamp.as_str()?)?;
+                Some((key.clone(), lamp))
+            })
+            .collect();
+
+        Ok(BestLamps {
+            lamps,
+        })
+    }
+
+    pub fn save<P>(
+        &self,
+        path: P,
+    ) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let map = self
+            .lamps
+            .iter()
+            .map(|(key, lamp)| (key.clone(), Value::from(lamp.name())))
+            .collect::<Map<_, _>>();
+
+        let text = serde_json::to_string_pretty(&Value::Object(map))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        fs::write(path, text)
+    }
+
+    pub fn get(
+        &self,
+        key: &str,
+    ) -> Option<ClearLamp>
+    {
+        self.lamps.get(key).cloned()
+    }
+
+    /// Keeps the lamp if it's better than the chart's best. Returns true if it
+    /// was kept.
+    pub fn update(
+        &mut self,
+        key: &str,
+        lamp: ClearLamp,
+    ) -> bool
+    {
+        match self.lamps.get(key) {
+            Some(best) if *best >= lamp => false,
+            _ => {
+                self.lamps.insert(key.to_owned(), lamp);
+                true
+            },
+        }
+    }
+}
+
+/// Returns the key of a chart in the records. The charts are told apart by
+/// their hash, so the charts that share a title and a difficulty don't share
+/// their lamps, while a chart keeps its lamp whichever format it's loaded from.
+pub fn chart_key(chart: &Chart) -> String {
+    format!("{:016x}", chart.hash())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(name)
+    }
+
+    #[test]
+    fn keeps_the_best_lamp() {
+        let mut lamps = BestLamps::default();
+        assert_eq!(lamps.get("chart"), None);
+
+        assert!(lamps.update("chart", ClearLamp::Clear));
+        assert!(!lamps.update("chart", ClearLamp::Failed));
+        assert!(!lamps.update("chart", ClearLamp::Clear));
+        assert_eq!(lamps.get("chart"), Some(ClearLamp::Clear));
+
+        assert!(lamps.update("chart", ClearLamp::UltimateChain));
+        assert_eq!(lamps.get("chart"), Some(ClearLamp::UltimateChain));
+
+        // the other charts keep their own
+        assert!(lamps.update("other", ClearLamp::Failed));
+        assert_eq!(lamps.get("other"), Some(ClearLamp::Failed));
+        assert_eq!(lamps.get("chart"), Some(ClearLamp::UltimateChain));
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let path = temp_path("yasc_best_lamps_round_trip.json");
+
+        let mut lamps = BestLamps::default();
+        lamps.update("0123456789abcdef", ClearLamp::HardClear);
+        lamps.update("fedcba9876543210", ClearLamp::Perfect);
+
+        lamps.save(&path).unwrap();
+        let loaded = BestLamps::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, lamps);
+    }
+
+    #[test]
+    fn loads_what_it_can() {
+        let missing = temp_path("yasc_best_lamps_missing.json");
+        assert_eq!(BestLamps::load(&missing).unwrap(), BestLamps::default());
+
+        // the lamps that can't be read are dropped
+        let path = temp_path("yasc_best_lamps_partial.json");
+        let text = r#"{ "a": "CLEAR", "b": "CLEARED", "c": 3 }"#;
+        fs::write(&path, text).unwrap();
+
+        let loaded = BestLamps::load(&path).unwrap();
+        assert_eq!(loaded.get("a"), Some(ClearLamp::Clear));
+        assert_eq!(loaded.get("b"), None);
+        assert_eq!(loaded.get("c"), None);
+
+        // while a file that isn't JSON isn't read at all
+        fs::write(&path, "not json").unwrap();
+        let error = BestLamps::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
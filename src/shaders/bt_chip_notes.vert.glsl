@@ -12,6 +12,9 @@ out vec2 texture_coord;
 
 void main() {
     // determine the vertex' real center
+    // both the note position and the song offset are scroll positions that
+    // have been worked out on the CPU, so stops and speed changes are already
+    // accounted for
     vec2 cur_pos = note_pos;
     cur_pos[1] = (cur_pos[1] - song_offset) * hi_speed;
 
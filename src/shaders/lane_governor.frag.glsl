@@ -5,6 +5,7 @@ in vec2 laser_texture_coord;
 
 uniform sampler2D lanes_texture;
 uniform sampler2D laser_texture;
+uniform float lanes_visibility;
 
 out vec4 color;
 
@@ -18,4 +19,7 @@ void main() {
         (sqrt(lanes_tex) + sqrt(laser_tex)) / 2.,
         vec4(2., 2., 2., 2.)
     );
+
+    // the hidden lanes fade out along with everything on them
+    color.rgb *= lanes_visibility;
 }
@@ -1,8 +1,10 @@
 #version 330
 
 layout (location = 0) in vec2 vertex_pos;
+layout (location = 1) in float side;
 
 uniform float laser_cutoff;
+uniform float center_split;
 uniform mat4 transform;
 
 // we emit from this shader the coordinates of the texture of the lanes and
@@ -39,9 +41,14 @@ void main() {
     laser_texture_coord = lanes_texture_coord;
     laser_texture_coord[1] *= laser_cutoff;
 
+    // the halves of the lanes move apart, while their textures stay as they
+    // are
+    vec2 split_pos = vertex_pos;
+    split_pos[0] += side * center_split / 2.;
+
     // map the position of the vectors according to the transformation matrix
     vec4 padded_vec = vec4(
-        vertex_pos,
+        split_pos,
         0.,
         1.
     );
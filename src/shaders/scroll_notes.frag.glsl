@@ -0,0 +1,8 @@
+#version 330
+
+in vec4 into_frag_color;
+out vec4 color;
+
+void main() {
+    color = into_frag_color;
+}
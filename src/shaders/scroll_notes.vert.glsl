@@ -0,0 +1,22 @@
+#version 330
+
+layout (location = 0) in vec2 scroll_pos;
+layout (location = 1) in float lift;
+layout (location = 2) in vec4 fill;
+
+uniform float song_offset;
+uniform float hi_speed;
+uniform mat4 transform;
+
+out vec4 into_frag_color;
+
+void main() {
+    // the same as the chips, the scroll positions have been worked out on the
+    // CPU. the lift is added after the speed so the heights stay the same.
+    vec2 cur_pos = scroll_pos;
+    cur_pos[1] = (cur_pos[1] - song_offset) * hi_speed + lift;
+
+    into_frag_color = fill;
+
+    gl_Position = transform * vec4(cur_pos, 0., 1.);
+}
@@ -1,4 +1,12 @@
 use crate::{
+    chart::{
+        Chart,
+        GraphPoint,
+        SpinDirection,
+        SpinEvent,
+        SpinKind,
+        Tick,
+    },
     environment::{
         actor_wrapper::{
             ActorWrapper,
@@ -10,6 +18,7 @@ use crate::{
             UpdatePayload,
             WrappedAddr,
         },
+        layout::Layout,
         update_routine::{
             CanBeWindowHandled,
             UpdateEnvelope,
@@ -17,17 +26,28 @@ use crate::{
         RenderWindowParts,
         UpdateWindowParts,
     },
+    judgment::hold::LongHitType,
     pipelines::lane_governor::*,
     song_player::{
         keyframe::{
+            Interpolate,
             Keyframe,
+            KeyframeTrack,
             TransformationKFCurve,
         },
         lanes::{
             Lanes,
             LanesInitRequest,
         },
+        notes::{
+            Notes,
+            NotesInitRequest,
+            NotesPayload,
+        },
+        session::PlaySettings,
         song_timer::SongTime,
+        tempo_map::TempoMap,
+        tilt::LaneTilt,
     },
     utils::{
         block_fn,
@@ -70,6 +90,7 @@ use gfx_graphics::{
     TextureSettings,
 };
 use image::ImageBuffer;
+use piston_window::Input;
 use sekibanki::Sender as TPSender;
 use shader_version::{
     glsl::GLSL,
@@ -92,6 +113,7 @@ pub struct LGRenderDetails {
     pub transform: Arc<Matrix4<f32>>,
 
     pub lanes: RenderResponseFuture<Lanes>,
+    pub notes: Option<RenderResponseFuture<Notes>>,
 
     pub pipeline: PipelineState<Resources, LaneGovernorRenderPipeline::Meta>,
     pub vbuf:     Buffer<Resources, Corner>,
@@ -106,6 +128,11 @@ pub struct LGRenderDetails {
     // this will be the color target that will be drawn on and it will come
     // from the payload
     pub color_target: RenderTargetView<Resources, Srgba8>,
+
+    // how far apart the halves of the lanes are, and how much of the lanes is
+    // shown
+    pub center_split:     f32,
+    pub lanes_visibility: f32,
 }
 
 impl LGRenderDetails {
@@ -131,6 +158,8 @@ impl LGRenderDetails {
                 self.laser_texture.sampler,
             ),
             lasers_cutoff: LASER_CUTOFF,
+            center_split: self.center_split,
+            lanes_visibility: self.lanes_visibility,
         };
 
         rwp.tex_ctx.encoder.draw(&self.slice, &self.pipeline, &data);
@@ -149,20 +178,11 @@ impl RenderDetails for LGRenderDetails {
         // render the lanes
         (&mut self.lanes).wait().unwrap().render(rwp);
 
-        // render the fx holds
-        //block_fn(|| (&mut self.fx_holds).wait());
-
-        // render the bt holds
-        //block_fn(|| (&mut self.bt_holds).wait());
-
-        // render the fx chips
-        //block_fn(|| (&mut self.fx_chips).wait());
-
-        // render the bt chips
-        //block_fn(|| (&mut self.bt_chips).wait());
-
-        // render the lasers
-        //block_fn(|| (&mut self.lasers).wait());
+        // render the notes on top of the lanes, and the lasers on their own
+        // texture
+        if let Some(notes) = self.notes.as_mut() {
+            notes.wait().unwrap().render(rwp);
+        }
 
         // then finally utilize the render target as a texture of a rectangle,
         // which would then be rendered on the screen
@@ -172,29 +192,124 @@ impl RenderDetails for LGRenderDetails {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// What the lanes are drawn with, besides the time
+#[derive(Debug, Clone, Default)]
+pub struct LGRenderPayload {
+    // the spin started by the latest slam that has been hit, if any
+    pub spin: Option<Spin>,
+
+    // how far along each of the holds is, by lane
+    pub bt_holds: Vec<Vec<LongHitType>>,
+    pub fx_holds: Vec<Vec<LongHitType>>,
+}
+
 pub struct LGInitRequest {
     // keyframes
-    rotation_events: Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-    slant_events:    Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-    zoom_events:     Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-
-    lanes: WrappedAddr<Lanes>,
+    tilt:              LaneTilt,
+    slant_events:      KeyframeTrack<TransformationKFCurve, Rad<f32>>,
+    zoom_events:       KeyframeTrack<TransformationKFCurve>,
+    shift_events:      KeyframeTrack<TransformationKFCurve>,
+    split_events:      KeyframeTrack<TransformationKFCurve>,
+    visibility_events: KeyframeTrack<TransformationKFCurve>,
+
+    layout: Layout,
+    lanes:  WrappedAddr<Lanes>,
+
+    // there are no notes without a chart
+    notes: Option<WrappedAddr<Notes>>,
 }
 
 impl LGInitRequest {
     pub fn debug_new(
+        layout: Layout,
         tx: &mut UnboundedSender<UpdateEnvelope>,
         sender: TPSender,
     ) -> LGInitRequest
     {
-        LGInitRequest::with_rsz(vec![], vec![], vec![], tx, sender)
+        LGInitRequest::with_tracks(
+            LaneTilt::level(DEBUG_FREQ),
+            KeyframeTrack::new(vec![], DEFAULT_SLANT),
+            KeyframeTrack::new(vec![], DEFAULT_ZOOM),
+            KeyframeTrack::new(vec![], DEFAULT_SHIFT),
+            KeyframeTrack::new(vec![], DEFAULT_SPLIT),
+            KeyframeTrack::new(vec![], DEFAULT_VISIBILITY),
+            layout,
+            None,
+            tx,
+            sender,
+        )
+    }
+
+    /// Creates the request from the notes and the camera events of a loaded
+    /// chart
+    pub fn from_chart(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+        settings: &PlaySettings,
+        layout: Layout,
+        tx: &mut UnboundedSender<UpdateEnvelope>,
+        sender: TPSender,
+    ) -> LGInitRequest
+    {
+        let manual_tilt =
+            graph_to_track(tempo_map, &chart.tilt, DEFAULT_ROTATION, |tilt| {
+                DEFAULT_ROTATION + Rad(tilt as f32 * TILT_UNIT)
+            });
+        let tilt = LaneTilt::new(chart, tempo_map, manual_tilt);
+
+        let slant_events =
+            graph_to_track(tempo_map, &chart.zoom_top, DEFAULT_SLANT, |zoom| {
+                DEFAULT_SLANT + Rad(zoom as f32 * SLANT_UNIT)
+            });
+
+        let zoom_events =
+            graph_to_track(tempo_map, &chart.zoom_bottom, DEFAULT_ZOOM, |zoom| {
+                DEFAULT_ZOOM - zoom as f32 * ZOOM_UNIT
+            });
+
+        let shift_events =
+            graph_to_track(tempo_map, &chart.zoom_side, DEFAULT_SHIFT, |zoom| {
+                DEFAULT_SHIFT + zoom as f32 * SHIFT_UNIT
+            });
+
+        let split_events = graph_to_track(
+            tempo_map,
+            &chart.center_split,
+            DEFAULT_SPLIT,
+            |split| DEFAULT_SPLIT + split as f32 * SPLIT_UNIT,
+        );
+
+        let visibility_events =
+            toggles_to_track(tempo_map, &chart.lane_toggles);
+
+        let notes = NotesInitRequest::from_chart(chart, tempo_map, settings)
+            .map_err(|e| eprintln!("Failed to load the notes: {}", e))
+            .ok();
+
+        LGInitRequest::with_tracks(
+            tilt,
+            slant_events,
+            zoom_events,
+            shift_events,
+            split_events,
+            visibility_events,
+            layout,
+            notes,
+            tx,
+            sender,
+        )
     }
 
     // the payload must be able to reach here
-    fn with_rsz(
-        rotation_events: Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-        slant_events: Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-        zoom_events: Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
+    fn with_tracks(
+        tilt: LaneTilt,
+        slant_events: KeyframeTrack<TransformationKFCurve, Rad<f32>>,
+        zoom_events: KeyframeTrack<TransformationKFCurve>,
+        shift_events: KeyframeTrack<TransformationKFCurve>,
+        split_events: KeyframeTrack<TransformationKFCurve>,
+        visibility_events: KeyframeTrack<TransformationKFCurve>,
+        layout: Layout,
+        notes: Option<NotesInitRequest>,
         tx: &mut UnboundedSender<UpdateEnvelope>,
         sender: TPSender,
     ) -> LGInitRequest
@@ -207,63 +322,62 @@ impl LGInitRequest {
             .unwrap()
             .send_then_receive(tx)
             .unwrap() // unwrap a canceled
-            .start_actor(Default::default(), sender);
+            .start_actor(Default::default(), sender.clone());
+
+        let notes = notes.map(|notes| {
+            notes
+                .send_then_receive(tx)
+                .unwrap() // unwrap a canceled
+                .start_actor(Default::default(), sender)
+        });
 
         LGInitRequest {
-            rotation_events,
+            tilt,
             slant_events,
             zoom_events,
+            shift_events,
+            split_events,
+            visibility_events,
 
+            layout,
             lanes,
+            notes,
         }
     }
-
-    fn create_render_target_texture<'a>(
-        &mut self,
-        uwp: &mut UpdateWindowParts<'a>,
-    ) -> Option<TextureWithTarget>
-    {
-        // creates a render target texture based on the current size of the
-        // client window
-
-        uwp.window
-            .window
-            .get_inner_size()
-            .map(|lz| (lz.width as u16, lz.height as u16))
-            .map(|(w, h)| {
-                TextureWithTarget::new(w, h, &mut uwp.tex_ctx.factory)
-            })
-    }
 }
 
 impl CanBeWindowHandled for LGInitRequest {
     type Response = Option<LaneGovernor>;
 
     fn handle<'a>(
-        mut self,
+        self,
         uwp: &mut UpdateWindowParts<'a>,
     ) -> Self::Response
     {
-        let lanes_texture = match self.create_render_target_texture(uwp) {
+        let lanes_texture = match create_render_target_texture(uwp) {
             Some(tex) => tex,
             None => return None,
         };
 
-        let laser_texture = match self.create_render_target_texture(uwp) {
+        let laser_texture = match create_render_target_texture(uwp) {
             Some(tex) => tex,
             None => return None,
         };
 
         let (vbuf, slice) = {
-            // declare the vertices of the square of the lanes
-            let vertices = vec![[-1., -1.], [1., -1.], [1., 1.], [-1., 1.]]
+            // declare the vertices of the square of the lanes. each half of
+            // the lanes is its own square, so they can be split apart.
+            let left = vec![[-1., -1.], [0., -1.], [0., 1.], [-1., 1.]]
                 .into_iter()
-                .map(|p| Corner::new(p))
-                .collect::<Vec<_>>();
+                .map(|p| Corner::new(p, -1.));
+            let right = vec![[0., -1.], [1., -1.], [1., 1.], [0., 1.]]
+                .into_iter()
+                .map(|p| Corner::new(p, 1.));
+            let vertices = left.chain(right).collect::<Vec<_>>();
 
             // declare the ordering of indices how we're going to render the
-            // triangle
-            let vert_order: &[u16] = &[0, 1, 2, 2, 3, 0];
+            // triangles
+            let vert_order: &[u16] = &[0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4];
 
             // create the vertex buffer
             uwp.tex_ctx
@@ -298,17 +412,23 @@ impl CanBeWindowHandled for LGInitRequest {
 
         Some(LaneGovernor {
             // keyframes
-            rotation_events: self.rotation_events,
+            tilt: self.tilt,
             slant_events: self.slant_events,
             zoom_events: self.zoom_events,
+            shift_events: self.shift_events,
+            split_events: self.split_events,
+            visibility_events: self.visibility_events,
 
             // current spin
             current_spin: None,
 
+            layout: self.layout,
+
             lanes_texture,
             laser_texture,
 
             lanes: self.lanes,
+            notes: self.notes,
 
             pipeline,
             vbuf,
@@ -317,26 +437,68 @@ impl CanBeWindowHandled for LGInitRequest {
     }
 }
 
+/// Asks for the render targets of the lanes to be made again, at the size the
+/// window has been resized to
+pub struct LGResizeRequest;
+
+impl CanBeWindowHandled for LGResizeRequest {
+    // the targets of the lanes and of the lasers
+    type Response = Option<(TextureWithTarget, TextureWithTarget)>;
+
+    fn handle<'a>(
+        self,
+        uwp: &mut UpdateWindowParts<'a>,
+    ) -> Self::Response
+    {
+        let lanes_texture = create_render_target_texture(uwp)?;
+        let laser_texture = create_render_target_texture(uwp)?;
+
+        Some((lanes_texture, laser_texture))
+    }
+}
+
+fn create_render_target_texture<'a>(
+    uwp: &mut UpdateWindowParts<'a>,
+) -> Option<TextureWithTarget>
+{
+    // creates a render target texture based on the current size of the client
+    // window, in the pixels of the screen rather than the logical ones so it
+    // stays sharp on high DPI screens. there's nothing to draw on while the
+    // window has no size.
+    let dpi = uwp.window.window.get_hidpi_factor();
+
+    uwp.window
+        .window
+        .get_inner_size()
+        .map(|lz| lz.to_physical(dpi))
+        .map(|pz| (pz.width as u16, pz.height as u16))
+        .filter(|(w, h)| 0 < *w && 0 < *h)
+        .map(|(w, h)| TextureWithTarget::new(w, h, &mut uwp.tex_ctx.factory))
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 
 pub struct LaneGovernor {
     // keyframes
-    rotation_events: Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-    slant_events:    Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
-    zoom_events:     Vec<(SongTime, Keyframe<TransformationKFCurve>)>,
+    tilt:              LaneTilt,
+    slant_events:      KeyframeTrack<TransformationKFCurve, Rad<f32>>,
+    zoom_events:       KeyframeTrack<TransformationKFCurve>,
+    shift_events:      KeyframeTrack<TransformationKFCurve>,
+    split_events:      KeyframeTrack<TransformationKFCurve>,
+    visibility_events: KeyframeTrack<TransformationKFCurve>,
 
     // current spin
     // this will only have a value if an input that corresponds to the
     // activation of a slam that has a spin is recognized
     current_spin: Option<Spin>,
 
+    // where the lanes go on the screen
+    layout: Layout,
+
     // at this point, we have the drawable assets. they will be needing the
     // matrix provided to them by the calculate_matrix()
     lanes: WrappedAddr<Lanes>,
-
-    // notes: WrappedAddr<Bt>,
-    // fx: WrappedAddr<Fx>,
-    // lasers: WrappedAddr<Lasers>,
+    notes: Option<WrappedAddr<Notes>>,
 
     // these will serve as render targets and are not intended to contain any
     // fixed texture whatsoever
@@ -355,6 +517,93 @@ pub struct LaneGovernor {
 const DEFAULT_ROTATION: Rad<f32> = Rad(0.);
 const DEFAULT_SLANT: Rad<f32> = Rad(0.6370451769779303); // Deg(36.5)
 const DEFAULT_ZOOM: f32 = -0.9765625;
+const DEFAULT_SHIFT: f32 = 0.;
+const DEFAULT_SPLIT: f32 = 0.;
+const DEFAULT_VISIBILITY: f32 = 1.;
+
+// the lanes without a chart never move, so any rate does for their tilt
+const DEBUG_FREQ: u32 = 44_100;
+
+// These convert the camera values of the charts. The charts' zooms range from
+// -300 to 300 while the tilt is in multiples of 10 degrees.
+const TILT_UNIT: f32 = 0.17453292; // Deg(10)
+const SLANT_UNIT: f32 = 0.0034906585; // Deg(0.2)
+const ZOOM_UNIT: f32 = 0.005;
+
+// the lanes are 2 units wide, so a shift or a split of 100 is half of them
+const SHIFT_UNIT: f32 = 0.01;
+const SPLIT_UNIT: f32 = 0.01;
+
+/// Converts the points of a chart's graph into a track of keyframes, using
+/// `map` to turn the chart's values into the governor's. The track is at the
+/// default if the graph is empty.
+fn graph_to_track<T, F>(
+    tempo_map: &TempoMap,
+    graph: &[GraphPoint],
+    default: T,
+    map: F,
+) -> KeyframeTrack<TransformationKFCurve, T>
+where
+    T: Interpolate,
+    F: Fn(f64) -> T,
+{
+    let mut keyframes = Vec::with_capacity(graph.len());
+
+    for point in graph.iter() {
+        let song_time = tempo_map.tick_to_song_time(point.tick);
+
+        // a slam is just two keyframes at the same time
+        let mut values = vec![point.value.v];
+        if point.value.is_slam() {
+            values.push(point.value.vf);
+        }
+
+        for value in values.into_iter() {
+            keyframes.push(Keyframe::new(
+                map(value),
+                song_time,
+                TransformationKFCurve::HalfSigmoid,
+                0.,
+            ));
+        }
+    }
+
+    KeyframeTrack::new(keyframes, default)
+}
+
+/// Converts the toggles of the lanes into a track of how much of them is shown,
+/// which starts with them shown
+fn toggles_to_track(
+    tempo_map: &TempoMap,
+    toggles: &[(Tick, Tick)],
+) -> KeyframeTrack<TransformationKFCurve>
+{
+    let mut keyframes = Vec::with_capacity(toggles.len() * 2);
+    let mut visibility = DEFAULT_VISIBILITY;
+
+    // a toggle without a length is just two keyframes at the same time
+    for (tick, length) in toggles.iter() {
+        let start = tempo_map.tick_to_song_time(*tick);
+        let end = tempo_map.tick_to_song_time(tick + length);
+
+        keyframes.push(Keyframe::new(
+            visibility,
+            start,
+            TransformationKFCurve::HalfSigmoid,
+            0.,
+        ));
+
+        visibility = 1. - visibility;
+        keyframes.push(Keyframe::new(
+            visibility,
+            end,
+            TransformationKFCurve::HalfSigmoid,
+            0.,
+        ));
+    }
+
+    KeyframeTrack::new(keyframes, DEFAULT_VISIBILITY)
+}
 
 impl LaneGovernor {
     pub fn get_rotation_adjustment(
@@ -376,40 +625,13 @@ impl LaneGovernor {
         self.get_current_rotation(time) + self.get_rotation_adjustment(time)
     }
 
+    /// Returns the tilt of the lanes, as of the time it was last moved to
     pub fn get_current_rotation(
         &self,
         time: &SongTime,
     ) -> Rad<f32>
     {
-        // if there are no rotation events, the rotation is just zero
-        if self.rotation_events.is_empty() {
-            return DEFAULT_ROTATION;
-        }
-
-        // find the index of the current rotation index given the time
-        let search =
-            self.rotation_events.binary_search_by_key(time, |(t, _)| *t);
-
-        match search {
-            Err(idx) => {
-                // in case that the index found is within the first index and
-                // the second to the last index, inclusive...
-                if idx != self.rotation_events.len() {
-                    Rad(self.rotation_events[idx - 1].1.interpolate_against(
-                        time,
-                        &self.rotation_events[idx].1,
-                    ))
-                }
-                // in case that the index found is the last index, we just give
-                // the output of the last
-                else {
-                    Rad(self.rotation_events[idx - 1].1.value())
-                }
-            },
-
-            // if we have an exact match (which is highly unlikely)
-            Ok(idx) => Rad(self.rotation_events[idx].1.value()),
-        }
+        self.tilt.roll(time)
     }
 
     pub fn get_current_slant(
@@ -417,33 +639,7 @@ impl LaneGovernor {
         time: &SongTime,
     ) -> Rad<f32>
     {
-        if self.slant_events.is_empty() {
-            return DEFAULT_SLANT;
-        }
-
-        // find the index of the current rotation index given the time
-        let search =
-            self.slant_events.binary_search_by_key(time, |(t, _)| t.clone());
-
-        match search {
-            Err(idx) => {
-                // in case that the index found is within the first index and
-                // the second to the last index, inclusive...
-                if idx != self.slant_events.len() {
-                    Rad(self.slant_events[idx - 1]
-                        .1
-                        .interpolate_against(time, &self.slant_events[idx].1))
-                }
-                // in case that the index found is the last index, we just give
-                // the output of the last
-                else {
-                    Rad(self.slant_events[idx - 1].1.value())
-                }
-            },
-
-            // if we have an exact match (which is highly unlikely)
-            Ok(idx) => Rad(self.slant_events[idx].1.value()),
-        }
+        self.slant_events.value_at(time)
     }
 
     pub fn get_current_zoom(
@@ -451,37 +647,42 @@ impl LaneGovernor {
         time: &SongTime,
     ) -> f32
     {
-        if self.zoom_events.is_empty() {
-            return DEFAULT_ZOOM;
-        }
+        self.zoom_events.value_at(time)
+    }
 
-        let search =
-            self.zoom_events.binary_search_by_key(time, |(t, _)| t.clone());
-
-        match search {
-            Err(idx) => {
-                // in case that the index found is within the first index and
-                // the second to the last index, inclusive...
-                if idx != self.zoom_events.len() {
-                    self.zoom_events[idx - 1]
-                        .1
-                        .interpolate_against(time, &self.zoom_events[idx].1)
-                }
-                // in case that the index found is the last index, we just give
-                // the output of the last
-                else {
-                    self.zoom_events[idx - 1].1.value()
-                }
-            },
+    /// Returns how far the lanes are moved sideways
+    pub fn get_current_shift(
+        &self,
+        time: &SongTime,
+    ) -> f32
+    {
+        self.shift_events.value_at(time)
+    }
 
-            // if we have an exact match (which is highly unlikely)
-            Ok(idx) => self.zoom_events[idx].1.value(),
-        }
+    /// Returns how far apart the halves of the lanes are
+    pub fn get_current_split(
+        &self,
+        time: &SongTime,
+    ) -> f32
+    {
+        self.split_events.value_at(time)
+    }
+
+    /// Returns how much of the lanes is shown, from 0 to 1
+    pub fn get_current_visibility(
+        &self,
+        time: &SongTime,
+    ) -> f32
+    {
+        self.visibility_events.value_at(time).max(0.).min(1.)
     }
 
+    /// Returns the matrix that puts the lanes where they are at the given
+    /// time, on a screen of the given size in pixels
     pub fn calculate_matrix(
         &self,
         time: &SongTime,
+        screen: [f32; 2],
     ) -> Matrix4<f32>
     {
         const BACK_OFFSET: f32 = -3.6;
@@ -490,6 +691,7 @@ impl LaneGovernor {
         let rotation = self.get_rotation_after_adjustment(time);
         let slant = self.get_current_slant(time);
         let zoom = self.get_current_zoom(time);
+        let shift = self.get_current_shift(time);
 
         let model =
             // move the lanes away by a given constant
@@ -512,8 +714,8 @@ impl LaneGovernor {
             // increase the vertical length of the lanes
             Matrix4::from_nonuniform_scale(1., VERT_SCALE, 1.) *
 
-            // move upwards by 1 unit
-            Matrix4::from_translation(Vector3::new(0., 1., 0.));
+            // move upwards by 1 unit, and sideways by the shift
+            Matrix4::from_translation(Vector3::new(shift, 1., 0.));
 
         let view = {
             let camera = get_default_first_person().camera(0.).orthogonal();
@@ -527,6 +729,8 @@ impl LaneGovernor {
             Matrix4::from(camera)
         };
 
+        // the lanes are projected into a square, and the square is fitted into
+        // the screen after they're rotated so the rotation doesn't stretch them
         let projection = Matrix4::from(PerspectiveFov {
             fovy:   Rad::from(Deg(90.)),
             aspect: 1.,
@@ -536,6 +740,16 @@ impl LaneGovernor {
 
         let post_mvp = mvp(&model, &view, &projection);
 
+        let [width, height] = screen;
+        let placement = self
+            .layout
+            .arrange(width, height)
+            .lanes
+            .clip_transform(width, height);
+
+        // fit the square of the lanes into their part of the screen
+        placement *
+
         // rotate the lanes from a center point in the camera
         Matrix4::from(
             Quaternion::from_axis_angle(
@@ -558,53 +772,83 @@ impl ActorWrapper for LaneGovernor {
 
     fn update(
         &mut self,
-        _: UpdatePayload<Self::Payload>,
+        mut payload: UpdatePayload<Self::Payload>,
         _: &ContextWrapper<Self>,
     )
     {
-        // are we going to manually update anyway?
+        // the lanes are drawn at the size of the window, so they're drawn on
+        // new targets once it's resized. the old ones are kept if the window
+        // has no size to make them at.
+        if let Some(Input::Resize(..)) = payload.event {
+            let resized = LGResizeRequest
+                .send_then_receive(&mut payload.tx)
+                .unwrap(); // can't be cancelled
+
+            if let Some((lanes_texture, laser_texture)) = resized {
+                self.lanes_texture = lanes_texture;
+                self.laser_texture = laser_texture;
+            }
+        }
     }
 }
 
 impl RenderableActorWrapper for LaneGovernor {
     type Details = LGRenderDetails;
-    type Payload = ();
+
+    type Payload = LGRenderPayload;
 
     fn emit_render_details(
         &mut self,
-        payload: RenderPayload<()>,
+        mut payload: RenderPayload<LGRenderPayload>,
         _: &ContextWrapper<Self>,
     ) -> Self::Details
     {
+        let LGRenderPayload {
+            spin,
+            bt_holds,
+            fx_holds,
+        } = std::mem::take(&mut payload.payload);
+        self.current_spin = spin;
+        let payload = payload.set_payload(());
+
         let song_time =
             payload.get_time().song_time.clone().unwrap_or(SongTime(0));
-        let transform = Arc::new(self.calculate_matrix(&song_time));
+        self.tilt.advance(song_time);
+
+        // the lanes are fitted to the size of what they're drawn on, which
+        // isn't always the size the window was asked for
+        let (width, height, ..) = payload.color_target.get_dimensions();
+        let screen = [width as f32, height as f32];
+        let transform = Arc::new(self.calculate_matrix(&song_time, screen));
 
         // declare the payloads. these will be useful.
-        // TODO: you need to have the texture AND the target view initialized
-        // during the update
         let mut lanes_payload = payload.clone();
         lanes_payload.color_target = self.lanes_texture.rtv.clone();
 
-        let mut laser_payload = payload.clone();
-        laser_payload.color_target = self.laser_texture.rtv.clone();
+        // the notes are drawn on the lanes, except for the lasers
+        let notes_payload = lanes_payload.clone().set_payload(NotesPayload {
+            laser_target: self.laser_texture.rtv.clone(),
+            bt_holds,
+            fx_holds,
+        });
 
         // send the payloads to the respective actors
         let lanes = self.lanes.send(lanes_payload);
+        let notes = self.notes.as_mut().map(|notes| notes.send(notes_payload));
 
         // declare the render details here
         let details = LGRenderDetails {
             transform,
             lanes,
-            // lasers,
-            // bt,
-            // fx,
+            notes,
             pipeline: self.pipeline.clone(),
             vbuf: self.vbuf.clone(),
             slice: self.slice.clone(),
             lanes_texture: self.lanes_texture.clone(),
             laser_texture: self.laser_texture.clone(),
             color_target: payload.color_target.clone(),
+            center_split: self.get_current_split(&song_time),
+            lanes_visibility: self.get_current_visibility(&song_time),
         };
 
         details
@@ -622,9 +866,15 @@ fn mvp(
 
 ////////////////////////////////////////////////////////////////////////////////
 
+// how far the lanes sway at first, and how many times they sway back and forth
+const SWAY_AMPLITUDE: f32 = 0.2617994; // Deg(15)
+const SWAY_CYCLES: f32 = 2.;
+
 #[derive(Debug, Clone)]
 pub struct SpinBuilder {
-    pub duration:  SongTime,
+    pub duration: SongTime,
+
+    // true if the lanes spin clockwise
     pub direction: bool,
     pub spin_type: SpinType,
 }
@@ -640,10 +890,31 @@ pub struct Spin {
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub enum SpinType {
     Spin,
+    HalfSpin,
     Sway,
 }
 
 impl SpinBuilder {
+    /// Creates the spin of a chart. It's yet to be started by its slam.
+    pub fn from_event(
+        event: &SpinEvent,
+        tempo_map: &TempoMap,
+    ) -> SpinBuilder
+    {
+        let start = tempo_map.tick_to_song_time(event.tick);
+        let end = tempo_map.tick_to_song_time(event.tick + event.length);
+
+        SpinBuilder {
+            duration:  end - start,
+            direction: event.direction == SpinDirection::Right,
+            spin_type: match event.kind {
+                SpinKind::Full => SpinType::Spin,
+                SpinKind::Half => SpinType::HalfSpin,
+                SpinKind::Sway => SpinType::Sway,
+            },
+        }
+    }
+
     pub fn build(
         self,
         start: SongTime,
@@ -668,6 +939,7 @@ impl SpinType {
         time_val: f32,
     ) -> Rad<f32>
     {
+        use core::f32::consts::PI;
         use SpinType::*;
 
         // if outside the range of (0, 1)
@@ -675,14 +947,32 @@ impl SpinType {
             return Rad(0.);
         }
 
+        // slows down towards the end
+        let ease_out = |x: f32| 1. - (1. - x) * (1. - x);
+        // slow on both ends
+        let smoothstep = |x: f32| x * x * (3. - 2. * x);
+
         match self {
-            Spin => {
-                unimplemented!()
-                // utilize envelopes in here
-                // there is an envelope crate out there
+            // a whole turn, starting off fast
+            Spin => Rad(2. * PI * ease_out(time_val)),
+
+            // half a turn out then back to the start
+            HalfSpin => {
+                if time_val < 0.5 {
+                    Rad(PI * ease_out(time_val * 2.))
+                }
+                else {
+                    Rad(PI * (1. - smoothstep(time_val * 2. - 1.)))
+                }
             },
 
-            Sway => unimplemented!(),
+            // swaying back and forth, dying down over time
+            Sway => {
+                let decay = (1. - time_val) * (1. - time_val);
+                let wave = (2. * PI * SWAY_CYCLES * time_val).sin();
+
+                Rad(SWAY_AMPLITUDE * decay * wave)
+            },
         }
     }
 }
@@ -699,8 +989,15 @@ impl Spin {
 
         let progress =
             (time_val.0 - self.start.0) as f32 / self.duration.0 as f32;
+        let rotation = self.spin_type.clamped_rotate(progress);
 
-        self.spin_type.clamped_rotate(progress)
+        // the positive angles go counterclockwise
+        if self.direction {
+            -rotation
+        }
+        else {
+            rotation
+        }
     }
 }
 
@@ -713,6 +1010,10 @@ fn get_default_first_person() -> FirstPerson {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The clock of the song being played, counted in the frames of its music.
+///
+/// It's only ever moved by the music as it's played, so everything timed by it
+/// stays in sync with what's heard.
 pub struct SongTimer {
     counter: AtomicI64,
     is_some: AtomicBool,
@@ -720,7 +1021,15 @@ pub struct SongTimer {
 }
 
 lazy_static! {
-    static ref CURRENT_SONG_TIMER: SongTimer = SongTimer::unstarted();
+    pub(crate) static ref CURRENT_SONG_TIMER: SongTimer =
+        SongTimer::unstarted();
+}
+
+// the tests that play music all move the one timer, so they take turns
+#[cfg(test)]
+lazy_static! {
+    pub(crate) static ref SONG_TIMER_LOCK: parking_lot::Mutex<()> =
+        parking_lot::Mutex::new(());
 }
 
 impl SongTimer {
@@ -744,7 +1053,7 @@ impl SongTimer {
         }
     }
 
-    ////// below are methods only accessible to the Governor/Song Player //////
+    ////////// below are methods only accessible to the music player //////////
 
     fn unstarted() -> SongTimer {
         SongTimer {
@@ -754,7 +1063,7 @@ impl SongTimer {
         }
     }
 
-    fn start(
+    pub(crate) fn start(
         &self,
         freq: u32,
     )
@@ -763,17 +1072,65 @@ impl SongTimer {
         self.freq.store(freq, Ordering::SeqCst);
     }
 
-    fn reset(&self) {
+    pub(crate) fn reset(&self) {
         self.counter.store(0, Ordering::Relaxed);
     }
 
-    fn stop_and_reset(&self) {
+    /// Moves the timer to the given time. It may be negative, before the music
+    /// has started.
+    pub(crate) fn set(
+        &self,
+        time: SongTime,
+    )
+    {
+        self.counter.store(time.0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stop_and_reset(&self) {
         self.is_some.store(false, Ordering::SeqCst);
         self.counter.store(0, Ordering::SeqCst);
         self.freq.store(0, Ordering::SeqCst);
     }
 
-    fn increment(&self) {
-        self.counter.fetch_add(1, Ordering::Relaxed);
+    /// Moves the timer forward by the given number of frames of the music.
+    /// It moves by less than a frame for every frame played while the song is
+    /// slowed down, which is left to the player to keep track of.
+    pub(crate) fn advance(
+        &self,
+        frames: i64,
+    )
+    {
+        self.counter.fetch_add(frames, Ordering::Relaxed);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    #[test]
+    fn spins_start_and_end_level() {
+        let types = [SpinType::Spin, SpinType::HalfSpin, SpinType::Sway];
+
+        for spin_type in types.iter() {
+            assert_eq!(spin_type.clamped_rotate(0.), Rad(0.));
+            assert_eq!(spin_type.clamped_rotate(1.), Rad(0.));
+
+            // and they get there smoothly, where a whole turn is level too
+            for &time_val in [0.001, 0.999].iter() {
+                let Rad(angle) = spin_type.clamped_rotate(time_val);
+                let off_level = angle % (2. * PI);
+
+                assert!(
+                    off_level.abs() < 0.02 || off_level.abs() > 2. * PI - 0.02,
+                    "{:?} at {}",
+                    spin_type,
+                    time_val,
+                );
+            }
+        }
     }
 }
@@ -1,24 +1,77 @@
 use crate::{
     song_player::song_timer::SongTime,
-    utils::linear_map,
+    utils::sigmoid,
 };
-use core::cmp::Ordering;
+use cgmath::{
+    Deg,
+    Rad,
+    Vector2,
+    Vector3,
+};
+use core::{
+    cmp::Ordering,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering as AtomicOrdering,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how many keyframes a track steps through from where it last looked before it
+// searches for where it is instead
+const CURSOR_STEPS: usize = 4;
 
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Debug, PartialEq)]
-pub struct Keyframe<C>
+pub struct Keyframe<C, T = f32>
 where C: KeyframeCurveType {
-    value:     f32,
+    value:     T,
     song_time: SongTime,
     curve:     C,
     tension:   f32,
 }
 
-impl<C> Keyframe<C>
-where C: KeyframeCurveType
+/// The keyframes of a value over the song, sorted by their time.
+///
+/// The value holds at the first keyframe before it, and at the last one after
+/// it. Keyframes at the same time jump from the first of them to the last.
+/// The track remembers where it last looked, so looking it up as the song goes
+/// forward takes a step at most instead of a search.
+#[derive(Debug)]
+pub struct KeyframeTrack<C, T = f32>
+where C: KeyframeCurveType {
+    keyframes: Vec<Keyframe<C, T>>,
+
+    // the value when there are no keyframes at all
+    default: T,
+
+    // how many keyframes were at or before the time last looked up
+    cursor: AtomicUsize,
+}
+
+impl<C, T> Keyframe<C, T>
+where
+    C: KeyframeCurveType,
+    T: Interpolate,
 {
-    pub fn value(&self) -> f32 {
+    pub fn new(
+        value: T,
+        song_time: SongTime,
+        curve: C,
+        tension: f32,
+    ) -> Keyframe<C, T>
+    {
+        Keyframe {
+            value,
+            song_time,
+            curve,
+            tension,
+        }
+    }
+
+    pub fn value(&self) -> T {
         self.value
     }
 
@@ -35,26 +88,35 @@ where C: KeyframeCurveType
     }
 }
 
-impl<C> Keyframe<C>
-where C: KeyframeCurveType
+impl<C, T> Keyframe<C, T>
+where
+    C: KeyframeCurveType,
+    T: Interpolate,
 {
+    /// Returns the value at the given time, on the way to the next keyframe.
+    /// The curve eases the progress from one to the other, which the value is
+    /// then interpolated by.
     pub fn interpolate_against(
         &self,
         time: &SongTime,
-        next: &Keyframe<C>,
-    ) -> f32
+        next: &Keyframe<C, T>,
+    ) -> T
     {
-        self.curve.interpolate(
+        let y = self.curve.interpolate(
             time,
-            (self.value, &self.song_time),
-            (next.value, &next.song_time),
+            (0., &self.song_time),
+            (1., &next.song_time),
             self.tension,
-        )
+        );
+
+        self.value.interpolate(&next.value, y)
     }
 }
 
-impl<C> PartialOrd for Keyframe<C>
-where C: KeyframeCurveType + PartialEq
+impl<C, T> PartialOrd for Keyframe<C, T>
+where
+    C: KeyframeCurveType + PartialEq,
+    T: PartialEq,
 {
     fn partial_cmp(
         &self,
@@ -86,15 +148,270 @@ pub trait KeyframeCurveType {
     ) -> f32;
 }
 
+/// A value that can be interpolated between keyframes
+pub trait Interpolate: Copy {
+    /// Returns the value the given part of the way to the other one. The part
+    /// may go past either end for the curves that overshoot, and is exactly
+    /// the other value at 1.
+    fn interpolate(
+        &self,
+        other: &Self,
+        part: f32,
+    ) -> Self;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl<C, T> KeyframeTrack<C, T>
+where
+    C: KeyframeCurveType,
+    T: Interpolate,
+{
+    /// Makes a track out of the keyframes, which are sorted by their time.
+    /// Keyframes at the same time are kept in the order they're given.
+    pub fn new(
+        mut keyframes: Vec<Keyframe<C, T>>,
+        default: T,
+    ) -> KeyframeTrack<C, T>
+    {
+        keyframes.sort_by_key(|keyframe| keyframe.song_time);
+
+        KeyframeTrack {
+            keyframes,
+            default,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe<C, T>] {
+        &self.keyframes
+    }
+
+    /// Returns the value at the given time
+    pub fn value_at(
+        &self,
+        time: &SongTime,
+    ) -> T
+    {
+        let keyframes = &self.keyframes;
+
+        let passed = self.passed(time);
+        self.cursor.store(passed, AtomicOrdering::Relaxed);
+
+        if passed == 0 {
+            return keyframes.first().map_or(self.default, Keyframe::value);
+        }
+
+        let this = &keyframes[passed - 1];
+        match keyframes.get(passed) {
+            Some(next) => this.interpolate_against(time, next),
+            None => this.value(),
+        }
+    }
+
+    /// Returns how many keyframes are at or before the given time, starting
+    /// from the last time looked up
+    fn passed(
+        &self,
+        time: &SongTime,
+    ) -> usize
+    {
+        let keyframes = &self.keyframes;
+        let is_after = |passed: usize| {
+            passed == 0 || keyframes[passed - 1].song_time <= *time
+        };
+        let is_before = |passed: usize| {
+            passed == keyframes.len() || *time < keyframes[passed].song_time
+        };
+
+        // the time usually hasn't passed more than a few keyframes since it
+        // was last looked up, so those are walked through before searching
+        let mut passed = self.cursor.load(AtomicOrdering::Relaxed);
+        passed = passed.min(keyframes.len());
+
+        if is_after(passed) {
+            for _ in 0 .. CURSOR_STEPS {
+                if is_before(passed) {
+                    return passed;
+                }
+
+                passed += 1;
+            }
+        }
+
+        // never finds an equal, so it ends up past every keyframe at the time
+        keyframes
+            .binary_search_by(|keyframe| {
+                if keyframe.song_time <= *time {
+                    Ordering::Less
+                }
+                else {
+                    Ordering::Greater
+                }
+            })
+            .unwrap_err()
+    }
+}
+
+impl Interpolate for f32 {
+    fn interpolate(
+        &self,
+        other: &f32,
+        part: f32,
+    ) -> f32
+    {
+        mix(*self, *other, part)
+    }
+}
+
+impl Interpolate for Rad<f32> {
+    fn interpolate(
+        &self,
+        other: &Rad<f32>,
+        part: f32,
+    ) -> Rad<f32>
+    {
+        Rad(mix(self.0, other.0, part))
+    }
+}
+
+impl Interpolate for Deg<f32> {
+    fn interpolate(
+        &self,
+        other: &Deg<f32>,
+        part: f32,
+    ) -> Deg<f32>
+    {
+        Deg(mix(self.0, other.0, part))
+    }
+}
+
+impl Interpolate for Vector2<f32> {
+    fn interpolate(
+        &self,
+        other: &Vector2<f32>,
+        part: f32,
+    ) -> Vector2<f32>
+    {
+        Vector2::new(mix(self.x, other.x, part), mix(self.y, other.y, part))
+    }
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(
+        &self,
+        other: &Vector3<f32>,
+        part: f32,
+    ) -> Vector3<f32>
+    {
+        Vector3::new(
+            mix(self.x, other.x, part),
+            mix(self.y, other.y, part),
+            mix(self.z, other.z, part),
+        )
+    }
+}
+
+// the colors, which are interpolated as they are
+impl Interpolate for [f32; 3] {
+    fn interpolate(
+        &self,
+        other: &[f32; 3],
+        part: f32,
+    ) -> [f32; 3]
+    {
+        let mut out = *self;
+        for (out, other) in out.iter_mut().zip(other.iter()) {
+            *out = mix(*out, *other, part);
+        }
+
+        out
+    }
+}
+
+impl Interpolate for [f32; 4] {
+    fn interpolate(
+        &self,
+        other: &[f32; 4],
+        part: f32,
+    ) -> [f32; 4]
+    {
+        let mut out = *self;
+        for (out, other) in out.iter_mut().zip(other.iter()) {
+            *out = mix(*out, *other, part);
+        }
+
+        out
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the handles of the easing presets, the same as the ones of CSS
+const EASE_IN: [(f32, f32); 2] = [(0.42, 0.), (1., 1.)];
+const EASE_OUT: [(f32, f32); 2] = [(0., 0.), (0.58, 1.)];
+const EASE_IN_OUT: [(f32, f32); 2] = [(0.42, 0.), (0.58, 1.)];
+
+// below this tension, the sigmoids are too flat to be scaled to the whole
+// progress without losing their precision, so they're treated as linear
+const LINEAR_TENSION: f32 = 1e-3;
+
+// how close the bezier has to get to the progress before it stops looking
+const BEZIER_EPSILON: f32 = 1e-6;
+const BEZIER_NEWTON_STEPS: usize = 8;
+const BEZIER_BISECTION_STEPS: usize = 32;
+
 ////////////////////////////////////////////////////////////////////////////////
 
 #[derive(Debug, Clone)]
 pub enum TransformationKFCurve {
+    // half of a sigmoid, which eases out for a positive tension and in for a
+    // negative one. it's linear without tension.
     HalfSigmoid,
+
+    // a whole sigmoid, which eases in and out for a positive tension and does
+    // the opposite for a negative one. it's linear without tension.
     Sigmoid,
+
+    // the value moves in as many even steps as the tension, rounded, or jumps
+    // to the next keyframe without tension
     Stair,
 }
 
+/// A cubic bezier from one keyframe to the next, with the ends of its handles
+/// given as the progress of the time and of the value between the keyframes.
+///
+/// The handles are kept within the keyframes in time, so the value never goes
+/// back in time, but the value may overshoot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubicBezier {
+    pub handles: [(f32, f32); 2],
+}
+
+/// A cubic Hermite from one keyframe to the next, with the slopes it leaves
+/// and reaches the keyframes at. The slopes are in parts of the change between
+/// the keyframes over the whole time between them, so slopes of 1 are linear.
+///
+/// The tension of the keyframe flattens the slopes, from none at 0 to flat at
+/// 1, as in a cardinal spline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hermite {
+    pub slopes: (f32, f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    In,
+    Out,
+    InOut,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 impl KeyframeCurveType for TransformationKFCurve {
     fn interpolate(
         &self,
@@ -104,65 +421,344 @@ impl KeyframeCurveType for TransformationKFCurve {
         tension: f32,
     ) -> f32
     {
-        use crate::utils::sigmoid;
         use TransformationKFCurve::*;
 
-        match self {
+        let x = progress(time, this.1, next.1);
+
+        // every curve starts and ends at the keyframes exactly, which the
+        // scaled sigmoids only get close to
+        if x <= 0. {
+            return this.0;
+        }
+        if 1. <= x {
+            return next.0;
+        }
+
+        let y = match self {
             HalfSigmoid => {
-                if tension == 0. {
-                    // if the tension is zero, the interpolation becomes linear
-                    linear_map(
-                        (time.0 as f32 - this.0) / (next.0 as f32 - this.0),
-                        0.,
-                        1.,
-                        this.0,
-                        next.0,
-                    )
+                // the steepness gets close to linear as it goes to zero, so
+                // it's only treated as linear once it's too flat to normalize
+                if tension.abs() < LINEAR_TENSION {
+                    x
                 }
                 else if tension.is_sign_positive() {
-                    let right_end_x = tension.abs().ln() - 1.;
-                    let x_val = linear_map(
-                        (time.0 as f32 - this.0) / (next.0 as f32 - this.0),
-                        0.,
-                        1.,
-                        0.,
-                        right_end_x,
-                    );
-
-                    let y_val = sigmoid(x_val);
-                    let right_end_y = sigmoid(right_end_x);
-
-                    y_val / right_end_y
+                    half_sigmoid(x, tension)
                 }
                 else {
-                    let left_end_x = 1. - tension.abs().ln();
-                    let x_val = linear_map(
-                        (time.0 as f32 - this.0) / (next.0 as f32 - this.0),
-                        0.,
-                        1.,
-                        left_end_x,
-                        0.,
-                    );
-
-                    let y_val = sigmoid(x_val);
-                    let left_end_y = sigmoid(left_end_x);
-
-                    1. - (y_val / left_end_y)
+                    1. - half_sigmoid(1. - x, -tension)
                 }
             },
 
             Sigmoid => {
-                if tension == 0. {
-                    // if the tension is zero, the interpolation becomes linear
-                    return (time.0 as f32 - this.0) / (next.0 as f32 - this.0);
+                if tension.abs() < LINEAR_TENSION {
+                    x
+                }
+                else if tension.is_sign_positive() {
+                    full_sigmoid(x, tension)
                 }
+                else {
+                    inverse_sigmoid(x, -tension)
+                }
+            },
 
-                unimplemented!()
+            Stair => {
+                // the last step is only reached at the next keyframe
+                let steps = tension.abs().round().max(1.);
+                (x * steps).floor() / steps
             },
+        };
+
+        mix(this.0, next.0, y)
+    }
+}
 
-            Stair => unimplemented!(),
+impl CubicBezier {
+    pub fn new(
+        first: (f32, f32),
+        second: (f32, f32),
+    ) -> CubicBezier
+    {
+        CubicBezier {
+            handles: [first, second],
         }
     }
+
+    /// Returns the progress of the value at the given progress of the time
+    fn ease(
+        &self,
+        x: f32,
+    ) -> f32
+    {
+        if x <= 0. {
+            return 0.;
+        }
+        if 1. <= x {
+            return 1.;
+        }
+
+        let [(x1, y1), (x2, y2)] = self.handles;
+        let (x1, x2) = (x1.max(0.).min(1.), x2.max(0.).min(1.));
+
+        // the time is found by newton's method, falling back to bisecting
+        // wherever the curve is too flat for it
+        let mut t = x;
+        let mut is_found = false;
+
+        for _ in 0 .. BEZIER_NEWTON_STEPS {
+            let error = bezier(t, x1, x2) - x;
+            if error.abs() < BEZIER_EPSILON {
+                is_found = true;
+                break;
+            }
+
+            let slope = bezier_slope(t, x1, x2);
+            if slope.abs() < BEZIER_EPSILON {
+                break;
+            }
+
+            t -= error / slope;
+        }
+
+        if !is_found {
+            let (mut low, mut high) = (0., 1.);
+            t = x;
+
+            for _ in 0 .. BEZIER_BISECTION_STEPS {
+                let error = bezier(t, x1, x2) - x;
+                if error.abs() < BEZIER_EPSILON {
+                    break;
+                }
+
+                if error < 0. {
+                    low = t;
+                }
+                else {
+                    high = t;
+                }
+
+                t = (low + high) / 2.;
+            }
+        }
+
+        bezier(t.max(0.).min(1.), y1, y2)
+    }
+}
+
+impl KeyframeCurveType for CubicBezier {
+    fn interpolate(
+        &self,
+        time: &SongTime,
+        this: (f32, &SongTime),
+        next: (f32, &SongTime),
+        _tension: f32,
+    ) -> f32
+    {
+        let y = self.ease(progress(time, this.1, next.1));
+        mix(this.0, next.0, y)
+    }
+}
+
+impl Hermite {
+    pub fn new(slopes: (f32, f32)) -> Hermite {
+        Hermite {
+            slopes,
+        }
+    }
+
+    /// Makes the segment of a Catmull-Rom spline between `this` and `next`,
+    /// passing through the keyframes around them at the same slopes they
+    /// would. The ends of the spline are missing a keyframe on one side, so
+    /// they're given the slope towards the one on the other side.
+    ///
+    /// A segment between keyframes of the same value stays flat, since it has
+    /// no change to take the slopes in parts of.
+    pub fn catmull_rom(
+        before: Option<(f32, SongTime)>,
+        this: (f32, SongTime),
+        next: (f32, SongTime),
+        after: Option<(f32, SongTime)>,
+    ) -> Hermite
+    {
+        let length = (next.1 - this.1).0 as f32;
+        let change = next.0 - this.0;
+
+        // the slope at a keyframe is the one between its neighbors, scaled to
+        // the length and the change of this segment
+        let slope = |from: (f32, SongTime), to: (f32, SongTime)| {
+            let span = (to.1 - from.1).0 as f32;
+
+            if span <= 0. || change == 0. {
+                0.
+            }
+            else {
+                (to.0 - from.0) / span * length / change
+            }
+        };
+
+        Hermite {
+            slopes: (
+                slope(before.unwrap_or(this), next),
+                slope(this, after.unwrap_or(next)),
+            ),
+        }
+    }
+}
+
+impl KeyframeCurveType for Hermite {
+    fn interpolate(
+        &self,
+        time: &SongTime,
+        this: (f32, &SongTime),
+        next: (f32, &SongTime),
+        tension: f32,
+    ) -> f32
+    {
+        let x = progress(time, this.1, next.1);
+
+        // the ends are returned as they are, which the polynomial only gets
+        // close to
+        if x <= 0. {
+            return this.0;
+        }
+        if 1. <= x {
+            return next.0;
+        }
+
+        let flatten = 1. - tension.max(0.).min(1.);
+        let (out_slope, in_slope) =
+            (self.slopes.0 * flatten, self.slopes.1 * flatten);
+
+        let x2 = x * x;
+        let x3 = x2 * x;
+
+        // the basis of the keyframe being left is left out, since it's only
+        // mixed from
+        let y = (x3 - 2. * x2 + x) * out_slope +
+            (-2. * x3 + 3. * x2) +
+            (x3 - x2) * in_slope;
+
+        mix(this.0, next.0, y)
+    }
+}
+
+impl Easing {
+    pub fn bezier(self) -> CubicBezier {
+        let [first, second] = match self {
+            Easing::In => EASE_IN,
+            Easing::Out => EASE_OUT,
+            Easing::InOut => EASE_IN_OUT,
+        };
+
+        CubicBezier::new(first, second)
+    }
+}
+
+impl KeyframeCurveType for Easing {
+    fn interpolate(
+        &self,
+        time: &SongTime,
+        this: (f32, &SongTime),
+        next: (f32, &SongTime),
+        tension: f32,
+    ) -> f32
+    {
+        self.bezier().interpolate(time, this, next, tension)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns how far the time is from one keyframe to the next, from 0 to 1.
+/// Keyframes at the same time are already at the next one.
+fn progress(
+    time: &SongTime,
+    this: &SongTime,
+    next: &SongTime,
+) -> f32
+{
+    if next.0 <= this.0 {
+        return 1.;
+    }
+
+    let x = (time.0 - this.0) as f64 / (next.0 - this.0) as f64;
+    x.max(0.).min(1.) as f32
+}
+
+/// Mixes the values of two keyframes, so that each end is exactly its value
+fn mix(
+    this: f32,
+    next: f32,
+    y: f32,
+) -> f32
+{
+    if y == 1. {
+        next
+    }
+    else {
+        this + (next - this) * y
+    }
+}
+
+/// The upper half of a sigmoid as steep as the tension, scaled to go from 0 to
+/// 1 over the progress
+fn half_sigmoid(
+    x: f32,
+    tension: f32,
+) -> f32
+{
+    (sigmoid(x * tension) - 0.5) / (sigmoid(tension) - 0.5)
+}
+
+/// A whole sigmoid as steep as the tension, scaled to go from 0 to 1 over the
+/// progress
+fn full_sigmoid(
+    x: f32,
+    tension: f32,
+) -> f32
+{
+    let low = sigmoid(-tension);
+    let high = sigmoid(tension);
+
+    (sigmoid((2. * x - 1.) * tension) - low) / (high - low)
+}
+
+/// The inverse of the whole sigmoid, which is fast at the ends and slow in the
+/// middle
+fn inverse_sigmoid(
+    y: f32,
+    tension: f32,
+) -> f32
+{
+    let low = sigmoid(-tension);
+    let high = sigmoid(tension);
+
+    let s = (low + y * (high - low)).max(core::f32::MIN_POSITIVE);
+    let logit = (s / (1. - s).max(core::f32::MIN_POSITIVE)).ln();
+
+    ((logit / tension + 1.) / 2.).max(0.).min(1.)
+}
+
+/// One dimension of a cubic bezier from 0 to 1, with the given handles
+fn bezier(
+    t: f32,
+    first: f32,
+    second: f32,
+) -> f32
+{
+    let u = 1. - t;
+    3. * u * u * t * first + 3. * u * t * t * second + t * t * t
+}
+
+fn bezier_slope(
+    t: f32,
+    first: f32,
+    second: f32,
+) -> f32
+{
+    let u = 1. - t;
+    3. * u * u * first +
+        6. * u * t * (second - first) +
+        3. * t * t * (1. - second)
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -171,3 +767,337 @@ pub enum LaserKFCurve {
     HalfSigmoid,
     Sigmoid,
 }
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the keyframes are a thousand frames apart, and go from -2 to 3
+    const LENGTH: i64 = 1000;
+    const FROM: f32 = -2.;
+    const TO: f32 = 3.;
+
+    const TENSIONS: &[f32] = &[
+        0., 1e-8, -1e-8, 1e-7, -1e-7, 1e-6, -1e-6, 1e-4, -1e-4, 1e-3, -1e-3,
+        2e-3, -2e-3, 0.1, -0.1, 0.5, -0.5, 1., -1., 2., -2., 4., -4.,
+    ];
+
+    fn sample(
+        curve: &dyn KeyframeCurveType,
+        time: i64,
+        tension: f32,
+    ) -> f32
+    {
+        curve.interpolate(
+            &SongTime(time),
+            (FROM, &SongTime(0)),
+            (TO, &SongTime(LENGTH)),
+            tension,
+        )
+    }
+
+    fn linear(time: i64) -> f32 {
+        FROM + (TO - FROM) * time as f32 / LENGTH as f32
+    }
+
+    fn assert_ends(
+        curve: &dyn KeyframeCurveType,
+        tension: f32,
+    )
+    {
+        assert_eq!(sample(curve, 0, tension), FROM, "tension {}", tension);
+        assert_eq!(sample(curve, LENGTH, tension), TO, "tension {}", tension);
+
+        // and holds past them
+        assert_eq!(sample(curve, -1, tension), FROM, "tension {}", tension);
+        assert_eq!(
+            sample(curve, LENGTH + 1, tension),
+            TO,
+            "tension {}",
+            tension
+        );
+    }
+
+    // no value is more than the given step away from the one a frame before
+    fn assert_continuous(
+        curve: &dyn KeyframeCurveType,
+        tension: f32,
+        step: f32,
+    )
+    {
+        let mut last = sample(curve, 0, tension);
+
+        for time in 1 ..= LENGTH {
+            let value = sample(curve, time, tension);
+
+            assert!(value.is_finite(), "tension {} at {}", tension, time);
+            assert!(
+                (value - last).abs() <= step,
+                "tension {} jumps from {} to {} at {}",
+                tension,
+                last,
+                value,
+                time
+            );
+
+            last = value;
+        }
+    }
+
+    fn curves() -> Vec<TransformationKFCurve> {
+        vec![TransformationKFCurve::HalfSigmoid, TransformationKFCurve::Sigmoid]
+    }
+
+    #[test]
+    fn sigmoids_hit_their_ends() {
+        for curve in curves() {
+            for &tension in TENSIONS.iter().chain(&[20., -20., 100., -100.]) {
+                assert_ends(&curve, tension);
+            }
+        }
+    }
+
+    #[test]
+    fn sigmoids_are_continuous() {
+        for curve in curves() {
+            for &tension in TENSIONS {
+                assert_continuous(&curve, tension, 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_sigmoids_are_linear() {
+        for curve in curves() {
+            for &tension in TENSIONS {
+                if 1e-2 < tension.abs() {
+                    continue;
+                }
+
+                for time in 0 ..= LENGTH {
+                    let value = sample(&curve, time, tension);
+                    let linear = linear(time);
+
+                    assert!(
+                        (value - linear).abs() < 1e-2,
+                        "tension {} is {} instead of {} at {}",
+                        tension,
+                        value,
+                        linear,
+                        time
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn half_sigmoids_ease_out_or_in() {
+        let curve = TransformationKFCurve::HalfSigmoid;
+        let linear = (FROM + TO) / 2.;
+
+        assert!(linear < sample(&curve, LENGTH / 2, 2.));
+        assert!(sample(&curve, LENGTH / 2, -2.) < linear);
+    }
+
+    #[test]
+    fn stairs_step_evenly() {
+        let curve = TransformationKFCurve::Stair;
+
+        for &tension in TENSIONS.iter().chain(&[3., -3., 7.]) {
+            assert_ends(&curve, tension);
+
+            let steps = tension.abs().round().max(1.);
+            for time in 0 .. LENGTH {
+                let value = sample(&curve, time, tension);
+                let step = (value - FROM) / (TO - FROM) * steps;
+
+                assert!((step - step.round()).abs() < 1e-4);
+            }
+        }
+
+        // without tension, it holds until the next keyframe
+        assert_eq!(sample(&curve, LENGTH - 1, 0.), FROM);
+    }
+
+    #[test]
+    fn beziers_hit_their_ends() {
+        let beziers = vec![
+            CubicBezier::new((0., 0.), (1., 1.)),
+            CubicBezier::new((0.25, 0.1), (0.25, 1.)),
+            CubicBezier::new((0.5, -1.), (0.5, 2.)),
+            CubicBezier::new((0.75, 0.), (0.25, 1.)),
+            Easing::In.bezier(),
+            Easing::Out.bezier(),
+            Easing::InOut.bezier(),
+        ];
+
+        for bezier in &beziers {
+            for &tension in TENSIONS {
+                assert_ends(bezier, tension);
+                assert_continuous(bezier, tension, 0.05);
+            }
+        }
+
+        for easing in &[Easing::In, Easing::Out, Easing::InOut] {
+            assert_ends(easing, 0.);
+            assert_continuous(easing, 0., 0.05);
+        }
+    }
+
+    #[test]
+    fn ease_presets_go_forward() {
+        for easing in &[Easing::In, Easing::Out, Easing::InOut] {
+            let mut last = FROM;
+
+            for time in 0 ..= LENGTH {
+                let value = sample(easing, time, 0.);
+                assert!(last - 1e-5 <= value, "{:?} at {}", easing, time);
+                last = value;
+            }
+        }
+    }
+
+    #[test]
+    fn hermites_hit_their_ends() {
+        let hermites = vec![
+            Hermite::new((0., 0.)),
+            Hermite::new((1., 1.)),
+            Hermite::new((3., -2.)),
+            Hermite::catmull_rom(
+                Some((1., SongTime(-500))),
+                (FROM, SongTime(0)),
+                (TO, SongTime(LENGTH)),
+                None,
+            ),
+        ];
+
+        for hermite in &hermites {
+            for &tension in TENSIONS {
+                assert_ends(hermite, tension);
+                assert_continuous(hermite, tension, 0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_hermites_are_linear() {
+        let hermite = Hermite::new((1., 1.));
+
+        for time in 0 ..= LENGTH {
+            let value = sample(&hermite, time, 0.);
+            assert!((value - linear(time)).abs() < 1e-4);
+        }
+    }
+
+    fn track(keyframes: &[(i64, f32)]) -> KeyframeTrack<TransformationKFCurve> {
+        let keyframes = keyframes
+            .iter()
+            .map(|(time, value)| {
+                Keyframe::new(
+                    *value,
+                    SongTime(*time),
+                    TransformationKFCurve::HalfSigmoid,
+                    0.,
+                )
+            })
+            .collect();
+
+        KeyframeTrack::new(keyframes, 7.)
+    }
+
+    fn assert_value(
+        track: &KeyframeTrack<TransformationKFCurve>,
+        time: i64,
+        expected: f32,
+    )
+    {
+        let value = track.value_at(&SongTime(time));
+        assert!(
+            (value - expected).abs() < 1e-4,
+            "{} instead of {} at {}",
+            value,
+            expected,
+            time
+        );
+    }
+
+    #[test]
+    fn tracks_hold_past_their_ends() {
+        let track = track(&[(1000, 1.), (2000, 3.)]);
+
+        assert_value(&track, i64::min_value(), 1.);
+        assert_value(&track, 0, 1.);
+        assert_value(&track, 1000, 1.);
+        assert_value(&track, 1500, 2.);
+        assert_value(&track, 2000, 3.);
+        assert_value(&track, 5000, 3.);
+        assert_value(&track, i64::max_value(), 3.);
+
+        // a lone keyframe holds everywhere
+        let lone = self::track(&[(1000, 4.)]);
+        assert_value(&lone, 0, 4.);
+        assert_value(&lone, 2000, 4.);
+    }
+
+    #[test]
+    fn empty_tracks_are_at_their_default() {
+        let track = track(&[]);
+
+        assert!(track.is_empty());
+        assert_value(&track, -1000, 7.);
+        assert_value(&track, 0, 7.);
+        assert_value(&track, 1000, 7.);
+    }
+
+    #[test]
+    fn tracks_jump_at_keyframes_at_the_same_time() {
+        // given out of order, but the slam keeps its order
+        let track = track(&[(2000, 5.), (0, 0.), (1000, 1.), (1000, 5.)]);
+
+        assert_value(&track, 500, 0.5);
+        assert_value(&track, 999, 0.999);
+        assert_value(&track, 1000, 5.);
+        assert_value(&track, 1500, 5.);
+
+        // and the same looking backwards
+        assert_value(&track, 999, 0.999);
+        assert_value(&track, 1000, 5.);
+    }
+
+    #[test]
+    fn tracks_look_up_any_time_in_any_order() {
+        // a keyframe every thousand frames, valued by its index
+        let keyframes = (0 .. 20)
+            .map(|idx| (idx * 1000, idx as f32))
+            .collect::<Vec<_>>();
+        let track = track(&keyframes);
+        let cursor = || track.cursor.load(AtomicOrdering::Relaxed);
+
+        // a few keyframes forward are stepped through
+        assert_value(&track, 500, 0.5);
+        assert_eq!(cursor(), 1);
+        assert_value(&track, 3500, 3.5);
+        assert_eq!(cursor(), 4);
+
+        // while further ahead and backwards are searched for
+        assert_value(&track, 15_250, 15.25);
+        assert_eq!(cursor(), 16);
+        assert_value(&track, 2_750, 2.75);
+        assert_eq!(cursor(), 3);
+        assert_value(&track, -100, 0.);
+        assert_eq!(cursor(), 0);
+        assert_value(&track, 25_000, 19.);
+        assert_eq!(cursor(), 20);
+        assert_value(&track, 19_000, 19.);
+        assert_value(&track, 18_999, 18.999);
+
+        // every time gives the same value whatever was looked up before
+        for time in (-4 .. 84).rev().map(|step| step * 250) {
+            let expected = (time as f32 / 1000.).max(0.).min(19.);
+            assert_value(&track, time, expected);
+        }
+    }
+}
@@ -1,7 +1,12 @@
 pub mod governor;
 pub mod lanes;
+pub mod notes;
 //pub mod bt;
 //pub mod fx;
 //pub mod lasers;
 pub mod keyframe;
+pub mod scroll_map;
+pub mod session;
 pub mod song_timer;
+pub mod tempo_map;
+pub mod tilt;
@@ -0,0 +1,750 @@
+use crate::{
+    chart::{
+        Chart,
+        LaserSection,
+    },
+    environment::{
+        actor_wrapper::{
+            ActorWrapper,
+            ContextWrapper,
+            RenderDetails,
+            RenderPayload,
+            RenderableActorWrapper,
+            UpdatePayload,
+        },
+        update_routine::CanBeWindowHandled,
+        RenderWindowParts,
+        UpdateWindowParts,
+    },
+    judgment::hold::LongHitType,
+    pipelines::notes::*,
+    song_player::{
+        scroll_map::ScrollMap,
+        session::PlaySettings,
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use cgmath::{
+    Matrix4,
+    Vector3,
+};
+use gfx::{
+    buffer::Role,
+    format::Srgba8,
+    handle::{
+        Buffer,
+        RenderTargetView,
+    },
+    memory::{
+        Bind,
+        Usage,
+    },
+    pso::PipelineState,
+    traits::FactoryExt as _,
+    Factory as _,
+    Slice,
+};
+use gfx_device_gl::{
+    Factory,
+    Resources,
+};
+use gfx_graphics::{
+    Texture,
+    TextureSettings,
+};
+use image::{
+    ImageResult,
+    RgbaImage,
+};
+use shader_version::{
+    glsl::GLSL,
+    Shaders,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+// the BT lanes are 42 of the 256 pixels of the texture of the lanes. the notes
+// are laid out in widths of a BT lane, and the lanes are 2 units wide.
+const LANE_WIDTH: f32 = 42. / 128.;
+
+// how many beats of the chart are shown on the lanes at a hi-speed of 1
+const SHOWN_BEATS: f32 = 4.;
+
+// the heights of the things that don't stretch with the hi-speed, in beats
+const CHIP_HEIGHT: f32 = 0.1;
+const SLAM_HEIGHT: f32 = 0.1;
+const LINE_HEIGHT: f32 = 0.02;
+
+// the lasers rest at the middle of the spaces at either side of the BT lanes
+const LASER_RANGE: f32 = 2.5;
+const LASER_WIDTH: f32 = 0.8;
+
+const MEASURE_LINE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.];
+const BT_HOLD_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.];
+const FX_HOLD_COLOR: [f32; 4] = [1., 0.6, 0., 0.7];
+const FX_CHIP_COLOR: [f32; 4] = [1., 0.5, 0., 1.];
+const LASER_COLORS: [[f32; 4]; 2] = [[0., 0.5, 1., 0.8], [1., 0.2, 0.6, 0.8]];
+
+// how bright the holds are once they're missed
+const MISS_BRIGHTNESS: f32 = 0.4;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The notes of a chart as they scroll down the lanes. Everything is placed by
+/// the scroll map, so the notes follow the BPM changes, the stops and the
+/// changes of the scroll speed together.
+pub struct NotesInitRequest {
+    note_image: RgbaImage,
+    scroll_map: ScrollMap,
+
+    hi_speed:      f32,
+    visual_offset: SongTime,
+
+    // the scroll positions of the BT chips of each lane
+    bt_chips: [Vec<f32>; 4],
+
+    // bottom to top, in the order they're drawn
+    lines:    Vec<[ScrollVertex; 4]>,
+    holds:    Vec<[ScrollVertex; 4]>,
+    fx_chips: Vec<[ScrollVertex; 4]>,
+    lasers:   Vec<[ScrollVertex; 4]>,
+}
+
+impl NotesInitRequest {
+    pub fn from_chart(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+        settings: &PlaySettings,
+    ) -> ImageResult<NotesInitRequest>
+    {
+        let note_image = image::load_from_memory(include_bytes!(
+            "../../build_assets/note.png"
+        ))?
+        .to_rgba();
+
+        let scroll_map = ScrollMap::from_chart(chart, tempo_map.clone());
+
+        let mut bt_chips = [vec![], vec![], vec![], vec![]];
+        for (lane, chips) in bt_chips.iter_mut().enumerate() {
+            *chips = scroll_map.chip_positions(&chart.bt[lane]);
+        }
+
+        let lines = scroll_map
+            .measure_line_positions(chart.last_tick())
+            .into_iter()
+            .map(|pos| {
+                ScrollVertex::quad(
+                    ([-2., pos], [2., pos]),
+                    ([-2., pos], [2., pos]),
+                    LINE_HEIGHT,
+                    MEASURE_LINE_COLOR,
+                )
+            })
+            .collect();
+
+        // the FX holds are under the BT holds
+        let mut holds = vec![];
+        for (lane, notes) in chart.fx.iter().enumerate() {
+            let positions = scroll_map.hold_positions(notes);
+            holds.extend(hold_quads(&positions, fx_x(lane), 2., FX_HOLD_COLOR));
+        }
+
+        for (lane, notes) in chart.bt.iter().enumerate() {
+            let positions = scroll_map.hold_positions(notes);
+            holds.extend(hold_quads(&positions, bt_x(lane), 1., BT_HOLD_COLOR));
+        }
+
+        let mut fx_chips = vec![];
+        for (lane, notes) in chart.fx.iter().enumerate() {
+            let x = fx_x(lane);
+
+            fx_chips.extend(scroll_map.chip_positions(notes).into_iter().map(
+                |pos| {
+                    ScrollVertex::quad(
+                        ([x - 1., pos], [x + 1., pos]),
+                        ([x - 1., pos], [x + 1., pos]),
+                        CHIP_HEIGHT,
+                        FX_CHIP_COLOR,
+                    )
+                },
+            ));
+        }
+
+        let mut lasers = vec![];
+        for (side, sections) in chart.lasers.iter().enumerate() {
+            for section in sections.iter() {
+                lasers.extend(laser_quads(
+                    &scroll_map,
+                    section,
+                    LASER_COLORS[side],
+                ));
+            }
+        }
+
+        Ok(NotesInitRequest {
+            note_image,
+            scroll_map,
+
+            hi_speed: settings.hi_speed,
+            visual_offset: settings.visual_offset(tempo_map.freq()),
+
+            bt_chips,
+
+            lines,
+            holds,
+            fx_chips,
+            lasers,
+        })
+    }
+}
+
+impl CanBeWindowHandled for NotesInitRequest {
+    type Response = Notes;
+
+    fn handle<'a>(
+        self,
+        uwp: &mut UpdateWindowParts<'a>,
+    ) -> Self::Response
+    {
+        let note_pipeline = uwp
+            .tex_ctx
+            .factory
+            .create_pipeline_simple(
+                Shaders::new()
+                    .set(
+                        GLSL::V3_30,
+                        include_str!("../shaders/bt_chip_notes.vert.glsl"),
+                    )
+                    .get(uwp.glsl)
+                    .unwrap()
+                    .as_bytes(),
+                Shaders::new()
+                    .set(
+                        GLSL::V3_30,
+                        include_str!("../shaders/bt_chip_notes.frag.glsl"),
+                    )
+                    .get(uwp.glsl)
+                    .unwrap()
+                    .as_bytes(),
+                note_pipe::new(),
+            )
+            .unwrap();
+
+        let scroll_pipeline = uwp
+            .tex_ctx
+            .factory
+            .create_pipeline_simple(
+                Shaders::new()
+                    .set(
+                        GLSL::V3_30,
+                        include_str!("../shaders/scroll_notes.vert.glsl"),
+                    )
+                    .get(uwp.glsl)
+                    .unwrap()
+                    .as_bytes(),
+                Shaders::new()
+                    .set(
+                        GLSL::V3_30,
+                        include_str!("../shaders/scroll_notes.frag.glsl"),
+                    )
+                    .get(uwp.glsl)
+                    .unwrap()
+                    .as_bytes(),
+                scroll_pipe::new(),
+            )
+            .unwrap();
+
+        let note_texture = Texture::from_image(
+            &mut uwp.tex_ctx,
+            &self.note_image,
+            &TextureSettings::new(),
+        )
+        .unwrap();
+
+        let factory = &mut uwp.tex_ctx.factory;
+
+        // the chips are drawn with the four corners of each of them
+        let (chip_vertices, _) = NoteLocation::from_vec4(self.bt_chips);
+        let bt_chips = if chip_vertices.is_empty() {
+            None
+        }
+        else {
+            let indices = quad_indices(chip_vertices.len() / 4);
+            Some(factory.create_vertex_buffer_with_slice(
+                &chip_vertices,
+                indices.as_slice(),
+            ))
+        };
+
+        Notes {
+            scroll_map: self.scroll_map,
+
+            hi_speed: self.hi_speed,
+            visual_offset: self.visual_offset,
+
+            transform: notes_transform().into(),
+
+            bt_chips,
+            lines: Shapes::new(factory, &self.lines),
+            holds: Shapes::dynamic(factory, self.holds.len()),
+            fx_chips: Shapes::new(factory, &self.fx_chips),
+            lasers: Shapes::new(factory, &self.lasers),
+
+            hold_quads: self.holds,
+            shown_hit_types: None,
+
+            note_texture,
+            note_pipeline,
+            scroll_pipeline,
+        }
+    }
+}
+
+/// Returns the matrix that puts the notes on the texture of the lanes
+fn notes_transform() -> Matrix4<f32> {
+    // the lanes' governor only shows the upper right quarter of the texture of
+    // the lanes. see lane_governor.vert.glsl.
+    Matrix4::from_translation(Vector3::new(0.5, 0.5, 0.)) *
+    Matrix4::from_nonuniform_scale(0.5, 0.5, 1.) *
+
+    // the judgment line is at the bottom of the lanes
+    Matrix4::from_translation(Vector3::new(0., -1., 0.)) *
+
+    // from the widths of the BT lanes and the beats
+    Matrix4::from_nonuniform_scale(LANE_WIDTH, 2. / SHOWN_BEATS, 1.)
+}
+
+/// Returns the position of the center of a BT lane
+fn bt_x(lane: usize) -> f32 {
+    lane as f32 - 1.5
+}
+
+/// Returns the position of the center of an FX lane, which is as wide as two
+/// BT lanes
+fn fx_x(lane: usize) -> f32 {
+    lane as f32 * 2. - 1.
+}
+
+/// Returns the position of the center of a laser of the given value
+fn laser_x(
+    value: f64,
+    wide: bool,
+) -> f32
+{
+    let x = (value as f32 * 2. - 1.) * LASER_RANGE;
+
+    if wide {
+        x * 2.
+    }
+    else {
+        x
+    }
+}
+
+/// Returns the color of a hold as it's judged. The holds light up while
+/// they're held, and the missed ones are dimmed.
+fn hold_fill(
+    fill: [f32; 4],
+    hit_type: LongHitType,
+) -> [f32; 4]
+{
+    let [r, g, b, a] = fill;
+    let lit = |c: f32| c + (1. - c) / 2.;
+
+    match hit_type {
+        LongHitType::Active => [lit(r), lit(g), lit(b), a],
+        LongHitType::Miss => {
+            [
+                r * MISS_BRIGHTNESS,
+                g * MISS_BRIGHTNESS,
+                b * MISS_BRIGHTNESS,
+                a,
+            ]
+        },
+        LongHitType::Incoming | LongHitType::Cleared => fill,
+    }
+}
+
+fn hold_quads(
+    positions: &[(f32, f32)],
+    x: f32,
+    width: f32,
+    fill: [f32; 4],
+) -> Vec<[ScrollVertex; 4]>
+{
+    let (left, right) = (x - width / 2., x + width / 2.);
+
+    positions
+        .iter()
+        .map(|&(start, end)| {
+            ScrollVertex::quad(
+                ([left, start], [right, start]),
+                ([left, end], [right, end]),
+                0.,
+                fill,
+            )
+        })
+        .collect()
+}
+
+/// Returns the segments between the points of a laser section, along with a
+/// bar across each of its slams
+fn laser_quads(
+    scroll_map: &ScrollMap,
+    section: &LaserSection,
+    fill: [f32; 4],
+) -> Vec<[ScrollVertex; 4]>
+{
+    let half = LASER_WIDTH / 2.;
+    let x = |value| laser_x(value, section.wide);
+    let points = scroll_map.laser_positions(section);
+
+    // a segment leaves a point at the value after its slam, if any
+    let segments = points.windows(2).map(|pair| {
+        let (start, from) = (pair[0].0, pair[0].1.vf);
+        let (end, to) = (pair[1].0, pair[1].1.v);
+
+        ScrollVertex::quad(
+            ([x(from) - half, start], [x(from) + half, start]),
+            ([x(to) - half, end], [x(to) + half, end]),
+            0.,
+            fill,
+        )
+    });
+
+    let slams = points.iter().filter(|(_, value)| value.is_slam()).map(
+        |&(pos, value)| {
+            let left = x(value.v).min(x(value.vf)) - half;
+            let right = x(value.v).max(x(value.vf)) + half;
+
+            ScrollVertex::quad(
+                ([left, pos], [right, pos]),
+                ([left, pos], [right, pos]),
+                SLAM_HEIGHT,
+                fill,
+            )
+        },
+    );
+
+    segments.chain(slams).collect()
+}
+
+/// Returns the indices of the two triangles of each of the quads. The first
+/// and the last corners of each quad must be across from each other, as they
+/// are for the chips and for `ScrollVertex::quad`.
+fn quad_indices(count: usize) -> Vec<u32> {
+    (0 .. count as u32)
+        .flat_map(|quad| {
+            [0, 1, 2, 2, 1, 3].iter().map(move |corner| quad * 4 + corner)
+        })
+        .collect()
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+struct Shapes {
+    vbuf:  Buffer<Resources, ScrollVertex>,
+    slice: Slice<Resources>,
+}
+
+impl Shapes {
+    // there's nothing to draw if there are no shapes
+    fn new(
+        factory: &mut Factory,
+        quads: &[[ScrollVertex; 4]],
+    ) -> Option<Shapes>
+    {
+        if quads.is_empty() {
+            return None;
+        }
+
+        let vertices = quads.iter().flat_map(|q| q.iter()).cloned();
+        let indices = quad_indices(quads.len());
+
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(
+            &vertices.collect::<Vec<_>>(),
+            indices.as_slice(),
+        );
+
+        Some(Shapes {
+            vbuf,
+            slice,
+        })
+    }
+
+    // the vertices are left to be written with `update_buffer`, since they
+    // change as the shapes are drawn
+    fn dynamic(
+        factory: &mut Factory,
+        quads: usize,
+    ) -> Option<Shapes>
+    {
+        if quads == 0 {
+            return None;
+        }
+
+        let vbuf = factory
+            .create_buffer(
+                quads * 4,
+                Role::Vertex,
+                Usage::Dynamic,
+                Bind::empty(),
+            )
+            .unwrap();
+
+        let indices = quad_indices(quads);
+        let slice = Slice {
+            start:       0,
+            end:         indices.len() as u32,
+            base_vertex: 0,
+            instances:   None,
+            buffer:      factory.create_index_buffer(indices.as_slice()),
+        };
+
+        Some(Shapes {
+            vbuf,
+            slice,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub struct NotesRenderDetails {
+    transform:   [[f32; 4]; 4],
+    hi_speed:    f32,
+    song_offset: f32,
+
+    lanes_target: RenderTargetView<Resources, Srgba8>,
+    laser_target: RenderTargetView<Resources, Srgba8>,
+
+    bt_chips: Option<(Buffer<Resources, NoteLocation>, Slice<Resources>)>,
+    lines:    Option<Shapes>,
+    holds:    Option<Shapes>,
+    fx_chips: Option<Shapes>,
+    lasers:   Option<Shapes>,
+
+    // the holds are only written again once they've changed
+    hold_vertices: Option<Vec<ScrollVertex>>,
+
+    note_texture:    Texture<Resources>,
+    note_pipeline:   PipelineState<Resources, note_pipe::Meta>,
+    scroll_pipeline: PipelineState<Resources, scroll_pipe::Meta>,
+}
+
+impl NotesRenderDetails {
+    fn draw_shapes<'a>(
+        &self,
+        rwp: &mut RenderWindowParts<'a>,
+        shapes: Option<&Shapes>,
+        out_color: &RenderTargetView<Resources, Srgba8>,
+    )
+    {
+        let shapes = match shapes {
+            Some(shapes) => shapes,
+            None => return,
+        };
+
+        let data = scroll_pipe::Data {
+            vbuf:        shapes.vbuf.clone(),
+            transform:   self.transform,
+            hi_speed:    self.hi_speed,
+            song_offset: self.song_offset,
+            out_color:   out_color.clone(),
+        };
+
+        rwp.tex_ctx
+            .encoder
+            .draw(&shapes.slice, &self.scroll_pipeline, &data);
+    }
+}
+
+impl RenderDetails for NotesRenderDetails {
+    fn render<'a>(
+        self,
+        rwp: &mut RenderWindowParts<'a>,
+    )
+    {
+        if let (Some(holds), Some(vertices)) =
+            (self.holds.as_ref(), self.hold_vertices.as_ref())
+        {
+            rwp.tex_ctx
+                .encoder
+                .update_buffer(&holds.vbuf, vertices, 0)
+                .unwrap();
+        }
+
+        // bottom to top, this is the ordering of render:
+        // Measure Lines -> FX Hold -> BT Hold -> FX Chip -> BT Chip
+        let lanes_target = &self.lanes_target;
+        self.draw_shapes(rwp, self.lines.as_ref(), lanes_target);
+        self.draw_shapes(rwp, self.holds.as_ref(), lanes_target);
+        self.draw_shapes(rwp, self.fx_chips.as_ref(), lanes_target);
+
+        if let Some((vbuf, slice)) = self.bt_chips.as_ref() {
+            let data = note_pipe::Data {
+                note_buffer:         vbuf.clone(),
+                transform:           self.transform,
+                hi_speed:            self.hi_speed,
+                song_offset:         self.song_offset,
+                out_color:           lanes_target.clone(),
+                texture_buffer:      (
+                    self.note_texture.view.clone(),
+                    self.note_texture.sampler.clone(),
+                ),
+                note_graphic_height: CHIP_HEIGHT,
+            };
+
+            rwp.tex_ctx.encoder.draw(slice, &self.note_pipeline, &data);
+        }
+
+        // the lasers have a texture of their own, which is drawn anew every
+        // frame
+        rwp.tex_ctx.encoder.clear(&self.laser_target, [0.; 4]);
+        self.draw_shapes(rwp, self.lasers.as_ref(), &self.laser_target);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+pub struct Notes {
+    scroll_map: ScrollMap,
+
+    hi_speed:      f32,
+    visual_offset: SongTime,
+
+    // from the notes into the texture of the lanes
+    transform: [[f32; 4]; 4],
+
+    bt_chips: Option<(Buffer<Resources, NoteLocation>, Slice<Resources>)>,
+    lines:    Option<Shapes>,
+    holds:    Option<Shapes>,
+    fx_chips: Option<Shapes>,
+    lasers:   Option<Shapes>,
+
+    // the holds as they are before they're judged, FX before BT like the
+    // judges' lanes, and the states they were last drawn in
+    hold_quads:      Vec<[ScrollVertex; 4]>,
+    shown_hit_types: Option<Vec<LongHitType>>,
+
+    note_texture:    Texture<Resources>,
+    note_pipeline:   PipelineState<Resources, note_pipe::Meta>,
+    scroll_pipeline: PipelineState<Resources, scroll_pipe::Meta>,
+}
+
+/// What the notes are drawn with, besides the time
+#[derive(Debug, Clone)]
+pub struct NotesPayload {
+    // the lasers are drawn on this, while the rest is drawn on the color
+    // target
+    pub laser_target: RenderTargetView<Resources, Srgba8>,
+
+    // how far along each of the holds is, by lane
+    pub bt_holds: Vec<Vec<LongHitType>>,
+    pub fx_holds: Vec<Vec<LongHitType>>,
+}
+
+impl Notes {
+    /// Returns the holds colored by how they've been judged, if they've
+    /// changed since they were last drawn
+    fn judged_holds(
+        &mut self,
+        payload: &NotesPayload,
+    ) -> Option<Vec<ScrollVertex>>
+    {
+        // the FX holds are drawn under the BT holds, so they come first
+        let hit_types = payload
+            .fx_holds
+            .iter()
+            .chain(payload.bt_holds.iter())
+            .flat_map(|lane| lane.iter().cloned())
+            .collect::<Vec<_>>();
+
+        if self.shown_hit_types.as_ref() == Some(&hit_types) {
+            return None;
+        }
+
+        // the holds that aren't judged, like those of a song that's only for
+        // show, are yet to come
+        let judged = hit_types
+            .iter()
+            .cloned()
+            .chain(core::iter::repeat(LongHitType::Incoming));
+
+        let vertices = self
+            .hold_quads
+            .iter()
+            .zip(judged)
+            .flat_map(|(quad, hit_type)| {
+                quad.iter().map(move |vertex| {
+                    ScrollVertex {
+                        fill: hold_fill(vertex.fill, hit_type),
+                        ..*vertex
+                    }
+                })
+            })
+            .collect();
+
+        self.shown_hit_types = Some(hit_types);
+        Some(vertices)
+    }
+}
+
+impl ActorWrapper for Notes {
+    type Payload = ();
+
+    fn update(
+        &mut self,
+        _: UpdatePayload<Self::Payload>,
+        _: &ContextWrapper<Self>,
+    )
+    {
+        // the notes only move with the song time, which comes with the render
+    }
+}
+
+impl RenderableActorWrapper for Notes {
+    type Details = NotesRenderDetails;
+
+    type Payload = NotesPayload;
+
+    fn emit_render_details(
+        &mut self,
+        payload: RenderPayload<Self::Payload>,
+        _: &ContextWrapper<Self>,
+    ) -> Self::Details
+    {
+        let song_time =
+            payload.get_time().song_time.clone().unwrap_or(SongTime(0));
+
+        // the notes are drawn as late as the visual offset says
+        let song_offset =
+            self.scroll_map.current_position(song_time - self.visual_offset);
+
+        let hold_vertices = self.judged_holds(&payload.payload);
+
+        NotesRenderDetails {
+            transform: self.transform,
+            hi_speed: self.hi_speed,
+            song_offset,
+
+            lanes_target: payload.color_target,
+            laser_target: payload.payload.laser_target,
+
+            bt_chips: self.bt_chips.clone(),
+            lines: self.lines.clone(),
+            holds: self.holds.clone(),
+            fx_chips: self.fx_chips.clone(),
+            lasers: self.lasers.clone(),
+
+            hold_vertices,
+
+            note_texture: self.note_texture.clone(),
+            note_pipeline: self.note_pipeline.clone(),
+            scroll_pipeline: self.scroll_pipeline.clone(),
+        }
+    }
+}
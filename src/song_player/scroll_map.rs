@@ -0,0 +1,319 @@
+use crate::{
+    chart::{
+        Chart,
+        GraphPoint,
+        GraphValue,
+        Interval,
+        LaserSection,
+        Tick,
+        TICKS_PER_BEAT,
+    },
+    song_player::{
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Maps the song time into the position of the notes along the lanes.
+///
+/// The position is measured in beats scrolled at normal speed. Since it follows
+/// the ticks, the notes scroll faster on higher BPMs and freeze during stops.
+/// On top of that, the chart may change the scroll speed smoothly.
+///
+/// Everything that scrolls must use this map so that they all stay together.
+/// The shaders then only have to subtract the current position from the
+/// position of the notes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollMap {
+    tempo_map: TempoMap,
+
+    // an empty list means a constant scroll speed of 1
+    points: Vec<ScrollPoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScrollPoint {
+    tick:     f64,
+    position: f64,
+
+    // the speed upon arriving at and leaving from the point
+    v:  f64,
+    vf: f64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl ScrollMap {
+    pub fn from_chart(
+        chart: &Chart,
+        tempo_map: TempoMap,
+    ) -> ScrollMap
+    {
+        ScrollMap::new(tempo_map, &chart.scroll_speed)
+    }
+
+    /// Creates the map from the scroll speed multipliers. The speed is linearly
+    /// interpolated between the points.
+    pub fn new(
+        tempo_map: TempoMap,
+        scroll_speed: &[GraphPoint],
+    ) -> ScrollMap
+    {
+        let mut points: Vec<ScrollPoint> =
+            Vec::with_capacity(scroll_speed.len());
+
+        for point in scroll_speed.iter() {
+            let tick = point.tick as f64;
+            let GraphValue {
+                v,
+                vf,
+            } = point.value;
+
+            // the speed before the first point is the same as the first
+            let position = match points.last() {
+                Some(last) => {
+                    last.position + (tick - last.tick) * (last.vf + v) / 2.
+                },
+                None => tick * v,
+            };
+
+            points.push(ScrollPoint {
+                tick,
+                position,
+                v,
+                vf,
+            });
+        }
+
+        ScrollMap {
+            tempo_map,
+            points,
+        }
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// Returns the scroll position of the given tick
+    pub fn tick_to_position(
+        &self,
+        tick: f64,
+    ) -> f64
+    {
+        let idx = self.points.partition_point(|p| p.tick <= tick);
+
+        let ticks = match (idx, self.points.get(idx)) {
+            // constant speed all throughout
+            (0, None) => tick,
+
+            // before the first point
+            (0, Some(first)) => tick * first.v,
+
+            // after the last point
+            (_, None) => {
+                let last = &self.points[idx - 1];
+                last.position + (tick - last.tick) * last.vf
+            },
+
+            // in between two points
+            (_, Some(next)) => {
+                let prev = &self.points[idx - 1];
+                let progress = (tick - prev.tick) / (next.tick - prev.tick);
+                let speed = prev.vf + (next.v - prev.vf) * progress;
+
+                prev.position + (tick - prev.tick) * (prev.vf + speed) / 2.
+            },
+        };
+
+        ticks / TICKS_PER_BEAT as f64
+    }
+
+    /// Returns the scroll position at the given time. This is the value the
+    /// note shaders take as the song offset.
+    pub fn current_position(
+        &self,
+        time: SongTime,
+    ) -> f32
+    {
+        self.tick_to_position(self.tempo_map.song_time_to_tick(time)) as f32
+    }
+
+    ////////////////////// positions of the scrolling things ///////////////////
+
+    /// Returns the positions of the chips among the notes of a lane
+    pub fn chip_positions(
+        &self,
+        lane: &[Interval],
+    ) -> Vec<f32>
+    {
+        lane.iter()
+            .filter(|note| note.is_chip())
+            .map(|note| self.tick_position(note.tick))
+            .collect()
+    }
+
+    /// Returns the start and end positions of the holds among the notes of a
+    /// lane
+    pub fn hold_positions(
+        &self,
+        lane: &[Interval],
+    ) -> Vec<(f32, f32)>
+    {
+        lane.iter()
+            .filter(|note| !note.is_chip())
+            .map(|note| {
+                (
+                    self.tick_position(note.tick),
+                    self.tick_position(note.tick + note.length),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the positions of each of the points of a laser section
+    pub fn laser_positions(
+        &self,
+        section: &LaserSection,
+    ) -> Vec<(f32, GraphValue)>
+    {
+        section
+            .points
+            .iter()
+            .map(|p| (self.tick_position(section.tick + p.tick), p.value))
+            .collect()
+    }
+
+    /// Returns the positions of the lines at the start of every measure up to
+    /// the given tick
+    pub fn measure_line_positions(
+        &self,
+        until: Tick,
+    ) -> Vec<f32>
+    {
+        (0 ..)
+            .map(|measure| self.tempo_map.measure_to_tick(measure))
+            .take_while(|tick| *tick <= until)
+            .map(|tick| self.tick_position(tick))
+            .collect()
+    }
+
+    fn tick_position(
+        &self,
+        tick: Tick,
+    ) -> f32
+    {
+        self.tick_to_position(tick as f64) as f32
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 120 BPM going to 240 at the second measure, on a timer of a thousand
+    // frames a second
+    fn tempo_map(stops: &[(Tick, Tick)]) -> TempoMap {
+        TempoMap::new(&[(0, 120.), (960, 240.)], &[], stops, 0, 1000)
+    }
+
+    fn point(
+        tick: Tick,
+        v: f64,
+        vf: f64,
+    ) -> GraphPoint
+    {
+        GraphPoint {
+            tick,
+            value: GraphValue {
+                v,
+                vf,
+            },
+        }
+    }
+
+    fn position_at(
+        map: &ScrollMap,
+        ms: i64,
+    ) -> f32
+    {
+        map.current_position(SongTime(ms))
+    }
+
+    #[test]
+    fn scrolls_faster_on_higher_bpms() {
+        let map = ScrollMap::new(tempo_map(&[]), &[]);
+
+        assert_eq!(position_at(&map, 0), 0.);
+        assert_eq!(position_at(&map, 1000), 2.);
+        assert_eq!(position_at(&map, 2000), 4.);
+
+        // four beats a second from then on
+        assert_eq!(position_at(&map, 2500), 6.);
+        assert_eq!(position_at(&map, 3000), 8.);
+    }
+
+    #[test]
+    fn freezes_during_stops() {
+        // two beats at 120 BPM, from the second second to the third
+        let tempo_map =
+            TempoMap::new(&[(0, 120.)], &[], &[(960, 480)], 0, 1000);
+        let map = ScrollMap::new(tempo_map, &[]);
+
+        for &ms in &[2000, 2250, 2500, 2999, 3000] {
+            assert_eq!(position_at(&map, ms), 4., "at {}", ms);
+        }
+
+        assert_eq!(position_at(&map, 3500), 5.);
+
+        // and never goes back
+        let mut last = position_at(&map, 0);
+        for ms in 1 .. 5000 {
+            let position = position_at(&map, ms);
+            assert!(last <= position, "goes back at {}", ms);
+            last = position;
+        }
+    }
+
+    #[test]
+    fn follows_the_scroll_speed() {
+        // the speed goes from 1 up to 2 over two beats, then jumps to a half
+        let map = ScrollMap::new(tempo_map(&[]), &[
+            point(0, 1., 1.),
+            point(480, 2., 0.5),
+        ]);
+
+        assert_eq!(map.tick_to_position(240.), 1.25);
+        assert_eq!(map.tick_to_position(480.), 3.);
+        assert_eq!(map.tick_to_position(960.), 4.);
+
+        // the speed before the first point is the speed at it
+        let map = ScrollMap::new(tempo_map(&[]), &[point(480, 2., 2.)]);
+        assert_eq!(map.tick_to_position(240.), 2.);
+        assert_eq!(map.tick_to_position(960.), 8.);
+    }
+
+    #[test]
+    fn keeps_the_notes_with_the_song() {
+        // with a stop of a beat in the first measure
+        let tempo_map = tempo_map(&[(480, 240)]);
+        let map = ScrollMap::new(tempo_map.clone(), &[point(0, 1., 1.5)]);
+
+        let chip = Interval {
+            tick:   1920,
+            length: 0,
+        };
+        let chip_time = tempo_map.tick_to_song_time(chip.tick);
+
+        assert_eq!(map.chip_positions(&[chip]), vec![
+            map.current_position(chip_time)
+        ]);
+
+        // the lines are at every fourth beat, scrolled at one and a half
+        assert_eq!(map.measure_line_positions(1920), vec![0., 6., 12.]);
+    }
+}
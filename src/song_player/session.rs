@@ -0,0 +1,387 @@
+use crate::{
+    chart::Chart,
+    environment::key_bindings::BindRoles,
+    judgment::{
+        JudgedObject,
+        Judgment,
+        JudgmentEngine,
+        JudgmentEvent,
+        JudgmentWindows,
+    },
+    score::{
+        gauge::{
+            Gauge,
+            GaugeOptions,
+        },
+        lamp::{
+            Chain,
+            PlayResult,
+        },
+        Score,
+    },
+    song_player::{
+        governor::{
+            Spin,
+            SpinBuilder,
+        },
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The settings a song is played with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaySettings {
+    pub hi_speed: f32,
+
+    // how much later the notes are drawn and the inputs are taken
+    pub visual_offset_ms: i32,
+    pub input_offset_ms:  i32,
+
+    pub gauge:   GaugeOptions,
+    pub windows: JudgmentWindows,
+
+    // how fast the song is played, from 0.5 to 1. the windows and the offsets
+    // are kept in real time, so they're narrower in song time as the song
+    // slows down.
+    pub rate: f32,
+}
+
+/// An input that is judged, along with the ones recorded in the replays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayInput {
+    Press(BindRoles),
+    Release(BindRoles),
+
+    // the side and how much its knob has been turned at once
+    Knob(usize, f32),
+}
+
+/// The gameplay side of a song being played.
+///
+/// Unlike the LaneGovernor, this does not need a window and is driven only by
+/// the inputs and the song time given to it.
+pub struct SongSession {
+    chart:     Chart,
+    tempo_map: TempoMap,
+    settings:  PlaySettings,
+    judgment:  JudgmentEngine,
+
+    // every input so far, along with the time they're judged at
+    inputs: Vec<(SongTime, PlayInput)>,
+
+    // every judgment so far, in the order they were made
+    events: Vec<JudgmentEvent>,
+
+    score: Score,
+    gauge: Gauge,
+    chain: Chain,
+
+    // only made once everything has been judged, or once the gauge has failed
+    result: Option<PlayResult>,
+
+    // the spins of the chart, by the time of the slams that start them
+    spins:        Vec<(SongTime, SpinBuilder)>,
+    current_spin: Option<Spin>,
+}
+
+impl Default for PlaySettings {
+    fn default() -> PlaySettings {
+        PlaySettings {
+            hi_speed:         1.,
+            visual_offset_ms: 0,
+            input_offset_ms:  0,
+            gauge:            GaugeOptions::default(),
+            windows:          JudgmentWindows::default(),
+            rate:             1.,
+        }
+    }
+}
+
+impl PlaySettings {
+    pub fn input_offset(
+        &self,
+        freq: u32,
+    ) -> SongTime
+    {
+        let seconds = self.input_offset_ms as f64 / 1000. * self.rate as f64;
+        SongTime::from_seconds(seconds, freq)
+    }
+
+    pub fn visual_offset(
+        &self,
+        freq: u32,
+    ) -> SongTime
+    {
+        let seconds = self.visual_offset_ms as f64 / 1000. * self.rate as f64;
+        SongTime::from_seconds(seconds, freq)
+    }
+
+    /// Returns the windows as they are in song time, at the rate of the song
+    pub fn song_windows(&self) -> JudgmentWindows {
+        self.windows.scaled(self.rate as f64)
+    }
+}
+
+impl SongSession {
+    pub fn new(
+        chart: Chart,
+        tempo_map: TempoMap,
+        settings: PlaySettings,
+    ) -> SongSession
+    {
+        let judgment =
+            JudgmentEngine::new(&chart, &tempo_map, &settings.song_windows());
+        let score = Score::new(judgment.note_counts());
+        let gauge = Gauge::new(settings.gauge, judgment.note_counts());
+
+        let mut spins = chart
+            .spins
+            .iter()
+            .map(|event| {
+                (
+                    tempo_map.tick_to_song_time(event.tick),
+                    SpinBuilder::from_event(event, &tempo_map),
+                )
+            })
+            .collect::<Vec<_>>();
+        spins.sort_by_key(|(time, _)| *time);
+
+        SongSession {
+            chart,
+            tempo_map,
+            settings,
+            judgment,
+            inputs: vec![],
+            events: vec![],
+            score,
+            gauge,
+            chain: Chain::default(),
+            result: None,
+            spins,
+            current_spin: None,
+        }
+    }
+
+    pub fn chart(&self) -> &Chart {
+        &self.chart
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    pub fn settings(&self) -> &PlaySettings {
+        &self.settings
+    }
+
+    pub fn judgment(&self) -> &JudgmentEngine {
+        &self.judgment
+    }
+
+    pub fn inputs(&self) -> &[(SongTime, PlayInput)] {
+        &self.inputs
+    }
+
+    pub fn events(&self) -> &[JudgmentEvent] {
+        &self.events
+    }
+
+    /// Returns the score so far
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
+
+    pub fn gauge(&self) -> &Gauge {
+        &self.gauge
+    }
+
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    /// Returns the result of the play, once it's over
+    pub fn result(&self) -> Option<&PlayResult> {
+        self.result.as_ref()
+    }
+
+    /// Returns true once there's nothing left to play, either because
+    /// everything has been judged or because the gauge has failed
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Returns the spin started by the latest slam that has been hit
+    pub fn current_spin(&self) -> Option<&Spin> {
+        self.current_spin.as_ref()
+    }
+
+    /// Judges an input given at the given time
+    pub fn input(
+        &mut self,
+        input: PlayInput,
+        time: SongTime,
+    )
+    {
+        if self.is_over() {
+            return;
+        }
+
+        // everything up to the input has to be judged first
+        self.update(time);
+        self.inputs.push((time, input));
+
+        let events = match input {
+            PlayInput::Press(role) => self.judgment.press(role, time),
+            PlayInput::Release(role) => self.judgment.release(role, time),
+            PlayInput::Knob(side, delta) => {
+                self.judgment.turn_knob(side, delta as f64, time)
+            },
+        };
+
+        self.push_events(events);
+    }
+
+    /// Returns the time by which everything in the chart has surely been
+    /// judged
+    pub fn end_time(&self) -> SongTime {
+        let last = self.tempo_map.tick_to_song_time(self.chart.last_tick());
+        last + SongTime::from_seconds(1., self.tempo_map.freq())
+    }
+
+    /// Moves the session forward to the given time
+    pub fn update(
+        &mut self,
+        time: SongTime,
+    )
+    {
+        let events = self.judgment.advance(time);
+        self.push_events(events);
+    }
+
+    fn push_events(
+        &mut self,
+        mut events: Vec<JudgmentEvent>,
+    )
+    {
+        // the song has ended early on a failed gauge
+        if self.is_over() {
+            return;
+        }
+
+        // the events after the gauge has failed don't count
+        let mut kept = events.len();
+
+        for (idx, event) in events.iter().enumerate() {
+            self.score.record(event);
+            self.gauge.record(event);
+            self.chain.record(event);
+
+            if self.gauge.has_failed() {
+                kept = idx + 1;
+                break;
+            }
+
+            let is_hit_slam = match event.object {
+                JudgedObject::Slam(_) => event.judgment != Judgment::Error,
+                _ => false,
+            };
+
+            if !is_hit_slam {
+                continue;
+            }
+
+            let idx = self.spins.binary_search_by_key(&event.time, |s| s.0);
+            if let Ok(idx) = idx {
+                self.current_spin =
+                    Some(self.spins[idx].1.clone().build(event.time));
+            }
+        }
+
+        events.truncate(kept);
+        self.events.extend(events);
+
+        if self.judgment.is_finished() || self.gauge.has_failed() {
+            self.result =
+                Some(PlayResult::new(&self.score, &self.gauge, &self.chain));
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chart::{
+            GraphPoint,
+            GraphValue,
+            LaserSection,
+            SpinDirection,
+            SpinEvent,
+            SpinKind,
+        },
+        song_player::governor::SpinType,
+    };
+    use cgmath::Rad;
+
+    // a slam to the right at one second starting a spin of half a second, on a
+    // timer of a thousand frames a second
+    fn session() -> SongSession {
+        let mut chart = Chart::default();
+        chart.bpm_changes = vec![(0, 120.)];
+        chart.lasers[0] = vec![LaserSection {
+            tick:   480,
+            points: vec![GraphPoint {
+                tick:  0,
+                value: GraphValue {
+                    v:  0.,
+                    vf: 1.,
+                },
+            }],
+            wide:   false,
+        }];
+        chart.spins = vec![SpinEvent {
+            tick:      480,
+            kind:      SpinKind::Full,
+            direction: SpinDirection::Right,
+            length:    240,
+        }];
+
+        let tempo_map = TempoMap::from_chart(&chart, 1000);
+        SongSession::new(chart, tempo_map, PlaySettings::default())
+    }
+
+    #[test]
+    fn spins_on_the_slams_that_are_hit() {
+        // the slam is hit a little late, but the spin starts on time
+        let mut session = session();
+        session.input(PlayInput::Knob(0, 0.5), SongTime(1030));
+        session.update(SongTime(2000));
+
+        let spin = session.current_spin().unwrap();
+        let halfway = SpinType::Spin.clamped_rotate(0.5);
+
+        assert_eq!(spin.clamped_rotate(&SongTime(1000)), Rad(0.));
+        assert_eq!(spin.clamped_rotate(&SongTime(1250)), -halfway);
+        assert_eq!(spin.clamped_rotate(&SongTime(1500)), Rad(0.));
+    }
+
+    #[test]
+    fn doesnt_spin_on_the_slams_that_are_missed() {
+        let mut session = session();
+        session.update(SongTime(2000));
+
+        let slam = session
+            .events()
+            .iter()
+            .find(|event| event.object == JudgedObject::Slam(0))
+            .unwrap();
+
+        assert_eq!(slam.judgment, Judgment::Error);
+        assert!(session.current_spin().is_none());
+    }
+}
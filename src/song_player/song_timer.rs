@@ -11,6 +11,24 @@ pub type SongTicker = i64;
 #[derive(Hash, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SongTime(pub i64);
 
+impl SongTime {
+    pub fn from_seconds(
+        seconds: f64,
+        freq: u32,
+    ) -> SongTime
+    {
+        SongTime((seconds * freq as f64).round() as i64)
+    }
+
+    pub fn as_seconds(
+        &self,
+        freq: u32,
+    ) -> f64
+    {
+        self.0 as f64 / freq as f64
+    }
+}
+
 impl Add for SongTime {
     type Output = Self;
 
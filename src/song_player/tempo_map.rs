@@ -0,0 +1,560 @@
+use crate::{
+    chart::{
+        Chart,
+        Tick,
+        TimeSignature,
+        TICKS_PER_BEAT,
+        TICKS_PER_WHOLE,
+    },
+    song_player::song_timer::SongTime,
+};
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Converts between the musical positions of a chart and the time of the song.
+///
+/// The time is continuous and linear within each segment. A stop is a segment
+/// where the time moves on while the ticks do not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    // the frequency of the song timer the map converts into
+    freq: u32,
+
+    tempo_segments: Vec<TempoSegment>,
+    measures:       Vec<MeasureSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoSegment {
+    tick:    f64,
+    seconds: f64,
+
+    // zero while stopped
+    ticks_per_second: f64,
+
+    // the BPM is kept even while stopped
+    bpm: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MeasureSegment {
+    measure:   u32,
+    tick:      Tick,
+    signature: TimeSignature,
+}
+
+/// A position in the chart in terms of measures and beats. All of them start
+/// from zero.
+///
+/// The length of the beat follows the denominator of the time signature of the
+/// measure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MusicalPosition {
+    pub measure: u32,
+    pub beat:    u32,
+    pub tick:    Tick,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl TempoMap {
+    /// Creates the map from the timing of a chart
+    pub fn from_chart(
+        chart: &Chart,
+        freq: u32,
+    ) -> TempoMap
+    {
+        TempoMap::new(
+            &chart.bpm_changes,
+            &chart.time_signatures,
+            &chart.stops,
+            chart.meta.offset_ms,
+            freq,
+        )
+    }
+
+    /// Creates the map from sorted lists of BPM changes, time signature changes
+    /// (by measure) and stops (with their lengths).
+    ///
+    /// The offset is the time in the song where the first measure starts.
+    pub fn new(
+        bpm_changes: &[(Tick, f64)],
+        time_signatures: &[(u32, TimeSignature)],
+        stops: &[(Tick, Tick)],
+        offset_ms: i64,
+        freq: u32,
+    ) -> TempoMap
+    {
+        TempoMap {
+            freq,
+            tempo_segments: build_tempo_segments(bpm_changes, stops, offset_ms),
+            measures: build_measure_segments(time_signatures),
+        }
+    }
+
+    pub fn freq(&self) -> u32 {
+        self.freq
+    }
+
+    ////////////////////////////// ticks and time //////////////////////////////
+
+    /// Returns the time, in seconds since the start of the song, of the given
+    /// tick. A tick with a stop on it happens at the start of the stop.
+    pub fn tick_to_seconds(
+        &self,
+        tick: f64,
+    ) -> f64
+    {
+        let idx = self.tempo_segments.partition_point(|s| s.tick < tick);
+        let segment = &self.tempo_segments[idx.max(1) - 1];
+
+        segment.seconds + (tick - segment.tick) / segment.ticks_per_second
+    }
+
+    /// Returns the tick at the given time. The tick stays constant during a
+    /// stop.
+    pub fn seconds_to_tick(
+        &self,
+        seconds: f64,
+    ) -> f64
+    {
+        // before the first segment, this extrapolates into negative ticks for
+        // the lead-in
+        let idx = self.tempo_segments.partition_point(|s| s.seconds <= seconds);
+        let segment = &self.tempo_segments[idx.max(1) - 1];
+
+        segment.tick + (seconds - segment.seconds) * segment.ticks_per_second
+    }
+
+    pub fn tick_to_ms(
+        &self,
+        tick: Tick,
+    ) -> f64
+    {
+        self.tick_to_seconds(tick as f64) * 1000.
+    }
+
+    pub fn ms_to_tick(
+        &self,
+        ms: f64,
+    ) -> f64
+    {
+        self.seconds_to_tick(ms / 1000.)
+    }
+
+    pub fn tick_to_song_time(
+        &self,
+        tick: Tick,
+    ) -> SongTime
+    {
+        SongTime::from_seconds(self.tick_to_seconds(tick as f64), self.freq)
+    }
+
+    pub fn song_time_to_tick(
+        &self,
+        time: SongTime,
+    ) -> f64
+    {
+        self.seconds_to_tick(time.as_seconds(self.freq))
+    }
+
+    /// Returns the BPM in effect at the given tick
+    pub fn bpm_at(
+        &self,
+        tick: f64,
+    ) -> f64
+    {
+        let idx = self.tempo_segments.partition_point(|s| s.tick <= tick);
+        self.tempo_segments[idx.max(1) - 1].bpm
+    }
+
+    ////////////////////////// beats and measures //////////////////////////////
+
+    pub fn beats_to_tick(beats: f64) -> f64 {
+        beats * TICKS_PER_BEAT as f64
+    }
+
+    pub fn tick_to_beats(tick: f64) -> f64 {
+        tick / TICKS_PER_BEAT as f64
+    }
+
+    /// Returns the tick where the given measure begins
+    pub fn measure_to_tick(
+        &self,
+        measure: u32,
+    ) -> Tick
+    {
+        let segment = self.measure_segment_by(|s| s.measure <= measure);
+        segment.tick + (measure - segment.measure) * segment.measure_length()
+    }
+
+    pub fn position_to_tick(
+        &self,
+        position: &MusicalPosition,
+    ) -> Tick
+    {
+        let measure = position.measure;
+        let segment = self.measure_segment_by(|s| s.measure <= measure);
+
+        self.measure_to_tick(position.measure) +
+            position.beat * segment.beat_length() +
+            position.tick
+    }
+
+    pub fn tick_to_position(
+        &self,
+        tick: Tick,
+    ) -> MusicalPosition
+    {
+        let segment = self.measure_segment_by(|s| s.tick <= tick);
+
+        let relative = tick - segment.tick;
+        let measure_length = segment.measure_length();
+        let in_measure = relative % measure_length;
+
+        MusicalPosition {
+            measure: segment.measure + relative / measure_length,
+            beat:    in_measure / segment.beat_length(),
+            tick:    in_measure % segment.beat_length(),
+        }
+    }
+
+    pub fn position_to_song_time(
+        &self,
+        position: &MusicalPosition,
+    ) -> SongTime
+    {
+        self.tick_to_song_time(self.position_to_tick(position))
+    }
+
+    fn measure_segment_by<F>(
+        &self,
+        pred: F,
+    ) -> &MeasureSegment
+    where
+        F: FnMut(&MeasureSegment) -> bool,
+    {
+        let idx = self.measures.partition_point(pred);
+        &self.measures[idx.max(1) - 1]
+    }
+}
+
+impl MeasureSegment {
+    fn measure_length(&self) -> Tick {
+        self.signature.measure_length()
+    }
+
+    fn beat_length(&self) -> Tick {
+        TICKS_PER_WHOLE / self.signature.denominator
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+fn build_tempo_segments(
+    bpm_changes: &[(Tick, f64)],
+    stops: &[(Tick, Tick)],
+    offset_ms: i64,
+) -> Vec<TempoSegment>
+{
+    enum Event {
+        Bpm(f64),
+        Stop(Tick),
+    }
+
+    // the BPM changes go first so that a stop on the same tick uses the new
+    // BPM
+    let mut events = bpm_changes
+        .iter()
+        .map(|&(tick, bpm)| (tick, Event::Bpm(bpm)))
+        .chain(stops.iter().map(|&(tick, len)| (tick, Event::Stop(len))))
+        .collect::<Vec<_>>();
+    events.sort_by_key(|(tick, event)| {
+        (*tick, if let Event::Bpm(_) = event { 0 } else { 1 })
+    });
+
+    let first_bpm = bpm_changes.first().map(|b| b.1).unwrap_or(120.);
+    let mut current = TempoSegment {
+        tick:             0.,
+        seconds:          offset_ms as f64 / 1000.,
+        ticks_per_second: bpm_to_tps(first_bpm),
+        bpm:              first_bpm,
+    };
+    let mut segments = vec![current];
+
+    for (tick, event) in events.into_iter() {
+        let tick = tick as f64;
+        let seconds = current.seconds +
+            (tick - current.tick) / bpm_to_tps(current.bpm);
+
+        match event {
+            Event::Bpm(bpm) => {
+                current = TempoSegment {
+                    tick,
+                    seconds,
+                    ticks_per_second: bpm_to_tps(bpm),
+                    bpm,
+                };
+                segments.push(current);
+            },
+
+            Event::Stop(length) => {
+                let bpm = current.bpm;
+                let stopped = TempoSegment {
+                    tick,
+                    seconds,
+                    ticks_per_second: 0.,
+                    bpm,
+                };
+
+                current = TempoSegment {
+                    tick,
+                    seconds: seconds + length as f64 / bpm_to_tps(bpm),
+                    ticks_per_second: bpm_to_tps(bpm),
+                    bpm,
+                };
+
+                segments.push(stopped);
+                segments.push(current);
+            },
+        }
+    }
+
+    segments
+}
+
+fn build_measure_segments(
+    time_signatures: &[(u32, TimeSignature)],
+) -> Vec<MeasureSegment>
+{
+    let mut current = MeasureSegment {
+        measure:   0,
+        tick:      0,
+        signature: TimeSignature::default(),
+    };
+    let mut segments = vec![];
+
+    for &(measure, signature) in time_signatures.iter() {
+        // a signature before the last one is out of order, and one too far in
+        // has no tick. neither can be placed, so they're left out.
+        let tick = measure
+            .checked_sub(current.measure)
+            .and_then(|measures| measures.checked_mul(current.measure_length()))
+            .and_then(|ticks| ticks.checked_add(current.tick));
+        let tick = match tick {
+            Some(tick) => tick,
+            None => continue,
+        };
+
+        current = MeasureSegment {
+            measure,
+            tick,
+            signature,
+        };
+        segments.push(current);
+    }
+
+    // there's always the implicit 4/4 at the start
+    if segments.first().map(|s| s.measure) != Some(0) {
+        segments.insert(0, MeasureSegment {
+            measure:   0,
+            tick:      0,
+            signature: TimeSignature::default(),
+        });
+    }
+
+    segments
+}
+
+fn bpm_to_tps(bpm: f64) -> f64 {
+    bpm * TICKS_PER_BEAT as f64 / 60.
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a thousand frames a second, so the song times are in milliseconds
+    const FREQ: u32 = 1000;
+
+    fn signature(
+        numerator: u32,
+        denominator: u32,
+    ) -> TimeSignature
+    {
+        TimeSignature {
+            numerator,
+            denominator,
+        }
+    }
+
+    fn assert_close(
+        actual: f64,
+        expected: f64,
+    )
+    {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    // 120 BPM, going to 240 at the third measure, with a stop of two beats at
+    // the second measure
+    fn tempo_map() -> TempoMap {
+        TempoMap::new(
+            &[(0, 120.), (1920, 240.)],
+            &[(0, signature(4, 4))],
+            &[(960, 480)],
+            0,
+            FREQ,
+        )
+    }
+
+    #[test]
+    fn converts_at_a_constant_bpm() {
+        let map = TempoMap::new(&[(0, 120.)], &[], &[], 0, FREQ);
+
+        assert_close(map.tick_to_seconds(0.), 0.);
+        assert_close(map.tick_to_seconds(240.), 0.5);
+        assert_close(map.seconds_to_tick(2.), 960.);
+        assert_eq!(map.tick_to_song_time(960), SongTime(2000));
+        assert_close(map.song_time_to_tick(SongTime(500)), 240.);
+    }
+
+    #[test]
+    fn starts_at_the_offset() {
+        let map = TempoMap::new(&[(0, 120.)], &[], &[], 500, FREQ);
+
+        assert_eq!(map.tick_to_song_time(0), SongTime(500));
+        assert_eq!(map.tick_to_song_time(240), SongTime(1000));
+
+        // the lead-in is before the first tick
+        assert_close(map.seconds_to_tick(0.), -240.);
+    }
+
+    #[test]
+    fn changes_the_bpm() {
+        let map = tempo_map();
+
+        // two seconds up to the stop, one second stopped, two more seconds up
+        // to the change, then a second a measure
+        assert_close(map.tick_to_seconds(1920.), 5.);
+        assert_close(map.tick_to_seconds(2880.), 6.);
+        assert_close(map.seconds_to_tick(6.), 2880.);
+
+        assert_eq!(map.bpm_at(0.), 120.);
+        assert_eq!(map.bpm_at(1919.), 120.);
+        assert_eq!(map.bpm_at(1920.), 240.);
+    }
+
+    #[test]
+    fn stops_the_ticks() {
+        let map = tempo_map();
+
+        // the stopped tick happens at the start of the stop
+        assert_close(map.tick_to_seconds(960.), 2.);
+        assert_close(map.tick_to_seconds(961.), 3. + 1. / 480.);
+
+        for &seconds in &[2., 2.25, 2.5, 2.999] {
+            assert_close(map.seconds_to_tick(seconds), 960.);
+        }
+
+        assert_close(map.seconds_to_tick(3.5), 1200.);
+
+        // the BPM is kept while stopped
+        assert_eq!(map.bpm_at(960.), 120.);
+    }
+
+    #[test]
+    fn stops_on_a_bpm_change_use_the_new_bpm() {
+        let map = TempoMap::new(
+            &[(0, 120.), (960, 240.)],
+            &[],
+            &[(960, 480)],
+            0,
+            FREQ,
+        );
+
+        // the two beats of the stop are at 240 BPM
+        assert_close(map.tick_to_seconds(961.), 2.5 + 1. / 960.);
+    }
+
+    #[test]
+    fn round_trips_ticks_and_time() {
+        let map = tempo_map();
+
+        for tick in (0 .. 4800).step_by(7) {
+            let tick = tick as f64;
+            assert_close(map.seconds_to_tick(map.tick_to_seconds(tick)), tick);
+        }
+
+        // the song times are rounded to the frame, which is at most a tick
+        // at these BPMs
+        for tick in (0 .. 4800).step_by(7) {
+            let time = map.tick_to_song_time(tick);
+            let back = map.song_time_to_tick(time);
+
+            assert!((back - tick as f64).abs() <= 1., "{} from {}", back, tick);
+        }
+    }
+
+    #[test]
+    fn changes_the_time_signature() {
+        let map = TempoMap::new(
+            &[(0, 120.)],
+            &[(0, signature(4, 4)), (2, signature(3, 4)), (4, signature(6, 8))],
+            &[],
+            0,
+            FREQ,
+        );
+
+        assert_eq!(map.measure_to_tick(1), 960);
+        assert_eq!(map.measure_to_tick(2), 1920);
+        assert_eq!(map.measure_to_tick(3), 2640);
+        assert_eq!(map.measure_to_tick(4), 3360);
+        assert_eq!(map.measure_to_tick(5), 4080);
+
+        let position = MusicalPosition {
+            measure: 4,
+            beat:    5,
+            tick:    10,
+        };
+        let tick = 3360 + 5 * 120 + 10;
+
+        assert_eq!(map.position_to_tick(&position), tick);
+        assert_eq!(map.tick_to_position(tick), position);
+
+        for tick in (0 .. 6000).step_by(13) {
+            let position = map.tick_to_position(tick);
+            assert_eq!(map.position_to_tick(&position), tick);
+        }
+    }
+
+    #[test]
+    fn starts_in_four_four() {
+        let map =
+            TempoMap::new(&[(0, 120.)], &[(2, signature(3, 4))], &[], 0, FREQ);
+
+        assert_eq!(map.measure_to_tick(2), 1920);
+        assert_eq!(map.measure_to_tick(3), 2640);
+    }
+
+    #[test]
+    fn leaves_out_signatures_out_of_order() {
+        let map = TempoMap::new(
+            &[(0, 120.)],
+            &[(0, signature(4, 4)), (2, signature(3, 4)), (1, signature(6, 8))],
+            &[],
+            0,
+            FREQ,
+        );
+
+        assert_eq!(map.measure_to_tick(2), 1920);
+        assert_eq!(map.measure_to_tick(3), 2640);
+    }
+}
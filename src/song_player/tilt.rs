@@ -0,0 +1,391 @@
+use crate::{
+    chart::{
+        Chart,
+        LaserSection,
+        TiltMode,
+    },
+    song_player::{
+        keyframe::{
+            Keyframe,
+            KeyframeTrack,
+            TransformationKFCurve,
+        },
+        song_timer::SongTime,
+        tempo_map::TempoMap,
+    },
+};
+use cgmath::Rad;
+
+////////////////////////////////////////////////////////////////////////////////
+
+// how far the lanes tilt at the normal tilt, with the lasers pulled all the way
+// from where they rest
+const LASER_TILT: f32 = 0.17453292; // Deg(10)
+
+// how long the tilt takes to get most of the way to where the lasers are, and
+// how long it takes to switch between the lasers and the manual tilt
+const LASER_EASE_MS: f64 = 120.;
+const MANUAL_FADE_MS: f64 = 200.;
+
+// the tilt is put where it should be at once after a jump in time any longer
+// than this, or any jump back
+const MAX_STEP_MS: f64 = 500.;
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// Tilts the lanes towards the lasers, or along the manual tilt of the chart,
+/// as the tilt modes of the chart say.
+///
+/// The tilt is moved along with the song, easing towards where the lasers are
+/// and fading between the lasers and the manual tilt as the mode switches.
+pub struct LaneTilt {
+    freq:   u32,
+    modes:  Vec<(SongTime, TiltMode)>,
+    manual: KeyframeTrack<TransformationKFCurve, Rad<f32>>,
+    lasers: [LaserPositions; 2],
+
+    // the time the tilt was last moved to
+    time: Option<SongTime>,
+
+    // the tilt by the lasers as it eases towards them, and where it's easing
+    // to. the target is held while there are no lasers in the keeping modes.
+    laser_tilt: f32,
+    target:     f32,
+
+    // how much of the manual tilt is in the tilt, from 0 to 1
+    manual_mix: f32,
+}
+
+// where the lasers of a side are, without the curves of the chart
+struct LaserPositions {
+    // the start and the end of every section
+    sections:  Vec<(SongTime, SongTime)>,
+    positions: KeyframeTrack<TransformationKFCurve>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+impl LaneTilt {
+    /// Makes the tilt of the chart. The manual tilt is given already in the
+    /// angles of the lanes.
+    pub fn new(
+        chart: &Chart,
+        tempo_map: &TempoMap,
+        manual: KeyframeTrack<TransformationKFCurve, Rad<f32>>,
+    ) -> LaneTilt
+    {
+        let modes = chart
+            .tilt_modes
+            .iter()
+            .map(|(tick, mode)| (tempo_map.tick_to_song_time(*tick), *mode))
+            .collect();
+
+        LaneTilt {
+            freq: tempo_map.freq(),
+            modes,
+            manual,
+            lasers: [
+                LaserPositions::new(&chart.lasers[0], tempo_map),
+                LaserPositions::new(&chart.lasers[1], tempo_map),
+            ],
+            time: None,
+            laser_tilt: 0.,
+            target: 0.,
+            manual_mix: 0.,
+        }
+    }
+
+    /// Makes a tilt that stays level, for the lanes without a chart
+    pub fn level(freq: u32) -> LaneTilt {
+        LaneTilt {
+            freq,
+            modes: vec![],
+            manual: KeyframeTrack::new(vec![], Rad(0.)),
+            lasers: [LaserPositions::empty(), LaserPositions::empty()],
+            time: None,
+            laser_tilt: 0.,
+            target: 0.,
+            manual_mix: 0.,
+        }
+    }
+
+    /// Moves the tilt along to the given time
+    pub fn advance(
+        &mut self,
+        time: SongTime,
+    )
+    {
+        let mode = self.mode_at(time);
+
+        // the lasers pull the lanes their way from where they rest, the left
+        // one from the left and the right one from the right
+        let left = self.lasers[0].position_at(&time);
+        let right = self.lasers[1].position_at(&time).map(|p| p - 1.);
+
+        if left.is_some() || right.is_some() || !mode.keeps() {
+            let pull = left.unwrap_or(0.) + right.unwrap_or(0.);
+
+            // the positive angles go counterclockwise, so the lanes tilt
+            // clockwise as the lasers go right
+            self.target = -pull * LASER_TILT * mode.scale() as f32;
+        }
+
+        let manual_target = if mode == TiltMode::Manual { 1. } else { 0. };
+
+        let step_ms = self
+            .time
+            .map(|last| (time - last).0 as f64 * 1000. / self.freq as f64)
+            .filter(|ms| 0. <= *ms && *ms <= MAX_STEP_MS);
+
+        match step_ms {
+            Some(ms) => {
+                let ease = 1. - (-ms / LASER_EASE_MS).exp() as f32;
+                self.laser_tilt += (self.target - self.laser_tilt) * ease;
+
+                let fade = (ms / MANUAL_FADE_MS) as f32;
+                self.manual_mix = if self.manual_mix < manual_target {
+                    (self.manual_mix + fade).min(manual_target)
+                }
+                else {
+                    (self.manual_mix - fade).max(manual_target)
+                };
+            },
+
+            None => {
+                self.laser_tilt = self.target;
+                self.manual_mix = manual_target;
+            },
+        }
+
+        self.time = Some(time);
+    }
+
+    /// Returns the tilt of the lanes at the given time, as of the time it was
+    /// last moved to
+    pub fn roll(
+        &self,
+        time: &SongTime,
+    ) -> Rad<f32>
+    {
+        let mix = self.manual_mix;
+        if mix <= 0. {
+            return Rad(self.laser_tilt);
+        }
+
+        let manual = self.manual.value_at(time);
+        Rad(self.laser_tilt * (1. - mix) + manual.0 * mix)
+    }
+
+    fn mode_at(
+        &self,
+        time: SongTime,
+    ) -> TiltMode
+    {
+        let idx = self.modes.partition_point(|(at, _)| *at <= time);
+
+        idx.checked_sub(1)
+            .map_or_else(TiltMode::default, |idx| self.modes[idx].1)
+    }
+}
+
+impl LaserPositions {
+    fn new(
+        sections: &[LaserSection],
+        tempo_map: &TempoMap,
+    ) -> LaserPositions
+    {
+        let mut bounds = Vec::with_capacity(sections.len());
+        let mut keyframes = vec![];
+
+        for section in sections.iter() {
+            let start = section.tick;
+            let end = section.tick + section.last_relative_tick();
+
+            bounds.push((
+                tempo_map.tick_to_song_time(start),
+                tempo_map.tick_to_song_time(end),
+            ));
+
+            // a slam is just two keyframes at the same time
+            for point in section.points.iter() {
+                let time = tempo_map.tick_to_song_time(start + point.tick);
+
+                let mut values = vec![point.value.v];
+                if point.value.is_slam() {
+                    values.push(point.value.vf);
+                }
+
+                for value in values.into_iter() {
+                    keyframes.push(Keyframe::new(
+                        value as f32,
+                        time,
+                        TransformationKFCurve::HalfSigmoid,
+                        0.,
+                    ));
+                }
+            }
+        }
+
+        LaserPositions {
+            sections:  bounds,
+            positions: KeyframeTrack::new(keyframes, 0.),
+        }
+    }
+
+    fn empty() -> LaserPositions {
+        LaserPositions {
+            sections:  vec![],
+            positions: KeyframeTrack::new(vec![], 0.),
+        }
+    }
+
+    /// Returns where the laser is at the given time, from 0 on the left to 1
+    /// on the right, if there is one
+    fn position_at(
+        &self,
+        time: &SongTime,
+    ) -> Option<f32>
+    {
+        let idx = self.sections.partition_point(|(start, _)| *start <= *time);
+        let (_, end) = self.sections.get(idx.checked_sub(1)?)?;
+
+        if *time <= *end {
+            Some(self.positions.value_at(time))
+        }
+        else {
+            None
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart::{
+        GraphPoint,
+        GraphValue,
+        Tick,
+    };
+
+    // a laser standing still from the tick to the end, on a timer of a
+    // thousand frames a second, where a beat is 500 frames
+    fn laser(
+        start: Tick,
+        end: Tick,
+        position: f64,
+    ) -> LaserSection
+    {
+        let point = |tick| {
+            GraphPoint {
+                tick,
+                value: GraphValue::new(position),
+            }
+        };
+
+        LaserSection {
+            tick:   start,
+            points: vec![point(0), point(end - start)],
+            wide:   false,
+        }
+    }
+
+    fn lane_tilt(
+        modes: Vec<(Tick, TiltMode)>,
+        lasers: [Vec<LaserSection>; 2],
+    ) -> LaneTilt
+    {
+        let mut chart = Chart::default();
+        chart.bpm_changes = vec![(0, 120.)];
+        chart.tilt_modes = modes;
+        chart.lasers = lasers;
+
+        let tempo_map = TempoMap::from_chart(&chart, 1000);
+        let manual = KeyframeTrack::new(vec![], Rad(0.3));
+
+        LaneTilt::new(&chart, &tempo_map, manual)
+    }
+
+    fn roll_at(
+        tilt: &mut LaneTilt,
+        time: i64,
+    ) -> f32
+    {
+        tilt.advance(SongTime(time));
+        tilt.roll(&SongTime(time)).0
+    }
+
+    #[test]
+    fn tilts_away_from_the_lasers() {
+        // the left laser pulled right tilts the lanes clockwise
+        let mut tilt = lane_tilt(vec![], [vec![laser(0, 480, 1.)], vec![]]);
+        assert_eq!(roll_at(&mut tilt, 500), -LASER_TILT);
+
+        // and the right laser pulled left tilts them counterclockwise
+        let mut tilt = lane_tilt(vec![], [vec![], vec![laser(0, 480, 0.)]]);
+        assert_eq!(roll_at(&mut tilt, 500), LASER_TILT);
+
+        // the lasers pulled as far as each other cancel out
+        let mut tilt = lane_tilt(vec![], [
+            vec![laser(0, 480, 0.5)],
+            vec![laser(0, 480, 0.5)],
+        ]);
+        assert_eq!(roll_at(&mut tilt, 500), 0.);
+    }
+
+    #[test]
+    fn keeps_the_tilt_once_the_lasers_end() {
+        let lasers = || [vec![laser(0, 480, 1.)], vec![]];
+
+        let mut kept = lane_tilt(vec![(0, TiltMode::KeepNormal)], lasers());
+        let mut normal = lane_tilt(vec![(0, TiltMode::Normal)], lasers());
+
+        for time in (0 .. 3000).step_by(100) {
+            roll_at(&mut kept, time);
+            roll_at(&mut normal, time);
+        }
+
+        assert_eq!(roll_at(&mut kept, 3000), -LASER_TILT);
+        assert!(roll_at(&mut normal, 3000).abs() < LASER_TILT * 1e-3);
+    }
+
+    #[test]
+    fn fades_into_the_manual_tilt() {
+        // the manual tilt from the first second to the second one
+        let modes = vec![(480, TiltMode::Manual), (960, TiltMode::Normal)];
+        let mut tilt = lane_tilt(modes, [vec![], vec![]]);
+
+        assert_eq!(roll_at(&mut tilt, 900), 0.);
+        assert!((roll_at(&mut tilt, 1000) - 0.15).abs() < 1e-6);
+        assert!((roll_at(&mut tilt, 1100) - 0.3).abs() < 1e-6);
+
+        // and back out of it
+        roll_at(&mut tilt, 1500);
+        assert!((roll_at(&mut tilt, 1900) - 0.3).abs() < 1e-6);
+        assert!((roll_at(&mut tilt, 2000) - 0.15).abs() < 1e-6);
+        assert_eq!(roll_at(&mut tilt, 2100), 0.);
+    }
+
+    #[test]
+    fn snaps_after_jumps() {
+        // a laser coming at a second
+        let lasers = || [vec![laser(480, 1440, 1.)], vec![]];
+
+        // a step as long as MAX_STEP_MS still eases
+        let mut tilt = lane_tilt(vec![], lasers());
+        roll_at(&mut tilt, 900);
+        let eased = roll_at(&mut tilt, 1400);
+        assert!(-LASER_TILT < eased && eased < -LASER_TILT * 0.9);
+
+        // but not one any longer
+        let mut tilt = lane_tilt(vec![], lasers());
+        roll_at(&mut tilt, 900);
+        assert_eq!(roll_at(&mut tilt, 1401), -LASER_TILT);
+
+        // nor a jump back
+        let mut tilt = lane_tilt(vec![], lasers());
+        assert_eq!(roll_at(&mut tilt, 1400), -LASER_TILT);
+        assert_eq!(roll_at(&mut tilt, 900), 0.);
+    }
+}